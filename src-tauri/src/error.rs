@@ -0,0 +1,143 @@
+//! Errors reported to the frontend, with stable codes it can react to.
+//!
+//! Domain code keeps returning `anyhow::Result`, raising coded errors with [`app_error!`]
+//! where the frontend may want to tell them apart. At the IPC boundary anything without
+//! a code is reported as [`ErrorCode::Internal`].
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What went wrong, stable across releases so the frontend can match on it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Not in a room
+    NoActiveChannel,
+    /// Not connected to the lobby
+    NotInLobby,
+    /// A join code which can't be parsed
+    InvalidTicket,
+    /// The game or tournament doesn't allow that right now
+    IllegalMove,
+    NotYourTurn,
+    /// Reserved for the host, moderators or the players of a game, or not possible in the
+    /// app's current state
+    NotPermitted,
+    /// No game, tournament, offer or peer to act on
+    NotFound,
+    /// A value the player entered was rejected
+    InvalidInput,
+    WrongPassphrase,
+    /// The identity is encrypted and the app has not been unlocked
+    Locked,
+    /// The node could not be started or reached the network
+    NetworkUnavailable,
+    /// Data written by a newer version of the app
+    UpdateRequired,
+    /// Stored or imported data which can't be read
+    Corrupt,
+    Internal,
+}
+
+/// An error as sent to the frontend.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AppError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<anyhow::Error> for AppError {
+    /// Recover the code of an error raised with [`app_error!`], even under added context.
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<AppError>() {
+            Some(coded) => Self::new(coded.code, e.to_string()),
+            None => Self::new(ErrorCode::Internal, e.to_string()),
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        match e {
+            tauri::Error::Anyhow(e) => e.into(),
+            e => Self::new(ErrorCode::Internal, e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorCode::Internal, e.to_string())
+    }
+}
+
+/// Result of an IPC command.
+pub type IpcResult<T> = Result<T, AppError>;
+
+/// Create an `anyhow::Error` carrying an [`ErrorCode`], formatted like `anyhow!`.
+macro_rules! app_error {
+    ($code:ident, $($arg:tt)+) => {
+        anyhow::Error::from($crate::error::AppError::new(
+            $crate::error::ErrorCode::$code,
+            format!($($arg)+),
+        ))
+    };
+}
+pub(crate) use app_error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context as _};
+
+    #[test]
+    fn coded_errors_keep_their_code() {
+        let error = AppError::from(app_error!(NotYourTurn, "Wait for {}.", "bob"));
+        assert_eq!(
+            error,
+            AppError::new(ErrorCode::NotYourTurn, "Wait for bob.")
+        );
+    }
+
+    #[test]
+    fn uncoded_errors_are_internal() {
+        let error = AppError::from(anyhow!("disk on fire"));
+        assert_eq!(error, AppError::new(ErrorCode::Internal, "disk on fire"));
+        let error = AppError::from(std::io::Error::other("disk on fire"));
+        assert_eq!(error.code, ErrorCode::Internal);
+    }
+
+    #[test]
+    fn codes_survive_added_context() {
+        let result: anyhow::Result<()> = Err(app_error!(Locked, "Unlock first."));
+        let error = AppError::from(result.context("Could not start").unwrap_err());
+        assert_eq!(error.code, ErrorCode::Locked);
+        assert_eq!(error.message, "Could not start");
+    }
+
+    #[test]
+    fn serialized_as_the_frontend_reads_it() {
+        let error = AppError::new(ErrorCode::WrongPassphrase, "Wrong passphrase.");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "code": "WrongPassphrase", "message": "Wrong passphrase." })
+        );
+    }
+}
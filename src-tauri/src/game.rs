@@ -1,9 +1,11 @@
-use board::Board;
+use crate::error::app_error;
+use board::{Board, Piece};
 use iroh::NodeId;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
 
 pub mod board;
+pub mod legacy;
+pub mod tournament;
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlayerType {
@@ -13,12 +15,58 @@ pub enum PlayerType {
     Ai,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
-pub enum Player {
-    /// Local Node is responsible for this player
-    Local(PlayerType),
-    /// Remote Node is responsible for this player
-    Remote(NodeId),
+/// A participant's place in the game.
+///
+/// Seats are absolute, so every peer reading the shared game agrees on who is who.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct Seat {
+    /// Node responsible for this seat
+    pub node_id: NodeId,
+    pub role: PlayerType,
+    /// Piece played from this seat, `Piece::Empty` for spectators
+    pub piece: Piece,
+}
+
+impl Seat {
+    /// Check whether this seat plays pieces rather than watching.
+    pub fn is_player(&self) -> bool {
+        self.role != PlayerType::Spectator
+    }
+}
+
+/// A seat as seen by one particular node.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SeatView {
+    #[serde(flatten)]
+    pub seat: Seat,
+    /// Whether this seat belongs to the viewing node
+    pub is_me: bool,
+}
+
+/// A game projected for display by one particular node.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameView {
+    pub state: GamePhase,
+    pub seats: Vec<SeatView>,
+    pub history: Vec<Move>,
+    pub version: u64,
+    /// The viewing node's own seat, if it has one
+    pub my_seat: Option<Seat>,
+    /// Whether the viewing node is the one to move next
+    pub my_turn: bool,
+}
+
+/// A single piece placed on the board.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub x: usize,
+    pub y: usize,
+    pub piece: Piece,
+    /// Node which made this move
+    pub by: NodeId,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -28,16 +76,35 @@ pub enum GamePhase {
     /// Game is in progress
     InProgress { turn: usize, board: Board },
     /// Game is over
-    Finished { winner: Player },
+    Finished { board: Board, result: GameResult },
+}
+
+/// How a finished game ended.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum GameResult {
+    /// A player completed a line of their pieces
+    Line { winner: NodeId, piece: Piece },
+    /// A player conceded the game
+    Resignation { resigned: NodeId },
+    /// Both players agreed to a draw
+    DrawAgreement,
+    /// Every square was filled without a line being made
+    BoardFull,
+    /// The game was called off before the first move
+    Aborted,
+    /// The game finished before results were recorded, with the winner if it is known
+    Legacy { winner: Option<NodeId> },
 }
 
 /// Game information
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Game {
     /// Game state
     pub state: GamePhase,
     /// Participating nodes, including spectators
-    pub participants: HashSet<Player>,
+    pub participants: Vec<Seat>,
+    /// Moves played so far, oldest first
+    pub history: Vec<Move>,
     /// Incremental version for optimistic concurrency or simple change detection
     pub version: u64,
 }
@@ -47,14 +114,474 @@ impl Game {
     pub fn new() -> Self {
         Self {
             state: GamePhase::New,
-            participants: HashSet::new(),
+            participants: Vec::new(),
+            history: Vec::new(),
             version: 0,
         }
     }
-    pub fn start_game(&mut self) {
+    /// Create a game between two nodes with their seats already taken, ready to play.
+    pub fn between(cross: NodeId, naught: NodeId) -> anyhow::Result<Self> {
+        let mut game = Self::new();
+        game.take_seat(cross, PlayerType::Human, Piece::Cross)?;
+        game.take_seat(naught, PlayerType::Human, Piece::Naught)?;
+        game.start_game()?;
+        Ok(game)
+    }
+    /// The seat held by the given node, if any.
+    pub fn seat_of(&self, node_id: &NodeId) -> Option<&Seat> {
+        self.participants
+            .iter()
+            .find(|seat| &seat.node_id == node_id)
+    }
+    /// The seat playing the given piece, if it has been taken.
+    pub fn player_for(&self, piece: Piece) -> Option<&Seat> {
+        self.participants
+            .iter()
+            .find(|seat| seat.is_player() && seat.piece == piece)
+    }
+    /// Sit the given node down, replacing any seat it already held.
+    ///
+    /// Players can only change seats before the game starts, spectators can join at any time.
+    /// A piece nobody plays can be taken during a game, as in games carried over from
+    /// before pieces were recorded.
+    pub fn take_seat(
+        &mut self,
+        node_id: NodeId,
+        role: PlayerType,
+        piece: Piece,
+    ) -> anyhow::Result<Seat> {
+        let seat = match role {
+            PlayerType::Spectator => Seat {
+                node_id,
+                role,
+                piece: Piece::Empty,
+            },
+            PlayerType::Human | PlayerType::Ai => {
+                if piece == Piece::Empty {
+                    return Err(app_error!(InvalidInput, "Players must choose a piece."));
+                }
+                if self
+                    .player_for(piece)
+                    .is_some_and(|seat| seat.node_id != node_id)
+                {
+                    return Err(app_error!(IllegalMove, "{piece:?} is already taken."));
+                }
+                Seat {
+                    node_id,
+                    role,
+                    piece,
+                }
+            }
+        };
+        if self.seat_of(&node_id).is_some_and(Seat::is_player)
+            && matches!(self.state, GamePhase::InProgress { .. })
+        {
+            return Err(app_error!(IllegalMove, "Cannot leave a game in progress."));
+        }
+        self.participants.retain(|seat| seat.node_id != node_id);
+        self.participants.push(seat);
+        self.version += 1;
+        Ok(seat)
+    }
+    pub fn start_game(&mut self) -> anyhow::Result<()> {
+        if matches!(self.state, GamePhase::InProgress { .. }) {
+            return Err(app_error!(IllegalMove, "Game is already in progress."));
+        }
+        if self.player_for(Piece::Cross).is_none() || self.player_for(Piece::Naught).is_none() {
+            return Err(app_error!(
+                IllegalMove,
+                "Both pieces need a player before starting."
+            ));
+        }
         self.state = GamePhase::InProgress {
             turn: 0,
             board: Board::new(),
+        };
+        self.history.clear();
+        self.version += 1;
+        Ok(())
+    }
+    /// Number of player seats still open, none once the game is underway.
+    pub fn seats_free(&self) -> usize {
+        match self.state {
+            GamePhase::InProgress { .. } => 0,
+            GamePhase::New | GamePhase::Finished { .. } => [Piece::Cross, Piece::Naught]
+                .into_iter()
+                .filter(|piece| self.player_for(*piece).is_none())
+                .count(),
+        }
+    }
+    /// Check whether the given node holds the seat for a player in this game.
+    fn require_player(&self, node_id: &NodeId) -> anyhow::Result<&Seat> {
+        self.seat_of(node_id)
+            .filter(|seat| seat.is_player())
+            .ok_or_else(|| app_error!(NotPermitted, "You are not playing in this game."))
+    }
+    /// The seat whose turn it is, if the game is in progress.
+    pub fn to_move(&self) -> Option<&Seat> {
+        match &self.state {
+            GamePhase::InProgress { turn, .. } => self.player_for(Piece::for_turn(*turn)),
+            _ => None,
+        }
+    }
+    /// Project the game for display by the given node.
+    pub fn view_for(&self, viewer: NodeId) -> GameView {
+        GameView {
+            state: self.state.clone(),
+            seats: self
+                .participants
+                .iter()
+                .map(|seat| SeatView {
+                    seat: *seat,
+                    is_me: seat.node_id == viewer,
+                })
+                .collect(),
+            history: self.history.clone(),
+            version: self.version,
+            my_seat: self.seat_of(&viewer).copied(),
+            my_turn: self.to_move().is_some_and(|seat| seat.node_id == viewer),
+        }
+    }
+    /// Place the next piece at the given square.
+    pub fn play_move(&mut self, x: usize, y: usize, by: NodeId) -> anyhow::Result<Move> {
+        let seat = *self.require_player(&by)?;
+        if self.to_move().is_some_and(|next| next.node_id != by) {
+            return Err(app_error!(NotYourTurn, "Not your turn."));
         }
+        let GamePhase::InProgress { turn, board } = &mut self.state else {
+            return Err(app_error!(IllegalMove, "Game is not in progress."));
+        };
+        if !Board::contains(x, y) {
+            return Err(app_error!(
+                IllegalMove,
+                "Square ({x}, {y}) is off the board."
+            ));
+        }
+        if board.get(x, y) != Piece::Empty {
+            return Err(app_error!(
+                IllegalMove,
+                "Square ({x}, {y}) is already taken."
+            ));
+        }
+        let piece = seat.piece;
+        board.set(x, y, piece);
+        *turn += 1;
+        let played = Move { x, y, piece, by };
+        self.history.push(played);
+        if let Some(piece) = board.winning_line() {
+            self.finish(GameResult::Line { winner: by, piece });
+        } else if board.is_full() {
+            self.finish(GameResult::BoardFull);
+        }
+        self.version += 1;
+        Ok(played)
+    }
+    /// The most recent move, if any.
+    pub fn last_move(&self) -> Option<&Move> {
+        self.history.last()
+    }
+    /// Rewind the game by one ply, returning the move that was taken back.
+    pub fn undo_move(&mut self) -> anyhow::Result<Move> {
+        let GamePhase::InProgress { turn, board } = &mut self.state else {
+            return Err(app_error!(IllegalMove, "Game is not in progress."));
+        };
+        let Some(last) = self.history.pop() else {
+            return Err(app_error!(IllegalMove, "No moves to take back."));
+        };
+        board.set(last.x, last.y, Piece::Empty);
+        *turn -= 1;
+        self.version += 1;
+        Ok(last)
+    }
+    /// Concede the game on behalf of the given node.
+    pub fn resign(&mut self, by: NodeId) -> anyhow::Result<()> {
+        self.require_player(&by)?;
+        if !matches!(self.state, GamePhase::InProgress { .. }) {
+            return Err(app_error!(IllegalMove, "Game is not in progress."));
+        }
+        self.finish(GameResult::Resignation { resigned: by });
+        self.version += 1;
+        Ok(())
+    }
+    /// End the game as a draw agreed by both players.
+    pub fn agree_draw(&mut self) -> anyhow::Result<()> {
+        if !matches!(self.state, GamePhase::InProgress { .. }) {
+            return Err(app_error!(IllegalMove, "Game is not in progress."));
+        }
+        self.finish(GameResult::DrawAgreement);
+        self.version += 1;
+        Ok(())
+    }
+    /// Call off the game, only allowed before the first move.
+    pub fn abort(&mut self, by: NodeId) -> anyhow::Result<()> {
+        self.require_player(&by)?;
+        if matches!(self.state, GamePhase::Finished { .. }) {
+            return Err(app_error!(IllegalMove, "Game is already finished."));
+        }
+        if !self.history.is_empty() {
+            return Err(app_error!(
+                IllegalMove,
+                "Cannot abort after the first move."
+            ));
+        }
+        self.finish(GameResult::Aborted);
+        self.version += 1;
+        Ok(())
+    }
+    /// The result of the game, if it has finished.
+    pub fn result(&self) -> Option<&GameResult> {
+        match &self.state {
+            GamePhase::Finished { result, .. } => Some(result),
+            _ => None,
+        }
+    }
+    /// The winning node, if the game finished with one.
+    pub fn winner(&self) -> Option<NodeId> {
+        let opponent = |loser: &NodeId| {
+            self.participants
+                .iter()
+                .find(|seat| seat.is_player() && &seat.node_id != loser)
+                .map(|seat| seat.node_id)
+        };
+        match self.result()? {
+            GameResult::Line { winner, .. } => Some(*winner),
+            GameResult::Resignation { resigned } => opponent(resigned),
+            GameResult::Legacy { winner } => *winner,
+            GameResult::DrawAgreement | GameResult::BoardFull | GameResult::Aborted => None,
+        }
+    }
+    /// Move the game into the finished phase, keeping the final board.
+    fn finish(&mut self, result: GameResult) {
+        let board = match &self.state {
+            GamePhase::InProgress { board, .. } | GamePhase::Finished { board, .. } => *board,
+            GamePhase::New => Board::new(),
+        };
+        self.state = GamePhase::Finished { board, result };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{AppError, ErrorCode};
+    use iroh::SecretKey;
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn code(error: anyhow::Error) -> ErrorCode {
+        AppError::from(error).code
+    }
+
+    /// A game in progress between two new nodes, crosses first.
+    fn started() -> (Game, NodeId, NodeId) {
+        let (cross, naught) = (node(), node());
+        (Game::between(cross, naught).unwrap(), cross, naught)
+    }
+
+    /// Play squares in turn, alternating between the players from crosses.
+    fn play(game: &mut Game, cross: NodeId, naught: NodeId, squares: &[(usize, usize)]) {
+        for (turn, &(x, y)) in squares.iter().enumerate() {
+            let by = if turn % 2 == 0 { cross } else { naught };
+            game.play_move(x, y, by).unwrap();
+        }
+    }
+
+    #[test]
+    fn seats_are_taken_before_the_game_starts() {
+        let (cross, naught, watcher) = (node(), node(), node());
+        let mut game = Game::new();
+        game.take_seat(cross, PlayerType::Human, Piece::Cross)
+            .unwrap();
+        let taken = game.take_seat(naught, PlayerType::Ai, Piece::Cross);
+        assert_eq!(code(taken.unwrap_err()), ErrorCode::IllegalMove);
+        let no_piece = game.take_seat(naught, PlayerType::Human, Piece::Empty);
+        assert_eq!(code(no_piece.unwrap_err()), ErrorCode::InvalidInput);
+        assert_eq!(code(game.start_game().unwrap_err()), ErrorCode::IllegalMove);
+        assert_eq!(game.seats_free(), 1);
+
+        game.take_seat(naught, PlayerType::Ai, Piece::Naught)
+            .unwrap();
+        // a spectator takes no piece, whichever it asks for
+        let seat = game
+            .take_seat(watcher, PlayerType::Spectator, Piece::Cross)
+            .unwrap();
+        assert_eq!(seat.piece, Piece::Empty);
+        assert_eq!(game.seats_free(), 0);
+        game.start_game().unwrap();
+        assert_eq!(game.to_move().map(|seat| seat.node_id), Some(cross));
+
+        let leave = game.take_seat(cross, PlayerType::Spectator, Piece::Empty);
+        assert_eq!(code(leave.unwrap_err()), ErrorCode::IllegalMove);
+        let swap = game.take_seat(watcher, PlayerType::Human, Piece::Naught);
+        assert_eq!(code(swap.unwrap_err()), ErrorCode::IllegalMove);
+        assert_eq!(code(game.start_game().unwrap_err()), ErrorCode::IllegalMove);
+    }
+
+    #[test]
+    fn moves_are_checked() {
+        let (mut game, cross, naught) = started();
+        let watcher = node();
+        game.take_seat(watcher, PlayerType::Spectator, Piece::Empty)
+            .unwrap();
+        let version = game.version;
+        assert_eq!(
+            code(game.play_move(0, 0, naught).unwrap_err()),
+            ErrorCode::NotYourTurn
+        );
+        assert_eq!(
+            code(game.play_move(0, 0, watcher).unwrap_err()),
+            ErrorCode::NotPermitted
+        );
+        assert_eq!(
+            code(game.play_move(3, 0, cross).unwrap_err()),
+            ErrorCode::IllegalMove
+        );
+        assert_eq!(game.version, version);
+
+        let played = game.play_move(1, 1, cross).unwrap();
+        assert_eq!(played.piece, Piece::Cross);
+        assert_eq!(game.last_move(), Some(&played));
+        assert_eq!(game.version, version + 1);
+        assert_eq!(
+            code(game.play_move(1, 1, naught).unwrap_err()),
+            ErrorCode::IllegalMove
+        );
+        assert!(game.view_for(naught).my_turn);
+        assert!(!game.view_for(cross).my_turn);
+    }
+
+    #[test]
+    fn lines_win_and_full_boards_draw() {
+        let (mut game, cross, naught) = started();
+        play(
+            &mut game,
+            cross,
+            naught,
+            &[(0, 0), (0, 1), (1, 1), (0, 2), (2, 2)],
+        );
+        assert_eq!(
+            game.result(),
+            Some(&GameResult::Line {
+                winner: cross,
+                piece: Piece::Cross
+            })
+        );
+        assert_eq!(game.winner(), Some(cross));
+        assert!(game.to_move().is_none());
+        assert_eq!(
+            code(game.play_move(2, 0, naught).unwrap_err()),
+            ErrorCode::IllegalMove
+        );
+
+        let (mut game, cross, naught) = started();
+        let squares = [
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (1, 1),
+            (0, 1),
+            (2, 1),
+            (1, 2),
+            (0, 2),
+            (2, 2),
+        ];
+        play(&mut game, cross, naught, &squares);
+        assert_eq!(game.result(), Some(&GameResult::BoardFull));
+        assert_eq!(game.winner(), None);
+        let GamePhase::Finished { board, .. } = &game.state else {
+            panic!("a full board ends the game");
+        };
+        assert!(board.is_full());
+    }
+
+    #[test]
+    fn players_resign_or_agree_a_draw() {
+        let (mut game, cross, naught) = started();
+        let watcher = node();
+        game.take_seat(watcher, PlayerType::Spectator, Piece::Empty)
+            .unwrap();
+        play(&mut game, cross, naught, &[(0, 0)]);
+        assert_eq!(
+            code(game.resign(watcher).unwrap_err()),
+            ErrorCode::NotPermitted
+        );
+        game.resign(naught).unwrap();
+        assert_eq!(
+            game.result(),
+            Some(&GameResult::Resignation { resigned: naught })
+        );
+        assert_eq!(game.winner(), Some(cross));
+        let GamePhase::Finished { board, .. } = &game.state else {
+            panic!("resigning ends the game");
+        };
+        assert_eq!(board.get(0, 0), Piece::Cross);
+        assert_eq!(
+            code(game.resign(cross).unwrap_err()),
+            ErrorCode::IllegalMove
+        );
+        assert_eq!(code(game.agree_draw().unwrap_err()), ErrorCode::IllegalMove);
+
+        let (mut game, ..) = started();
+        game.agree_draw().unwrap();
+        assert_eq!(game.result(), Some(&GameResult::DrawAgreement));
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn moves_are_taken_back_one_ply_at_a_time() {
+        let (mut game, cross, naught) = started();
+        assert_eq!(code(game.undo_move().unwrap_err()), ErrorCode::IllegalMove);
+        play(&mut game, cross, naught, &[(0, 0), (1, 1)]);
+        let version = game.version;
+
+        let undone = game.undo_move().unwrap();
+        assert_eq!((undone.x, undone.y, undone.by), (1, 1, naught));
+        assert_eq!(game.version, version + 1);
+        assert_eq!(game.history.len(), 1);
+        assert_eq!(game.to_move().map(|seat| seat.node_id), Some(naught));
+        let GamePhase::InProgress { turn, board } = &game.state else {
+            panic!("taking a move back keeps the game going");
+        };
+        assert_eq!(*turn, 1);
+        assert_eq!(board.get(1, 1), Piece::Empty);
+        assert_eq!(board.get(0, 0), Piece::Cross);
+
+        // the square can be played again
+        game.play_move(1, 1, naught).unwrap();
+        game.undo_move().unwrap();
+        game.undo_move().unwrap();
+        assert!(game.history.is_empty());
+        assert_eq!(game.to_move().map(|seat| seat.node_id), Some(cross));
+
+        play(
+            &mut game,
+            cross,
+            naught,
+            &[(0, 0), (0, 1), (1, 1), (0, 2), (2, 2)],
+        );
+        assert_eq!(code(game.undo_move().unwrap_err()), ErrorCode::IllegalMove);
+    }
+
+    #[test]
+    fn games_abort_only_before_the_first_move() {
+        let (mut game, cross, naught) = started();
+        assert_eq!(
+            code(game.abort(node()).unwrap_err()),
+            ErrorCode::NotPermitted
+        );
+        game.abort(naught).unwrap();
+        assert_eq!(game.result(), Some(&GameResult::Aborted));
+        assert_eq!(game.winner(), None);
+        assert_eq!(code(game.abort(cross).unwrap_err()), ErrorCode::IllegalMove);
+
+        let (mut game, cross, naught) = started();
+        play(&mut game, cross, naught, &[(0, 0)]);
+        assert_eq!(
+            code(game.abort(naught).unwrap_err()),
+            ErrorCode::IllegalMove
+        );
+        assert!(game.result().is_none());
     }
 }
@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 /// Square grid, so this is the length of one row/column.
 const BOARD_SIZE: usize = 3;
 
-#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub enum Piece {
     #[default]
     Empty,
@@ -13,6 +13,17 @@ pub enum Piece {
     Cross,
 }
 
+impl Piece {
+    /// The piece placed on a given turn, crosses always go first.
+    pub fn for_turn(turn: usize) -> Self {
+        if turn.is_multiple_of(2) {
+            Self::Cross
+        } else {
+            Self::Naught
+        }
+    }
+}
+
 impl Debug for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -43,6 +54,10 @@ impl Board {
     pub fn new() -> Self {
         Self([Piece::Empty; BOARD_SIZE * BOARD_SIZE])
     }
+    /// Check that a square lies on the board
+    pub fn contains(x: usize, y: usize) -> bool {
+        x < BOARD_SIZE && y < BOARD_SIZE
+    }
     /// Get the piece in a particular square
     pub fn get(&self, x: usize, y: usize) -> Piece {
         self.0[x + y * BOARD_SIZE]
@@ -51,4 +66,25 @@ impl Board {
     pub fn set(&mut self, x: usize, y: usize, piece: Piece) {
         self.0[x + y * BOARD_SIZE] = piece;
     }
+    /// Check whether every square has a piece in it
+    pub fn is_full(&self) -> bool {
+        self.0.iter().all(|piece| *piece != Piece::Empty)
+    }
+    /// Return the piece which fills a complete row, column or diagonal, if any
+    pub fn winning_line(&self) -> Option<Piece> {
+        let rows = (0..BOARD_SIZE).map(|y| (0..BOARD_SIZE).map(|x| (x, y)).collect::<Vec<_>>());
+        let cols = (0..BOARD_SIZE).map(|x| (0..BOARD_SIZE).map(|y| (x, y)).collect::<Vec<_>>());
+        let diagonals = [
+            (0..BOARD_SIZE).map(|i| (i, i)).collect::<Vec<_>>(),
+            (0..BOARD_SIZE)
+                .map(|i| (i, BOARD_SIZE - 1 - i))
+                .collect::<Vec<_>>(),
+        ];
+        rows.chain(cols).chain(diagonals).find_map(|line| {
+            let first = self.get(line[0].0, line[0].1);
+            let complete =
+                first != Piece::Empty && line.iter().all(|&(x, y)| self.get(x, y) == first);
+            complete.then_some(first)
+        })
+    }
 }
@@ -0,0 +1,212 @@
+//! Game state in the shape stored before seats were made absolute.
+//!
+//! Older games recorded participants as `Local` or `Remote` relative to the node which
+//! wrote them, without moves or pieces, as bare postcard under `game_state`. They are
+//! converted into a [`Game`] when read, keeping who was in it, the board and the winner.
+//! As neither who played which piece nor the moves were recorded, everybody is seated as
+//! a spectator, and a game in progress resumes once players take its pieces again.
+
+use super::{board::Board, Game, GamePhase, GameResult, PlayerType, Seat};
+use crate::game::board::Piece;
+use iroh::NodeId;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tracing::warn;
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum Player {
+    /// Local Node is responsible for this player
+    Local(PlayerType),
+    /// Remote Node is responsible for this player
+    Remote(NodeId),
+}
+
+#[derive(Debug, Deserialize)]
+pub enum LegacyPhase {
+    New,
+    InProgress { turn: usize, board: Board },
+    Finished { winner: Player },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegacyGame {
+    pub state: LegacyPhase,
+    pub participants: HashSet<Player>,
+    pub version: u64,
+}
+
+impl LegacyGame {
+    /// Resolve relative players into absolute seats, `Local` ones belonging to the node
+    /// which wrote the game, if its author is bound to one.
+    pub fn migrate(self, writer: Option<NodeId>) -> Game {
+        let resolve = |player: Player| match player {
+            Player::Remote(node_id) => Some(node_id),
+            Player::Local(_) => writer,
+        };
+        let mut participants: Vec<Seat> = Vec::new();
+        for player in self.participants {
+            let Some(node_id) = resolve(player) else {
+                warn!("Dropping local player, the game's writer can't be identified");
+                continue;
+            };
+            if participants.iter().all(|seat| seat.node_id != node_id) {
+                participants.push(Seat {
+                    node_id,
+                    role: PlayerType::Spectator,
+                    piece: Piece::Empty,
+                });
+            }
+        }
+        let state = match self.state {
+            LegacyPhase::New => GamePhase::New,
+            LegacyPhase::InProgress { turn, board } => GamePhase::InProgress { turn, board },
+            // finished games only recorded their winner, not the final board
+            LegacyPhase::Finished { winner } => GamePhase::Finished {
+                board: Board::new(),
+                result: GameResult::Legacy {
+                    winner: resolve(winner),
+                },
+            },
+        };
+        Game {
+            state,
+            participants,
+            history: Vec::new(),
+            version: self.version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::doc::schema;
+    use iroh::SecretKey;
+    use serde::Serialize;
+
+    /// The types as the app stored them before seats, serialised the same way.
+    mod stored {
+        use super::*;
+
+        #[derive(Serialize, PartialEq, Eq, Hash)]
+        pub enum Player {
+            Local(PlayerType),
+            Remote(NodeId),
+        }
+
+        #[derive(Serialize)]
+        pub enum GamePhase {
+            New,
+            InProgress { turn: usize, board: Board },
+            Finished { winner: Player },
+        }
+
+        #[derive(Serialize)]
+        pub struct Game {
+            pub state: GamePhase,
+            pub participants: HashSet<Player>,
+            pub version: u64,
+        }
+    }
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    /// Store a game the way older versions did and read it back.
+    fn read(state: stored::GamePhase, participants: Vec<stored::Player>) -> LegacyGame {
+        let stored = stored::Game {
+            state,
+            participants: participants.into_iter().collect(),
+            version: 4,
+        };
+        let bytes = postcard::to_stdvec(&stored).unwrap();
+        schema::decode::<LegacyGame>(&bytes).unwrap().unwrap()
+    }
+
+    fn seated(game: &Game) -> Vec<NodeId> {
+        let mut seated: Vec<_> = game.participants.iter().map(|seat| seat.node_id).collect();
+        seated.sort();
+        seated
+    }
+
+    #[test]
+    fn new_games_seat_everyone_as_spectators() {
+        let (writer, remote) = (node(), node());
+        let players = || {
+            vec![
+                stored::Player::Local(PlayerType::Human),
+                stored::Player::Local(PlayerType::Ai),
+                stored::Player::Remote(remote),
+            ]
+        };
+        let game = read(stored::GamePhase::New, players()).migrate(Some(writer));
+        assert!(matches!(game.state, GamePhase::New));
+        let mut expected = vec![writer, remote];
+        expected.sort();
+        assert_eq!(seated(&game), expected);
+        assert!(game.participants.iter().all(|seat| !seat.is_player()));
+        assert!(game.history.is_empty());
+        assert_eq!(game.version, 4);
+
+        // without a bound writer, only the remote players can be placed
+        let game = read(stored::GamePhase::New, players()).migrate(None);
+        assert_eq!(seated(&game), vec![remote]);
+    }
+
+    #[test]
+    fn games_in_progress_resume_once_pieces_are_taken() {
+        let (writer, remote) = (node(), node());
+        let mut board = Board::new();
+        board.set(1, 1, Piece::Cross);
+        let players = vec![
+            stored::Player::Local(PlayerType::Human),
+            stored::Player::Remote(remote),
+        ];
+        let state = stored::GamePhase::InProgress { turn: 1, board };
+        let mut game = read(state, players).migrate(Some(writer));
+        let GamePhase::InProgress { turn, board } = &game.state else {
+            panic!("games in progress stay in progress");
+        };
+        assert_eq!(*turn, 1);
+        assert_eq!(board.get(1, 1), Piece::Cross);
+        assert!(game.to_move().is_none());
+
+        game.take_seat(writer, PlayerType::Human, Piece::Cross)
+            .unwrap();
+        game.take_seat(remote, PlayerType::Human, Piece::Naught)
+            .unwrap();
+        assert_eq!(game.to_move().map(|seat| seat.node_id), Some(remote));
+        game.play_move(0, 0, remote).unwrap();
+        assert_eq!(game.to_move().map(|seat| seat.node_id), Some(writer));
+    }
+
+    #[test]
+    fn finished_games_keep_their_winner() {
+        let (writer, remote) = (node(), node());
+        let players = || {
+            vec![
+                stored::Player::Local(PlayerType::Human),
+                stored::Player::Remote(remote),
+            ]
+        };
+        let won_by = |winner| stored::GamePhase::Finished { winner };
+
+        let game = read(won_by(stored::Player::Remote(remote)), players()).migrate(None);
+        assert_eq!(
+            game.result(),
+            Some(&GameResult::Legacy {
+                winner: Some(remote)
+            })
+        );
+        assert_eq!(game.winner(), Some(remote));
+
+        let local = stored::Player::Local(PlayerType::Human);
+        let game = read(won_by(local), players()).migrate(Some(writer));
+        assert_eq!(game.winner(), Some(writer));
+        // a local winner can't be placed without the writer
+        let local = stored::Player::Local(PlayerType::Human);
+        let game = read(won_by(local), players()).migrate(None);
+        assert_eq!(game.result(), Some(&GameResult::Legacy { winner: None }));
+    }
+}
@@ -0,0 +1,393 @@
+//! Pairing and scoring for tournaments played between the members of a room.
+//!
+//! Only the format and roster are stored, the bracket is derived from them and the recorded
+//! match results so every peer reaches the same pairings without coordinating.
+
+use super::GameResult;
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TournamentFormat {
+    /// Everybody plays everybody else once
+    RoundRobin,
+    /// Single elimination, winners advance until one remains
+    Knockout,
+}
+
+/// Identifies one match within a tournament.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MatchId {
+    pub round: u32,
+    pub index: u32,
+}
+
+/// Two entrants drawn against each other.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Pairing {
+    pub id: MatchId,
+    /// Plays crosses, and moves first
+    pub cross: NodeId,
+    /// Plays naughts, `None` if `cross` has a bye this round
+    pub naught: Option<NodeId>,
+}
+
+impl Pairing {
+    /// Check whether this pairing is a bye rather than a game.
+    pub fn is_bye(&self) -> bool {
+        self.naught.is_none()
+    }
+    /// Check whether the given node plays in this pairing.
+    pub fn involves(&self, node_id: &NodeId) -> bool {
+        &self.cross == node_id || self.naught.as_ref() == Some(node_id)
+    }
+}
+
+/// The recorded outcome of a tournament match.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MatchResult {
+    pub id: MatchId,
+    /// `None` for a drawn match
+    pub winner: Option<NodeId>,
+    pub result: GameResult,
+}
+
+/// One entrant's record so far.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    pub node_id: NodeId,
+    pub played: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Two for a win, one for a draw
+    pub points: u32,
+}
+
+/// Tournament settings shared by the room.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tournament {
+    pub format: TournamentFormat,
+    /// Entrants in seeding order
+    pub roster: Vec<NodeId>,
+    /// microseconds since EPOCH
+    pub created: u64,
+}
+
+/// Seeds in the order they are drawn into a bracket of the given size, so the top two
+/// seeds can only meet in the final, the top four in the semi-finals and so on.
+///
+/// Each round of doubling pairs every seed with the one it would face, `1 8 4 5 2 7 3 6`
+/// for eight entrants counting from one.
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let count = order.len() * 2;
+        order = order
+            .into_iter()
+            .flat_map(|seed| [seed, count - 1 - seed])
+            .collect();
+    }
+    order
+}
+
+/// A tournament with its current bracket, results and standings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TournamentView {
+    pub format: TournamentFormat,
+    pub roster: Vec<NodeId>,
+    /// Pairings known so far, by round
+    pub rounds: Vec<Vec<Pairing>>,
+    pub results: Vec<MatchResult>,
+    pub standings: Vec<Standing>,
+    /// Overall winner once the tournament is complete
+    pub champion: Option<NodeId>,
+    pub complete: bool,
+}
+
+impl Tournament {
+    /// Entrants appearing more than once keep their first place in the roster.
+    pub fn new(format: TournamentFormat, mut roster: Vec<NodeId>, created: u64) -> Self {
+        let mut seen = HashSet::new();
+        roster.retain(|node_id| seen.insert(*node_id));
+        Self {
+            format,
+            roster,
+            created,
+        }
+    }
+
+    /// Work out every round which can be paired given the results so far.
+    pub fn rounds(&self, results: &HashMap<MatchId, MatchResult>) -> Vec<Vec<Pairing>> {
+        match self.format {
+            TournamentFormat::RoundRobin => self.round_robin(),
+            TournamentFormat::Knockout => self.knockout(results),
+        }
+    }
+
+    /// Circle method, fix the first entrant and rotate everybody else one place each round.
+    fn round_robin(&self) -> Vec<Vec<Pairing>> {
+        let mut entrants: Vec<Option<NodeId>> = self.roster.iter().copied().map(Some).collect();
+        if entrants.len() % 2 == 1 {
+            entrants.push(None);
+        }
+        let count = entrants.len();
+        if count < 2 {
+            return Vec::new();
+        }
+        let mut rounds = Vec::new();
+        for round in 0..count - 1 {
+            let mut pairings = Vec::new();
+            for index in 0..count / 2 {
+                let (mut a, mut b) = (entrants[index], entrants[count - 1 - index]);
+                // alternate who moves first so nobody always plays crosses
+                if (round + index) % 2 == 1 {
+                    std::mem::swap(&mut a, &mut b);
+                }
+                let id = MatchId {
+                    round: round as u32,
+                    index: index as u32,
+                };
+                if let Some(pairing) = Self::pair(id, a, b) {
+                    pairings.push(pairing);
+                }
+            }
+            rounds.push(pairings);
+            entrants[1..].rotate_right(1);
+        }
+        rounds
+    }
+
+    /// Seeded bracket, top seeds receive byes when the roster isn't a power of two.
+    ///
+    /// A drawn knockout match is won by the higher seed.
+    fn knockout(&self, results: &HashMap<MatchId, MatchResult>) -> Vec<Vec<Pairing>> {
+        if self.roster.len() < 2 {
+            return Vec::new();
+        }
+        let size = self.roster.len().next_power_of_two();
+        let mut entrants: Vec<Option<NodeId>> = bracket_order(size)
+            .into_iter()
+            .map(|seed| self.roster.get(seed).copied())
+            .collect();
+        let mut rounds = Vec::new();
+        let mut round = 0;
+        while entrants.len() >= 2 {
+            let pairings: Vec<Pairing> = entrants
+                .chunks(2)
+                .enumerate()
+                .filter_map(|(index, pair)| {
+                    let id = MatchId {
+                        round,
+                        index: index as u32,
+                    };
+                    Self::pair(id, pair[0], pair[1])
+                })
+                .collect();
+            let winners: Option<Vec<Option<NodeId>>> = pairings
+                .iter()
+                .map(|pairing| self.advancing(pairing, results).map(Some))
+                .collect();
+            rounds.push(pairings);
+            match winners {
+                Some(winners) => entrants = winners,
+                None => break, // round still being played
+            }
+            round += 1;
+        }
+        rounds
+    }
+
+    /// Build a pairing between two slots, putting any lone entrant on a bye.
+    fn pair(id: MatchId, a: Option<NodeId>, b: Option<NodeId>) -> Option<Pairing> {
+        match (a, b) {
+            (Some(cross), naught) => Some(Pairing { id, cross, naught }),
+            (None, Some(cross)) => Some(Pairing {
+                id,
+                cross,
+                naught: None,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// The entrant going through from a knockout pairing, once it is decided.
+    fn advancing(
+        &self,
+        pairing: &Pairing,
+        results: &HashMap<MatchId, MatchResult>,
+    ) -> Option<NodeId> {
+        let Some(naught) = pairing.naught else {
+            return Some(pairing.cross);
+        };
+        let result = results.get(&pairing.id)?;
+        Some(result.winner.unwrap_or_else(|| {
+            let seed = |node_id: &NodeId| self.roster.iter().position(|id| id == node_id);
+            if seed(&pairing.cross) <= seed(&naught) {
+                pairing.cross
+            } else {
+                naught
+            }
+        }))
+    }
+
+    /// Tally each entrant's record from the played matches, best first.
+    pub fn standings(
+        &self,
+        rounds: &[Vec<Pairing>],
+        results: &HashMap<MatchId, MatchResult>,
+    ) -> Vec<Standing> {
+        let mut table: Vec<Standing> = self
+            .roster
+            .iter()
+            .map(|node_id| Standing {
+                node_id: *node_id,
+                played: 0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+                points: 0,
+            })
+            .collect();
+        for pairing in rounds.iter().flatten() {
+            let (Some(naught), Some(result)) = (pairing.naught, results.get(&pairing.id)) else {
+                continue;
+            };
+            for standing in table.iter_mut() {
+                if standing.node_id != pairing.cross && standing.node_id != naught {
+                    continue;
+                }
+                standing.played += 1;
+                match result.winner {
+                    Some(winner) if winner == standing.node_id => {
+                        standing.wins += 1;
+                        standing.points += 2;
+                    }
+                    Some(_) => standing.losses += 1,
+                    None => {
+                        standing.draws += 1;
+                        standing.points += 1;
+                    }
+                }
+            }
+        }
+        // stable sort keeps seeding order as the tie break
+        table.sort_by_key(|standing| Reverse(standing.points));
+        table
+    }
+
+    /// Combine the settings and results into the full picture for display.
+    pub fn view(&self, results: HashMap<MatchId, MatchResult>) -> TournamentView {
+        let rounds = self.rounds(&results);
+        let standings = self.standings(&rounds, &results);
+        let decided = |pairing: &Pairing| pairing.is_bye() || results.contains_key(&pairing.id);
+        let complete = match self.format {
+            TournamentFormat::RoundRobin => rounds.iter().flatten().all(decided),
+            TournamentFormat::Knockout => rounds
+                .last()
+                .is_some_and(|last| last.len() == 1 && last.iter().all(decided)),
+        };
+        let champion = match self.format {
+            _ if !complete => None,
+            TournamentFormat::RoundRobin => standings.first().map(|standing| standing.node_id),
+            TournamentFormat::Knockout => rounds
+                .last()
+                .and_then(|last| last.first())
+                .and_then(|last| self.advancing(last, &results)),
+        };
+        let mut results: Vec<MatchResult> = results.into_values().collect();
+        results.sort_by_key(|result| result.id);
+        TournamentView {
+            format: self.format,
+            roster: self.roster.clone(),
+            rounds,
+            results,
+            standings,
+            champion,
+            complete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::board::Piece;
+    use iroh::SecretKey;
+
+    fn roster(count: usize) -> Vec<NodeId> {
+        (0..count)
+            .map(|_| SecretKey::generate(rand::rngs::OsRng).public())
+            .collect()
+    }
+
+    fn win(pairing: &Pairing, winner: NodeId) -> MatchResult {
+        MatchResult {
+            id: pairing.id,
+            winner: Some(winner),
+            result: GameResult::Line {
+                winner,
+                piece: Piece::Cross,
+            },
+        }
+    }
+
+    #[test]
+    fn brackets_keep_the_top_seeds_apart() {
+        assert_eq!(bracket_order(8), [0, 7, 3, 4, 1, 6, 2, 5]);
+
+        let seeds = roster(8);
+        let tournament = Tournament::new(TournamentFormat::Knockout, seeds.clone(), 0);
+        let mut results = HashMap::new();
+        // the higher seed wins every match
+        for _ in 0..3 {
+            let rounds = tournament.rounds(&results);
+            for pairing in rounds.last().unwrap() {
+                let seed = |node_id| seeds.iter().position(|id| *id == node_id);
+                let winner = match pairing.naught {
+                    Some(naught) if seed(naught) < seed(pairing.cross) => naught,
+                    _ => pairing.cross,
+                };
+                results.insert(pairing.id, win(pairing, winner));
+            }
+        }
+        let rounds = tournament.rounds(&results);
+        let semi_finals: Vec<_> = rounds[1]
+            .iter()
+            .map(|pairing| (pairing.cross, pairing.naught.unwrap()))
+            .collect();
+        assert_eq!(semi_finals, [(seeds[0], seeds[3]), (seeds[1], seeds[2])]);
+        let view = tournament.view(results);
+        assert!(view.complete);
+        assert_eq!(view.champion, Some(seeds[0]));
+    }
+
+    #[test]
+    fn top_seeds_get_the_byes() {
+        let seeds = roster(5);
+        let tournament = Tournament::new(TournamentFormat::Knockout, seeds.clone(), 0);
+        let rounds = tournament.rounds(&HashMap::new());
+        let byes: Vec<_> = rounds[0]
+            .iter()
+            .filter(|pairing| pairing.is_bye())
+            .map(|pairing| pairing.cross)
+            .collect();
+        assert_eq!(byes, [seeds[0], seeds[1], seeds[2]]);
+        assert_eq!(rounds[0].len(), 4);
+    }
+
+    #[test]
+    fn entrants_are_only_entered_once() {
+        let seeds = roster(3);
+        let entered = vec![seeds[0], seeds[1], seeds[0], seeds[2], seeds[1]];
+        let tournament = Tournament::new(TournamentFormat::RoundRobin, entered, 0);
+        assert_eq!(tournament.roster, seeds);
+        let rounds = tournament.rounds(&HashMap::new());
+        assert_eq!(rounds.iter().flatten().filter(|p| !p.is_bye()).count(), 3);
+    }
+}
@@ -1,41 +1,112 @@
+pub mod diagnostics;
 pub mod doc;
 mod event;
+pub mod lobby;
+pub mod notify;
+pub mod search;
+pub mod storage;
+pub mod voice;
 
+use crate::error::app_error;
 use std::{path::PathBuf, sync::Arc};
 
 use anyhow::Result;
 use doc::{BlobsClient, DocsClient};
-pub use event::spawn_event_listener;
+pub use event::{spawn_event_listener, DeltaCursor, RoomSnapshot};
 pub use iroh::NodeId;
-use iroh::{endpoint::RemoteInfo, protocol::Router, SecretKey};
-use iroh_blobs::net_protocol::Blobs;
+use iroh::{
+    endpoint::{Connection, RemoteInfo},
+    protocol::Router,
+    RelayMode, RelayUrl, SecretKey,
+};
+use iroh_base::Signature;
+use iroh_blobs::{net_protocol::Blobs, store::GcConfig};
 use iroh_docs::protocol::Docs;
+use iroh_docs::rpc::AddrInfoOptions;
 use iroh_gossip::net::Gossip;
+use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
+use voice::{VoiceProtocol, VOICE_ALPN};
+
+/// How this node finds and reaches its peers.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkMode {
+    /// Discovery through n0's DNS servers, falling back to their relays
+    #[default]
+    Internet,
+    /// Discovery by mDNS on the local network only, with no relays
+    LocalNetwork,
+}
+
+/// Which relay servers to fall back on when peers can't be dialled directly.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub enum RelaySettings {
+    /// n0's public production relays
+    #[default]
+    Default,
+    /// No relays, only direct connections
+    Disabled,
+    /// Only the listed relays
+    Custom(Vec<RelayUrl>),
+}
+
+impl RelaySettings {
+    /// Check the settings can be spawned with.
+    fn check(&self) -> Result<()> {
+        if *self == RelaySettings::Custom(Vec::new()) {
+            return Err(app_error!(
+                InvalidInput,
+                "At least one custom relay is needed."
+            ));
+        }
+        Ok(())
+    }
+
+    fn relay_mode(&self) -> RelayMode {
+        match self {
+            RelaySettings::Default => RelayMode::Default,
+            RelaySettings::Disabled => RelayMode::Disabled,
+            RelaySettings::Custom(urls) => RelayMode::Custom(urls.iter().cloned().collect()),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct GossipNode {
-    #[allow(unused)]
     secret_key: SecretKey,
+    path: PathBuf,
+    mode: NetworkMode,
+    relays: RelaySettings,
     router: Router,
-    #[allow(unused)]
     gossip: Gossip,
     blobs: BlobsClient,
     docs: DocsClient,
+    voice: VoiceProtocol,
 }
 
 impl GossipNode {
     /// Spawns a gossip node.
-    pub async fn spawn(secret_key: Option<SecretKey>, path: PathBuf) -> Result<Self> {
+    pub async fn spawn(
+        secret_key: Option<SecretKey>,
+        path: PathBuf,
+        mode: NetworkMode,
+        relays: RelaySettings,
+    ) -> Result<Self> {
+        relays.check()?;
         let secret_key = secret_key.unwrap_or_else(|| SecretKey::generate(rand::rngs::OsRng));
-        let endpoint = iroh::Endpoint::builder()
-            .discovery_n0()
-            .secret_key(secret_key.clone())
-            .bind()
-            .await?;
+        let builder = match mode {
+            NetworkMode::Internet => iroh::Endpoint::builder()
+                .relay_mode(relays.relay_mode())
+                .discovery_n0(),
+            // relays are never reachable without internet access
+            NetworkMode::LocalNetwork => iroh::Endpoint::builder()
+                .relay_mode(RelayMode::Disabled)
+                .discovery_local_network(),
+        };
+        let endpoint = builder.secret_key(secret_key.clone()).bind().await?;
 
         let node_id = endpoint.node_id();
-        info!("endpoint bound");
+        info!("endpoint bound in {mode:?} mode with {relays:?} relays");
         info!("node id: {node_id:#?}");
 
         // build the protocol router
@@ -47,15 +118,30 @@ impl GossipNode {
         let blobs = Blobs::persistent(&path).await?.build(builder.endpoint());
         builder = builder.accept(iroh_blobs::ALPN, blobs.clone());
         info!("blobs spawned");
-        let docs = Docs::persistent(path).spawn(&blobs, &gossip).await?;
+        let docs = Docs::persistent(path.clone())
+            .spawn(&blobs, &gossip)
+            .await?;
         builder = builder.accept(iroh_docs::ALPN, Arc::new(docs.clone()));
         info!("docs spawned");
+        let voice = VoiceProtocol::default();
+        builder = builder.accept(VOICE_ALPN, voice.clone());
+        info!("voice spawned");
+        // collect blobs no document refers to, such as superseded values and dropped rooms
+        blobs.add_protected(docs.protect_cb())?;
+        blobs.start_gc(GcConfig {
+            period: storage::GC_PERIOD,
+            done_callback: None,
+        })?;
         Ok(Self {
             gossip,
             secret_key,
+            path,
+            mode,
+            relays,
             router: builder.spawn(),
             blobs: blobs.client().clone(),
             docs: docs.client().clone(),
+            voice,
         })
     }
 
@@ -64,7 +150,82 @@ impl GossipNode {
         self.router.endpoint().node_id()
     }
 
-    #[allow(unused)]
+    /// Returns the secret key identifying this node.
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+
+    /// Returns how this node finds its peers.
+    pub fn network_mode(&self) -> NetworkMode {
+        self.mode
+    }
+
+    /// Returns the relay servers this node falls back on.
+    pub fn relay_settings(&self) -> &RelaySettings {
+        &self.relays
+    }
+
+    /// Shuts this node down and spawns it again with the same identity and data,
+    /// but with new network settings.
+    ///
+    /// Any documents opened through the old node are closed along with it. Settings
+    /// which are rejected leave the node running, and if it fails to spawn with them it
+    /// is spawned again with the settings it had.
+    pub async fn respawn(&mut self, mode: NetworkMode, relays: RelaySettings) -> Result<()> {
+        relays.check()?;
+        self.shutdown().await;
+        let key = Some(self.secret_key.clone());
+        match Self::spawn(key.clone(), self.path.clone(), mode, relays).await {
+            Ok(node) => {
+                *self = node;
+                Ok(())
+            }
+            Err(e) => {
+                warn!("Failed to spawn with new network settings, restoring the old ones: {e}");
+                let relays = self.relays.clone();
+                *self = Self::spawn(key, self.path.clone(), self.mode, relays).await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns which addressing information to put in tickets for this node.
+    ///
+    /// Without relays or DNS discovery, peers can only dial the direct addresses.
+    pub fn ticket_addr_options(&self) -> AddrInfoOptions {
+        match self.mode {
+            NetworkMode::Internet => AddrInfoOptions::default(),
+            NetworkMode::LocalNetwork => AddrInfoOptions::Addresses,
+        }
+    }
+
+    /// Returns the handle for joining gossip topics outside of documents.
+    pub fn gossip(&self) -> &Gossip {
+        &self.gossip
+    }
+
+    /// Returns the handler taking voice calls for this node.
+    pub fn voice(&self) -> &VoiceProtocol {
+        &self.voice
+    }
+
+    /// Opens a connection to a node on the given protocol.
+    pub async fn connect(&self, node_id: NodeId, alpn: &[u8]) -> Result<Connection> {
+        self.router.endpoint().connect(node_id, alpn).await
+    }
+
+    /// Signs a message with this node's secret key.
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.secret_key.sign(message)
+    }
+
+    /// Returns a secret only this node and the other one can compute, by X25519 between
+    /// their keys, the same conversion iroh uses to encrypt to a node.
+    pub fn shared_secret(&self, other: &NodeId) -> [u8; 32] {
+        let ours = self.secret_key.secret().to_scalar();
+        (other.public().to_montgomery() * ours).to_bytes()
+    }
+
     /// Returns information about all the remote nodes this [`Endpoint`] knows about.
     pub fn remote_info(&self) -> Vec<RemoteInfo> {
         self.router
@@ -73,7 +234,6 @@ impl GossipNode {
             .collect::<Vec<_>>()
     }
 
-    #[allow(unused)]
     /// Shuts down the [`Endpoint`]
     pub async fn shutdown(&self) {
         if let Err(err) = self.router.shutdown().await {
@@ -82,3 +242,67 @@ impl GossipNode {
         self.router.endpoint().close().await;
     }
 }
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Spawn a node on the local network only, storing its data in a directory removed
+    /// along with it.
+    pub async fn spawn_node() -> (GossipNode, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let node = GossipNode::spawn(
+            None,
+            dir.path().to_path_buf(),
+            NetworkMode::LocalNetwork,
+            RelaySettings::Disabled,
+        )
+        .await
+        .unwrap();
+        (node, dir)
+    }
+
+    /// Tell a node where to reach another, without waiting for discovery.
+    pub async fn introduce(node: &GossipNode, other: &GossipNode) {
+        let addr = other.router.endpoint().node_addr().await.unwrap();
+        node.router.endpoint().add_node_addr(addr).unwrap();
+    }
+
+    /// Poll until the check returns a value, panicking after a few seconds.
+    pub async fn eventually<T, F: std::future::Future<Output = Option<T>>>(
+        mut check: impl FnMut() -> F,
+    ) -> T {
+        for _ in 0..100 {
+            if let Some(value) = check().await {
+                return value;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("condition not met in time");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rejected_settings_keep_the_node_running() {
+        let (mut node, _dir) = testing::spawn_node().await;
+        let node_id = node.node_id();
+        let result = node
+            .respawn(NetworkMode::LocalNetwork, RelaySettings::Custom(Vec::new()))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(node.relay_settings(), &RelaySettings::Disabled);
+        node.docs.create().await.unwrap();
+
+        node.respawn(NetworkMode::LocalNetwork, RelaySettings::Disabled)
+            .await
+            .unwrap();
+        assert_eq!(node.node_id(), node_id);
+        node.docs.create().await.unwrap();
+        node.shutdown().await;
+    }
+}
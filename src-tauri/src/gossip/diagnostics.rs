@@ -0,0 +1,135 @@
+//! Connection quality to the peers of a room, from what the endpoint knows about them.
+//!
+//! The roster comes from the room document and the connection details from
+//! [`GossipNode::remote_info`], so peers we have never reached are still listed.
+
+use crate::{
+    gossip::{doc::peers::PeerInfo, GossipNode},
+    state::ActiveChannel,
+};
+use iroh::{
+    endpoint::{ConnectionType, RemoteInfo},
+    RelayUrl,
+};
+use n0_future::task::AbortOnDropHandle;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tauri::{AppHandle, Emitter as _};
+use tokio::{sync::Mutex as TokioMutex, time::interval};
+use tracing::{debug, warn};
+
+use super::event::Event;
+
+/// How often connection diagnostics are sent to the frontend.
+pub const DIAGNOSTICS_INTERVAL: Duration = Duration::from_secs(5);
+/// Round trip time above which a connection is too slow for timed games.
+const POOR_LATENCY: Duration = Duration::from_millis(300);
+
+/// How packets currently reach a peer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionKind {
+    /// Straight to the peer's address
+    Direct,
+    /// Through a relay server
+    Relay,
+    /// Through a relay while a direct address is being confirmed
+    Mixed,
+    /// No known way to reach the peer
+    None,
+}
+
+/// A room peer along with the state of our connection to it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerConnection {
+    #[serde(flatten)]
+    pub peer: PeerInfo,
+    pub connection: ConnectionKind,
+    /// Round trip time of the current path in milliseconds
+    pub latency_ms: Option<u64>,
+    /// Milliseconds since anything was last sent to or received from the peer
+    pub last_seen_ms: Option<u64>,
+    /// Relay server the peer is reachable through
+    pub relay_url: Option<RelayUrl>,
+    /// Whether the connection looks too unreliable for a timed game
+    pub poor: bool,
+}
+
+impl PeerConnection {
+    fn new(peer: PeerInfo, remote: Option<&RemoteInfo>) -> Self {
+        let Some(remote) = remote else {
+            return Self {
+                peer,
+                connection: ConnectionKind::None,
+                latency_ms: None,
+                last_seen_ms: None,
+                relay_url: None,
+                poor: true,
+            };
+        };
+        let connection = match remote.conn_type {
+            ConnectionType::Direct(_) => ConnectionKind::Direct,
+            ConnectionType::Relay(_) => ConnectionKind::Relay,
+            ConnectionType::Mixed(..) => ConnectionKind::Mixed,
+            ConnectionType::None => ConnectionKind::None,
+        };
+        let poor = connection == ConnectionKind::None
+            || remote.latency.is_none_or(|latency| latency > POOR_LATENCY);
+        Self {
+            peer,
+            connection,
+            latency_ms: remote.latency.map(|latency| latency.as_millis() as u64),
+            last_seen_ms: remote.last_used.map(|elapsed| elapsed.as_millis() as u64),
+            relay_url: remote.relay_url.as_ref().map(|info| info.relay_url.clone()),
+            poor,
+        }
+    }
+}
+
+/// Match each peer of the room, other than ourselves, to what the endpoint knows about it.
+pub async fn diagnose(
+    channel: &ActiveChannel,
+    node: &GossipNode,
+) -> anyhow::Result<Vec<PeerConnection>> {
+    let remotes = node.remote_info();
+    let me = node.node_id();
+    Ok(channel
+        .activity
+        .get_all_peer_info()
+        .await?
+        .into_iter()
+        .filter(|peer| peer.id != me)
+        .map(|peer| {
+            let remote = remotes.iter().find(|remote| remote.node_id == peer.id);
+            PeerConnection::new(peer, remote)
+        })
+        .collect())
+}
+
+/// Spawns a background task which periodically reports connection diagnostics for the room.
+pub fn spawn_diagnostics_reporter(
+    app: AppHandle,
+    node: GossipNode,
+    active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
+) -> AbortOnDropHandle<()> {
+    AbortOnDropHandle::new(n0_future::task::spawn(async move {
+        let mut ticks = interval(DIAGNOSTICS_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let peers = match active_channel.lock().await.as_ref() {
+                Some(channel) => diagnose(channel, &node).await,
+                None => continue,
+            };
+            match peers {
+                Ok(peers) => {
+                    debug!("Connection diagnostics for {} peers", peers.len());
+                    let event = Event::ConnectionUpdate { peers };
+                    if let Err(e) = app.emit("chat-event", &event) {
+                        warn!("Failed to emit connection diagnostics: {e}");
+                    }
+                }
+                Err(e) => warn!("Failed to diagnose connections: {e}"),
+            }
+        }
+    }))
+}
@@ -1,13 +1,29 @@
 //! Any logic related to updating and synchronizing of the Document used for
 //! sharing state data between nodes.
 
+pub mod admission;
+pub mod attachment;
 pub mod chat;
+pub mod game;
+pub mod moderation;
 pub mod peers;
+pub mod profile;
+pub mod room;
+pub mod schema;
+pub mod sealing;
+pub mod ticket;
+pub mod tournament;
 
-use std::ops::Deref;
+use std::{collections::HashSet, ops::Deref};
 
-use crate::gossip::GossipNode;
+use crate::{
+    error::app_error,
+    gossip::{GossipNode, NodeId},
+};
+use admission::Admission;
+use anyhow::{anyhow, Context as _};
 use bytes::Bytes;
+use iroh_base::Signature;
 use iroh_blobs::rpc::{client::blobs, proto as blobs_proto};
 use iroh_blobs::Hash;
 use iroh_docs::rpc::{client::docs, proto as docs_proto};
@@ -16,10 +32,14 @@ use iroh_docs::Entry;
 use iroh_docs::{
     engine::LiveEvent,
     rpc::client::docs::{Doc, ShareMode},
-    AuthorId, DocTicket, NamespaceId,
+    AuthorId, Capability, CapabilityKind, DocTicket, NamespaceId,
 };
-use n0_future::Stream;
+use n0_future::{Stream, StreamExt as _};
 use quic_rpc::transport::flume::FlumeConnector;
+use schema::DocValue;
+use sealing::RoomKey;
+use serde::{Deserialize, Serialize};
+use ticket::{RoomCredentials, RoomTicket};
 
 pub type BlobsRPCConnector = FlumeConnector<blobs_proto::Response, blobs_proto::Request>;
 
@@ -29,23 +49,90 @@ pub type BlobsClient = blobs::Client<BlobsRPCConnector>;
 
 pub type DocsClient = docs::Client<DocsRPCConnector>;
 
-// Key constants for structuring data in the iroh-doc
+// Key constants for structuring data in the iroh-doc, see [`schema::DocKey`] for parsing
 pub const PEERS_PREFIX: &[u8] = b"peers/";
 pub const NICKNAME_KEY_SUFFIX: &[u8] = b"/nickname";
+/// Raw image content, see [`profile`].
+pub const AVATAR_KEY_SUFFIX: &[u8] = b"/avatar";
 pub const MESSAGES_PREFIX: &[u8] = b"messages/";
-pub const GAME_STATE_KEY: &[u8] = b"game_state";
+pub const GAME_STATE_KEY: &[u8] = b"game/state";
+/// Games stored with relative `Player` identities, see [`crate::game::legacy`].
+pub const LEGACY_GAME_STATE_KEY: &[u8] = b"game_state";
+pub const TAKEBACK_REQUEST_KEY: &[u8] = b"takeback/request";
+pub const TAKEBACK_RESPONSE_KEY: &[u8] = b"takeback/response";
+pub const DRAW_OFFER_KEY: &[u8] = b"draw/offer";
+pub const DRAW_RESPONSE_KEY: &[u8] = b"draw/response";
+pub const TOURNAMENT_KEY: &[u8] = b"tournament/settings";
+pub const TOURNAMENT_GAMES_PREFIX: &[u8] = b"tournament/games/";
+pub const TOURNAMENT_RESULTS_PREFIX: &[u8] = b"tournament/results/";
+pub const ADMISSION_PREFIX: &[u8] = b"admission/";
+pub const ADMISSION_SETTINGS_KEY: &[u8] = b"admission/settings";
+pub const ADMISSION_AUTHORS_PREFIX: &[u8] = b"admission/authors/";
+pub const ADMISSION_REQUESTS_PREFIX: &[u8] = b"admission/requests/";
+pub const ADMISSION_DECISIONS_PREFIX: &[u8] = b"admission/decisions/";
+pub const ADMISSION_CHALLENGES_PREFIX: &[u8] = b"admission/challenges/";
+pub const ADMISSION_RESPONSES_PREFIX: &[u8] = b"admission/responses/";
+pub const MODERATION_PREFIX: &[u8] = b"moderation/";
+pub const MODERATION_ROLES_PREFIX: &[u8] = b"moderation/roles/";
+pub const MODERATION_ACTIONS_PREFIX: &[u8] = b"moderation/actions/";
+pub const ROOM_META_KEY: &[u8] = b"room/meta";
+/// Raw file content rather than a [`DocValue`], see [`attachment`].
+pub const ATTACHMENTS_PREFIX: &[u8] = b"attachments/";
+
+/// A value signed by the node which wrote it.
+///
+/// Doc entries are only tied to a per-session author, so entries which only certain
+/// nodes may write carry a signature from the node's key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Signed {
+    pub signer: NodeId,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Signed {
+    pub fn sign<T: DocValue + Serialize>(value: &T, node: &GossipNode) -> anyhow::Result<Self> {
+        let payload = schema::encode(value)?;
+        let signature = node.sign(&payload).to_bytes().to_vec();
+        Ok(Self {
+            signer: node.node_id(),
+            payload,
+            signature,
+        })
+    }
+    /// Decode the value, checking it was signed by the node it claims.
+    pub fn verify<T: DocValue>(&self) -> anyhow::Result<T> {
+        let signature: [u8; Signature::BYTE_SIZE] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .context("malformed signature")?;
+        self.signer
+            .verify(&self.payload, &Signature::from_bytes(&signature))
+            .map_err(|_| anyhow!("signature does not match {}", self.signer))?;
+        schema::decode(&self.payload)?
+            .ok_or_else(|| anyhow!("signed value was written by a newer version"))
+    }
+}
 
 /// Shared state data synchronized between connected nodes.
 /// The doc holds all information about the current shared activity.
 /// This includes the ephemeral chat ticket.
 /// There can be multiple shared activities within this application,
 /// representing a person starting multiple games at once.
+#[derive(Clone)]
 pub struct SharedActivity {
     gossip: GossipNode,
     activity: Doc<DocsRPCConnector>,
     #[allow(unused)]
     ticket: DocTicket,
     author_id: AuthorId,
+    read_only: bool,
+    /// Seals the room's chat content, rooms created before keys existed have none until
+    /// their creator opens them again, see [`sealing`]
+    key: Option<RoomKey>,
+    /// Node whose signed entries decide admission, pinned by the ticket we joined with
+    host: Option<NodeId>,
 }
 
 impl Deref for SharedActivity {
@@ -57,28 +144,110 @@ impl Deref for SharedActivity {
 
 impl SharedActivity {
     /// Begin or join a new shared activity session.
-    pub async fn new(ticket: Option<DocTicket>, gossip: GossipNode) -> anyhow::Result<Self> {
+    ///
+    /// A new room gets a fresh key and us as its host, a joined one those its ticket carries.
+    pub async fn new(ticket: Option<RoomTicket>, gossip: GossipNode) -> anyhow::Result<Self> {
         let author = gossip.docs.authors().create().await?;
-        let activity: Doc<DocsRPCConnector> = match ticket {
-            None => gossip.docs.create().await?,
-            Some(ticket) => gossip.docs.import(ticket).await?,
+        let read_only = ticket
+            .as_ref()
+            .is_some_and(|ticket| matches!(ticket.doc.capability.kind(), CapabilityKind::Read));
+        let (activity, key, host): (Doc<DocsRPCConnector>, _, _) = match ticket {
+            None => (
+                gossip.docs.create().await?,
+                Some(RoomKey::generate()),
+                Some(gossip.node_id()),
+            ),
+            Some(RoomTicket {
+                doc: ticket,
+                key,
+                host,
+            }) => {
+                // set the download policy before the first sync, so attachments stay lazy
+                let doc = gossip.docs.import_namespace(ticket.capability).await?;
+                doc.set_download_policy(attachment::download_policy())
+                    .await?;
+                doc.start_sync(ticket.nodes).await?;
+                (doc, key, host)
+            }
         };
-        let ticket = activity.share(ShareMode::Write, Default::default()).await?;
+        let share_mode = match read_only {
+            true => ShareMode::Read,
+            false => ShareMode::Write,
+        };
+        let ticket = activity
+            .share(share_mode, gossip.ticket_addr_options())
+            .await?;
 
         Ok(Self {
             gossip,
             activity,
             ticket,
             author_id: author,
+            read_only,
+            key,
+            host,
         })
     }
+    /// Open a stored room without joining it, to read what was synced while we were in it.
+    ///
+    /// The room is read only and not synced, returning `None` if it isn't stored.
+    pub async fn open_stored(
+        id: NamespaceId,
+        gossip: GossipNode,
+        credentials: &RoomCredentials,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(activity) = gossip.docs.open(id).await? else {
+            return Ok(None);
+        };
+        let author = gossip.docs.authors().default().await?;
+        Ok(Some(Self {
+            gossip,
+            activity,
+            ticket: DocTicket::new(Capability::Read(id), Vec::new()),
+            author_id: author,
+            read_only: true,
+            key: credentials.key.clone(),
+            host: credentials.host,
+        }))
+    }
     /// Get the stringified ticket information to share with others.
     pub async fn ticket(&self) -> anyhow::Result<String> {
+        let share_mode = match self.read_only {
+            true => ShareMode::Read,
+            false => ShareMode::Write,
+        };
         let ticket = self
             .activity
-            .share(ShareMode::Write, Default::default())
+            .share(share_mode, self.gossip.ticket_addr_options())
             .await?;
-        Ok(ticket.to_string())
+        Ok(self.room_ticket(ticket).to_string())
+    }
+    /// Get a read only ticket, for others to watch without taking part.
+    pub async fn spectator_ticket(&self) -> anyhow::Result<String> {
+        let ticket = self
+            .activity
+            .share(ShareMode::Read, self.gossip.ticket_addr_options())
+            .await?;
+        Ok(self.room_ticket(ticket).to_string())
+    }
+    fn room_ticket(&self, doc: DocTicket) -> RoomTicket {
+        RoomTicket {
+            doc,
+            key: self.key.clone(),
+            host: self.host,
+        }
+    }
+    /// Return what we need to keep to read the room once we leave it.
+    pub fn credentials(&self) -> RoomCredentials {
+        RoomCredentials {
+            key: self.key.clone(),
+            host: self.host,
+            password: None,
+        }
+    }
+    /// Check whether we joined with a read only ticket, so can't write to the document.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
     /// Return the ID of this activity
     pub fn id(&self) -> NamespaceId {
@@ -91,6 +260,20 @@ impl SharedActivity {
         self.activity.subscribe().await
     }
 
+    /// Return the node id of this participant
+    pub fn node_id(&self) -> NodeId {
+        self.gossip.node_id()
+    }
+
+    /// Helper function to list the authors this node wrote with, in any session
+    pub(self) async fn our_authors(&self) -> anyhow::Result<HashSet<AuthorId>> {
+        let mut ours = HashSet::new();
+        let mut authors = self.gossip.docs.authors().list().await?;
+        while let Some(author) = authors.next().await {
+            ours.insert(author?);
+        }
+        Ok(ours)
+    }
     /// Helper function to write to the document
     pub(self) async fn write(
         &self,
@@ -101,11 +284,88 @@ impl SharedActivity {
             .set_bytes(self.author_id, key.into(), value.into())
             .await
     }
+    /// Helper function to write a value in the versioned schema
+    pub(self) async fn write_value<T: DocValue + Serialize>(
+        &self,
+        key: impl Into<Bytes>,
+        value: &T,
+    ) -> anyhow::Result<Hash> {
+        self.write(key, schema::encode(value)?).await
+    }
+    /// Helper function to write a value signed by this node
+    pub(self) async fn write_signed<T: DocValue + Serialize>(
+        &self,
+        key: impl Into<Bytes>,
+        value: &T,
+    ) -> anyhow::Result<Hash> {
+        let signed = Signed::sign(value, &self.gossip)?;
+        self.write_value(key, &signed).await
+    }
     /// Helper function to read one entry from the document
     pub(self) async fn read_unique(&self, key: impl Into<Bytes>) -> anyhow::Result<Option<Entry>> {
         let query = Query::key_exact(key.into());
         self.activity.get_one(query).await
     }
+    /// Helper function to read the most recent entry for a key written by any admitted author
+    pub(self) async fn read_latest(
+        &self,
+        admission: &Admission,
+        key: impl Into<Bytes>,
+    ) -> anyhow::Result<Option<Entry>> {
+        let mut entries = self.activity.get_many(Query::key_exact(key.into())).await?;
+        let mut latest: Option<Entry> = None;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if admission.honours(&entry.author())
+                && latest
+                    .as_ref()
+                    .is_none_or(|latest| entry.timestamp() > latest.timestamp())
+            {
+                latest = Some(entry);
+            }
+        }
+        Ok(latest)
+    }
+    /// Helper function to read and decode the most recent value for a key
+    pub(self) async fn read_value<T: DocValue>(
+        &self,
+        admission: &Admission,
+        key: impl Into<Bytes>,
+    ) -> anyhow::Result<Option<T>> {
+        match self.read_latest(admission, key).await? {
+            None => Ok(None),
+            Some(entry) => self.decode_entry(&entry).await,
+        }
+    }
+    /// Helper function to decode an entry's value, failing if a newer version wrote it
+    pub(self) async fn decode_entry<T: DocValue>(
+        &self,
+        entry: &Entry,
+    ) -> anyhow::Result<Option<T>> {
+        let bytes = self.read_bytes(entry.content_hash()).await?;
+        match schema::decode(&bytes)? {
+            Some(value) => Ok(Some(value)),
+            None => Err(app_error!(
+                UpdateRequired,
+                "{} was written by a newer version of the app, update to read it",
+                String::from_utf8_lossy(entry.key())
+            )),
+        }
+    }
+    /// Helper function to seal chat content with the room's key, if it has one
+    pub(self) fn seal_content(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        match &self.key {
+            Some(key) => key.seal(data),
+            None => Ok(data),
+        }
+    }
+    /// Helper function to open chat content sealed with [`SharedActivity::seal_content`]
+    pub(self) fn open_content(&self, data: Bytes) -> anyhow::Result<Bytes> {
+        match &self.key {
+            Some(key) => Ok(key.open(data.to_vec())?.into()),
+            None => Ok(data),
+        }
+    }
     /// Helper function to get the content bytes associated with an entry
     pub async fn read_bytes(&self, hash: Hash) -> anyhow::Result<Bytes> {
         self.gossip.blobs.read_to_bytes(hash).await
@@ -0,0 +1,714 @@
+//! Optional admission of joiners by the room's host.
+//!
+//! The host is the node which created the room, pinned by the tickets it hands out, see
+//! [`super::ticket`]. Only [`AdmissionSettings`] it signed count, so rooms joined with a
+//! ticket from before hosts were pinned are open. Each session binds its doc author to
+//! its node with a signed [`AuthorBinding`], so entries can be traced back to nodes. While
+//! approval is required, only entries from the host and from nodes it approved are
+//! honoured. Sanctions from [`super::moderation`] are folded in, so banned nodes and
+//! kicked sessions are not.
+//!
+//! With a room password, the host answers each join request with an
+//! [`AdmissionChallenge`] carrying a fresh nonce. The joiner proves the password with a
+//! hash of the nonce and the password, keyed with a secret only it and the host can
+//! derive from their node keys, so other members reading the doc can't guess the
+//! password offline, and an old proof can't be replayed.
+
+use crate::{
+    error::app_error,
+    gossip::doc::{
+        moderation::Moderation,
+        peers::{PeerInfo, PeerStatus},
+        schema, SharedActivity, Signed, ADMISSION_AUTHORS_PREFIX, ADMISSION_CHALLENGES_PREFIX,
+        ADMISSION_DECISIONS_PREFIX, ADMISSION_PREFIX, ADMISSION_REQUESTS_PREFIX,
+        ADMISSION_RESPONSES_PREFIX, ADMISSION_SETTINGS_KEY, MODERATION_PREFIX,
+    },
+    utils::get_timestamp,
+};
+use iroh::NodeId;
+use iroh_docs::{store::Query, AuthorId, Entry, NamespaceId};
+use n0_future::StreamExt as _;
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+/// Context for deriving room password keys, bump if the proof changes.
+const PASSWORD_CONTEXT: &str = "survival room password v2";
+const NONCE_LEN: usize = 16;
+
+/// How the host admits new nodes into the room.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionSettings {
+    pub host: NodeId,
+    /// Whether joiners wait for the host's approval before their entries count
+    pub approval_required: bool,
+    /// Whether joiners proving the room password are approved automatically
+    pub has_password: bool,
+}
+
+/// Claim by a node that a doc author writes on its behalf.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(super) struct AuthorBinding {
+    author: AuthorId,
+    node_id: NodeId,
+}
+
+/// A node asking the host to be let into the room.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinRequest {
+    pub node_id: NodeId,
+    pub nickname: String,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// A join request as written before password challenges, up to version 1 of its encoding.
+#[derive(Deserialize)]
+pub(super) struct JoinRequestV1 {
+    node_id: NodeId,
+    nickname: String,
+    /// a static proof of the password, no longer accepted
+    _proof: Option<String>,
+    timestamp: u64,
+}
+
+impl From<JoinRequestV1> for JoinRequest {
+    fn from(request: JoinRequestV1) -> Self {
+        Self {
+            node_id: request.node_id,
+            nickname: request.nickname,
+            timestamp: request.timestamp,
+        }
+    }
+}
+
+/// The host asking a joiner to prove the room password.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(super) struct AdmissionChallenge {
+    node_id: NodeId,
+    /// hex encoded, picked afresh for every challenge
+    nonce: String,
+    /// microseconds since EPOCH
+    timestamp: u64,
+}
+
+/// A joiner's answer to the host's latest challenge.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(super) struct ChallengeResponse {
+    node_id: NodeId,
+    /// hex encoded nonce of the challenge answered
+    nonce: String,
+    /// hex encoded, see [`password_proof`]
+    proof: String,
+}
+/// The host's verdict on a node.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionDecision {
+    pub node_id: NodeId,
+    pub approved: bool,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// Where a node stands with the room's host.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionStatus {
+    /// Anyone with a ticket is let in
+    Open,
+    Host,
+    Approved,
+    Pending,
+    Rejected,
+    /// Banned by the host or a moderator, overrides everything else
+    Banned,
+}
+
+/// Admission as seen by one particular node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionView {
+    pub settings: Option<AdmissionSettings>,
+    pub my_status: AdmissionStatus,
+    /// Requests from nodes the host has not decided on yet
+    pub pending: Vec<JoinRequest>,
+}
+
+/// Proof that a node knows the room password, answering the challenge with the given nonce.
+///
+/// Keyed with the secret the joiner and the host share, so only they can check it.
+fn password_proof(
+    room: &NamespaceId,
+    password: &str,
+    nonce: &str,
+    shared_secret: &[u8; 32],
+) -> blake3::Hash {
+    let key = blake3::derive_key(PASSWORD_CONTEXT, shared_secret);
+    let message = [
+        room.as_bytes().as_slice(),
+        nonce.as_bytes(),
+        password.as_bytes(),
+    ]
+    .concat();
+    blake3::keyed_hash(&key, &message)
+}
+
+fn author_key(author: &AuthorId) -> Vec<u8> {
+    [ADMISSION_AUTHORS_PREFIX, author.as_bytes()].concat()
+}
+
+fn request_key(node_id: &NodeId) -> Vec<u8> {
+    [ADMISSION_REQUESTS_PREFIX, node_id.as_bytes()].concat()
+}
+
+fn decision_key(node_id: &NodeId) -> Vec<u8> {
+    [ADMISSION_DECISIONS_PREFIX, node_id.as_bytes()].concat()
+}
+
+fn challenge_key(node_id: &NodeId) -> Vec<u8> {
+    [ADMISSION_CHALLENGES_PREFIX, node_id.as_bytes()].concat()
+}
+
+fn response_key(node_id: &NodeId) -> Vec<u8> {
+    [ADMISSION_RESPONSES_PREFIX, node_id.as_bytes()].concat()
+}
+
+/// Everything the room's admission entries say, verified.
+#[derive(Debug, Default)]
+pub struct Admission {
+    settings: Option<AdmissionSettings>,
+    /// Node each author writes for, with when it bound itself in microseconds since EPOCH
+    authors: HashMap<AuthorId, (NodeId, u64)>,
+    decisions: HashMap<NodeId, AdmissionDecision>,
+    requests: HashMap<NodeId, JoinRequest>,
+    /// The host's latest challenge to each joiner
+    challenges: HashMap<NodeId, AdmissionChallenge>,
+    /// Each joiner's latest answer, with when it was written in microseconds since EPOCH
+    responses: HashMap<NodeId, (ChallengeResponse, u64)>,
+    moderation: Moderation,
+}
+
+impl Admission {
+    /// Build from the room's admission and moderation entries, dropping any which fail
+    /// verification or aren't signed by the pinned host where they need to be.
+    fn from_entries(entries: Vec<(Entry, Signed)>, host: Option<NodeId>) -> Self {
+        // the host's latest settings
+        let settings = entries
+            .iter()
+            .filter(|(entry, signed)| {
+                entry.key() == ADMISSION_SETTINGS_KEY && Some(signed.signer) == host
+            })
+            .filter_map(|(entry, signed)| {
+                let settings: AdmissionSettings = signed.verify().ok()?;
+                (settings.host == signed.signer).then_some((entry.timestamp(), settings))
+            })
+            .max_by_key(|(timestamp, _)| *timestamp)
+            .map(|(_, settings)| settings);
+        let mut admission = Self {
+            settings,
+            ..Self::default()
+        };
+
+        for (entry, signed) in &entries {
+            let key = entry.key();
+            if key.starts_with(ADMISSION_AUTHORS_PREFIX) {
+                // only the author itself can vouch for its binding
+                match signed.verify::<AuthorBinding>() {
+                    Ok(binding)
+                        if binding.node_id == signed.signer && binding.author == entry.author() =>
+                    {
+                        admission
+                            .authors
+                            .insert(binding.author, (binding.node_id, entry.timestamp()));
+                    }
+                    _ => debug!("Ignoring invalid author binding"),
+                }
+            } else if key.starts_with(ADMISSION_REQUESTS_PREFIX) {
+                match signed.verify::<JoinRequest>() {
+                    Ok(request) if request.node_id == signed.signer => {
+                        admission.requests.insert(request.node_id, request);
+                    }
+                    _ => debug!("Ignoring invalid join request"),
+                }
+            } else if key.starts_with(ADMISSION_DECISIONS_PREFIX) {
+                match signed.verify::<AdmissionDecision>() {
+                    Ok(decision) if Some(signed.signer) == host => {
+                        let newer = admission
+                            .decisions
+                            .get(&decision.node_id)
+                            .is_none_or(|known| decision.timestamp > known.timestamp);
+                        if newer {
+                            admission.decisions.insert(decision.node_id, decision);
+                        }
+                    }
+                    _ => debug!("Ignoring admission decision not signed by the host"),
+                }
+            } else if key.starts_with(ADMISSION_CHALLENGES_PREFIX) {
+                match signed.verify::<AdmissionChallenge>() {
+                    Ok(challenge) if Some(signed.signer) == host => {
+                        let newer = admission
+                            .challenges
+                            .get(&challenge.node_id)
+                            .is_none_or(|known| challenge.timestamp > known.timestamp);
+                        if newer {
+                            admission.challenges.insert(challenge.node_id, challenge);
+                        }
+                    }
+                    _ => debug!("Ignoring admission challenge not signed by the host"),
+                }
+            } else if key.starts_with(ADMISSION_RESPONSES_PREFIX) {
+                match signed.verify::<ChallengeResponse>() {
+                    Ok(response) if response.node_id == signed.signer => {
+                        let newer = admission
+                            .responses
+                            .get(&response.node_id)
+                            .is_none_or(|(_, known)| entry.timestamp() > *known);
+                        if newer {
+                            admission
+                                .responses
+                                .insert(response.node_id, (response, entry.timestamp()));
+                        }
+                    }
+                    _ => debug!("Ignoring invalid challenge response"),
+                }
+            }
+        }
+        admission.moderation = Moderation::from_entries(&entries, host);
+        admission
+    }
+
+    pub fn host(&self) -> Option<NodeId> {
+        self.settings.as_ref().map(|settings| settings.host)
+    }
+
+    pub fn settings(&self) -> Option<&AdmissionSettings> {
+        self.settings.as_ref()
+    }
+
+    pub fn moderation(&self) -> &Moderation {
+        &self.moderation
+    }
+
+    /// Whether only entries from bound authors count, once approval is on or anyone was
+    /// kicked or banned.
+    fn restricted(&self) -> bool {
+        self.settings
+            .as_ref()
+            .is_some_and(|settings| settings.approval_required)
+            || self.moderation.has_sanctions()
+    }
+
+    /// Whether the author's session started before its node was last kicked.
+    fn kicked(&self, node_id: &NodeId, bound_at: u64) -> bool {
+        self.moderation
+            .kicked_at(node_id)
+            .is_some_and(|kicked_at| bound_at <= kicked_at)
+    }
+
+    /// The node an author writes for, if it has bound itself.
+    pub fn node_of(&self, author: &AuthorId) -> Option<NodeId> {
+        self.authors.get(author).map(|(node_id, _)| *node_id)
+    }
+
+    pub fn status(&self, node_id: &NodeId) -> AdmissionStatus {
+        if self.moderation.is_banned(node_id) {
+            return AdmissionStatus::Banned;
+        }
+        let Some(settings) = &self.settings else {
+            return AdmissionStatus::Open;
+        };
+        if &settings.host == node_id {
+            return AdmissionStatus::Host;
+        }
+        if !settings.approval_required {
+            return AdmissionStatus::Open;
+        }
+        // a kick also undoes an earlier approval
+        let kicked_at = self.moderation.kicked_at(node_id).unwrap_or(0);
+        match self.decisions.get(node_id) {
+            Some(decision) if decision.approved && decision.timestamp > kicked_at => {
+                AdmissionStatus::Approved
+            }
+            Some(decision) if decision.approved => AdmissionStatus::Pending,
+            Some(_) => AdmissionStatus::Rejected,
+            None => AdmissionStatus::Pending,
+        }
+    }
+
+    /// Whether entries from this node count towards the room's state.
+    pub fn honours_node(&self, node_id: &NodeId) -> bool {
+        matches!(
+            self.status(node_id),
+            AdmissionStatus::Open | AdmissionStatus::Host | AdmissionStatus::Approved
+        )
+    }
+
+    /// Whether entries from this author count towards the room's state.
+    pub fn honours(&self, author: &AuthorId) -> bool {
+        match self.authors.get(author) {
+            Some((node_id, bound_at)) => {
+                self.honours_node(node_id) && !self.kicked(node_id, *bound_at)
+            }
+            None => !self.restricted(),
+        }
+    }
+
+    /// Whether chat messages from this author are shown, which muted nodes' are not.
+    pub fn can_chat(&self, author: &AuthorId) -> bool {
+        self.honours(author)
+            && self
+                .node_of(author)
+                .is_none_or(|node_id| !self.moderation.is_muted(&node_id))
+    }
+
+    /// Show a peer's entry in the roster, as pending if the host hasn't approved it.
+    ///
+    /// Entries from authors not bound to the peer they describe are dropped, as are
+    /// rejected and banned peers and sessions ended by a kick.
+    pub fn review_peer(&self, mut peer: PeerInfo, author: &AuthorId) -> Option<PeerInfo> {
+        if !self.restricted() {
+            return Some(peer);
+        }
+        let live_session = self.authors.get(author).is_some_and(|(node_id, bound_at)| {
+            *node_id == peer.id && !self.kicked(node_id, *bound_at)
+        });
+        if !live_session {
+            return None;
+        }
+        match self.status(&peer.id) {
+            AdmissionStatus::Rejected | AdmissionStatus::Banned => None,
+            AdmissionStatus::Pending => {
+                peer.status = PeerStatus::Pending;
+                Some(peer)
+            }
+            _ => Some(peer),
+        }
+    }
+
+    /// Project admission for display by the given node.
+    pub fn view_for(&self, viewer: &NodeId) -> AdmissionView {
+        let mut pending: Vec<JoinRequest> = self
+            .requests
+            .values()
+            .filter(|request| self.status(&request.node_id) == AdmissionStatus::Pending)
+            .cloned()
+            .collect();
+        pending.sort_by_key(|request| request.timestamp);
+        AdmissionView {
+            settings: self.settings.clone(),
+            my_status: self.status(viewer),
+            pending,
+        }
+    }
+}
+
+impl SharedActivity {
+    /// Read and verify every admission and moderation entry in the room.
+    pub async fn admission(&self) -> anyhow::Result<Admission> {
+        let mut signed = Vec::new();
+        for prefix in [ADMISSION_PREFIX, MODERATION_PREFIX] {
+            let mut entries = self.activity.get_many(Query::key_prefix(prefix)).await?;
+            while let Some(entry) = entries.next().await {
+                let entry = entry?;
+                // one entry still syncing shouldn't hide the rest
+                let bytes = match self.read_bytes(entry.content_hash()).await {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        debug!("Skipping unreadable admission entry {e}");
+                        continue;
+                    }
+                };
+                match schema::decode::<Signed>(&bytes) {
+                    Ok(Some(value)) => signed.push((entry, value)),
+                    Ok(None) => debug!("Ignoring admission entry from a newer version"),
+                    Err(e) => debug!("Ignoring malformed admission entry {e}"),
+                }
+            }
+        }
+        Ok(Admission::from_entries(signed, self.host))
+    }
+    /// Vouch that this session's author writes on behalf of our node.
+    pub async fn bind_author(&self) -> anyhow::Result<()> {
+        let binding = AuthorBinding {
+            author: self.author_id,
+            node_id: self.node_id(),
+        };
+        self.write_signed(author_key(&self.author_id), &binding)
+            .await?;
+        Ok(())
+    }
+    /// Claim the room as its host, with anyone let in until approval is turned on.
+    pub async fn claim_host(&self) -> anyhow::Result<()> {
+        let settings = AdmissionSettings {
+            host: self.node_id(),
+            approval_required: false,
+            has_password: false,
+        };
+        self.write_signed(ADMISSION_SETTINGS_KEY, &settings).await?;
+        Ok(())
+    }
+    /// Ask the host to let us in, the room password is proved once it challenges us.
+    pub async fn request_admission(&self, nickname: &str) -> anyhow::Result<()> {
+        let node_id = self.node_id();
+        let request = JoinRequest {
+            node_id,
+            nickname: nickname.to_string(),
+            timestamp: get_timestamp(),
+        };
+        self.write_signed(request_key(&node_id), &request).await?;
+        Ok(())
+    }
+    /// Fail unless we host this room.
+    async fn require_host(&self) -> anyhow::Result<Admission> {
+        let admission = self.admission().await?;
+        if admission.host() != Some(self.node_id()) {
+            return Err(app_error!(
+                NotPermitted,
+                "Only the host can manage admission."
+            ));
+        }
+        Ok(admission)
+    }
+    /// Fail unless the room honours what we write, so we don't act on entries others ignore.
+    pub(super) fn require_admitted(&self, admission: &Admission) -> anyhow::Result<()> {
+        if !admission.honours(&self.author_id) {
+            return Err(app_error!(
+                NotPermitted,
+                "You haven't been admitted to this room."
+            ));
+        }
+        Ok(())
+    }
+    /// Change how joiners are admitted, host only.
+    ///
+    /// Turning approval on approves every peer already in the room.
+    pub async fn set_admission(
+        &self,
+        approval_required: bool,
+        has_password: bool,
+    ) -> anyhow::Result<AdmissionSettings> {
+        let admission = self.require_host().await?;
+        let was_required = admission
+            .settings()
+            .is_some_and(|settings| settings.approval_required);
+        if approval_required && !was_required {
+            let host = self.node_id();
+            for peer in self.get_all_peer_info().await? {
+                if peer.id != host && admission.status(&peer.id) != AdmissionStatus::Rejected {
+                    self.decide_admission(peer.id, true).await?;
+                }
+            }
+        }
+        let settings = AdmissionSettings {
+            host: self.node_id(),
+            approval_required,
+            has_password,
+        };
+        self.write_signed(ADMISSION_SETTINGS_KEY, &settings).await?;
+        Ok(settings)
+    }
+    /// Approve or reject a node, host only.
+    pub async fn decide_admission(&self, node_id: NodeId, approved: bool) -> anyhow::Result<()> {
+        self.require_host().await?;
+        info!("Admission of {node_id}: approved {approved}");
+        let decision = AdmissionDecision {
+            node_id,
+            approved,
+            timestamp: get_timestamp(),
+        };
+        self.write_signed(decision_key(&node_id), &decision).await?;
+        Ok(())
+    }
+    /// Challenge a pending joiner to prove the room password, host only.
+    pub async fn challenge_joiner(&self, node_id: NodeId) -> anyhow::Result<()> {
+        let admission = self.require_host().await?;
+        if admission.status(&node_id) != AdmissionStatus::Pending {
+            return Ok(());
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let challenge = AdmissionChallenge {
+            node_id,
+            nonce: hex::encode(nonce),
+            timestamp: get_timestamp(),
+        };
+        self.write_signed(challenge_key(&node_id), &challenge)
+            .await?;
+        Ok(())
+    }
+    /// Challenge every pending joiner not challenged since it asked, host only.
+    ///
+    /// Catches up on requests made while we were away or before a password was set.
+    pub async fn challenge_pending(&self) -> anyhow::Result<()> {
+        let admission = self.require_host().await?;
+        for request in admission.view_for(&self.node_id()).pending {
+            let challenged = admission
+                .challenges
+                .get(&request.node_id)
+                .is_some_and(|challenge| challenge.timestamp > request.timestamp);
+            if !challenged {
+                self.challenge_joiner(request.node_id).await?;
+            }
+        }
+        Ok(())
+    }
+    /// Answer the host's latest challenge to us with the room password, if not done yet.
+    pub async fn answer_challenge(&self, password: &str) -> anyhow::Result<()> {
+        let admission = self.admission().await?;
+        let node_id = self.node_id();
+        let (Some(host), Some(challenge)) = (admission.host(), admission.challenges.get(&node_id))
+        else {
+            return Ok(());
+        };
+        let answered = admission
+            .responses
+            .get(&node_id)
+            .is_some_and(|(response, _)| response.nonce == challenge.nonce);
+        if answered || admission.status(&node_id) != AdmissionStatus::Pending {
+            return Ok(());
+        }
+        let shared_secret = self.gossip.shared_secret(&host);
+        let proof = password_proof(&self.id(), password, &challenge.nonce, &shared_secret);
+        let response = ChallengeResponse {
+            node_id,
+            nonce: challenge.nonce.clone(),
+            proof: proof.to_hex().to_string(),
+        };
+        info!("Answering the host's password challenge");
+        self.write_signed(response_key(&node_id), &response).await?;
+        Ok(())
+    }
+    /// Approve a joiner whose answer to our latest challenge proves the room password,
+    /// host only.
+    ///
+    /// Joiners with a wrong proof are left for the host to decide.
+    pub async fn review_challenge_response(
+        &self,
+        node_id: NodeId,
+        password: &str,
+    ) -> anyhow::Result<()> {
+        let admission = self.require_host().await?;
+        if admission.status(&node_id) != AdmissionStatus::Pending {
+            return Ok(());
+        }
+        let (Some(challenge), Some((response, _))) = (
+            admission.challenges.get(&node_id),
+            admission.responses.get(&node_id),
+        ) else {
+            return Ok(());
+        };
+        if response.nonce != challenge.nonce {
+            debug!("Ignoring answer to an earlier challenge");
+            return Ok(());
+        }
+        let shared_secret = self.gossip.shared_secret(&node_id);
+        let expected = password_proof(&self.id(), password, &challenge.nonce, &shared_secret);
+        // comparing hashes takes constant time
+        match blake3::Hash::from_hex(&response.proof) {
+            Ok(proof) if proof == expected => self.decide_admission(node_id, true).await,
+            _ => {
+                info!("Wrong room password from {node_id}");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::{
+        doc::ticket::RoomTicket,
+        testing::{eventually, spawn_node},
+    };
+
+    #[tokio::test]
+    async fn joiners_prove_the_password_to_the_pinned_host() {
+        let (host_node, _host_dir) = spawn_node().await;
+        let host = SharedActivity::new(None, host_node).await.unwrap();
+        host.bind_author().await.unwrap();
+        host.claim_host().await.unwrap();
+        host.set_admission(true, true).await.unwrap();
+
+        let (joiner_node, _joiner_dir) = spawn_node().await;
+        let joiner_id = joiner_node.node_id();
+        let ticket: RoomTicket = host.ticket().await.unwrap().parse().unwrap();
+        let joiner = SharedActivity::new(Some(ticket), joiner_node)
+            .await
+            .unwrap();
+        joiner.bind_author().await.unwrap();
+        // claiming the room doesn't make the joiner its host
+        joiner.claim_host().await.unwrap();
+        joiner.request_admission("bob").await.unwrap();
+
+        eventually(|| async {
+            let admission = host.admission().await.unwrap();
+            admission.requests.contains_key(&joiner_id).then_some(())
+        })
+        .await;
+        let admission = host.admission().await.unwrap();
+        assert_eq!(admission.host(), Some(host.node_id()));
+        assert_eq!(admission.status(&joiner_id), AdmissionStatus::Pending);
+
+        // a wrong password leaves the joiner waiting
+        host.challenge_pending().await.unwrap();
+        let answered = |nonce: Option<String>| {
+            let nonce = nonce.clone();
+            let host = &host;
+            async move {
+                let admission = host.admission().await.unwrap();
+                let (response, _) = admission.responses.get(&joiner_id)?;
+                (Some(&response.nonce) != nonce.as_ref()).then(|| response.nonce.clone())
+            }
+        };
+        eventually(|| async {
+            let admission = joiner.admission().await.unwrap();
+            admission.challenges.get(&joiner_id).map(|_| ())
+        })
+        .await;
+        joiner.answer_challenge("wrong password").await.unwrap();
+        let first = eventually(|| answered(None)).await;
+        host.review_challenge_response(joiner_id, "right password")
+            .await
+            .unwrap();
+        let admission = host.admission().await.unwrap();
+        assert_eq!(admission.status(&joiner_id), AdmissionStatus::Pending);
+
+        // an answer to an earlier challenge is not accepted either
+        host.challenge_joiner(joiner_id).await.unwrap();
+        eventually(|| async {
+            let admission = joiner.admission().await.unwrap();
+            let challenge = admission.challenges.get(&joiner_id)?;
+            (challenge.nonce != first).then_some(())
+        })
+        .await;
+        joiner.answer_challenge("right password").await.unwrap();
+        eventually(|| answered(Some(first.clone()))).await;
+        host.review_challenge_response(joiner_id, "right password")
+            .await
+            .unwrap();
+        let admission = host.admission().await.unwrap();
+        assert_eq!(admission.status(&joiner_id), AdmissionStatus::Approved);
+    }
+
+    #[tokio::test]
+    async fn entries_still_syncing_are_skipped() {
+        let (node, _dir) = spawn_node().await;
+        let host = SharedActivity::new(None, node.clone()).await.unwrap();
+        host.bind_author().await.unwrap();
+        host.claim_host().await.unwrap();
+        // an entry whose content we haven't fetched yet
+        let missing = iroh_blobs::Hash::new(b"not downloaded");
+        host.activity
+            .set_hash(host.author_id, request_key(&node.node_id()), missing, 64)
+            .await
+            .unwrap();
+
+        let admission = host.admission().await.unwrap();
+        assert_eq!(admission.host(), Some(node.node_id()));
+        assert!(admission.requests.is_empty());
+    }
+}
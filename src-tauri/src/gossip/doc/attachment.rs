@@ -0,0 +1,172 @@
+//! Files shared in the chat, such as images, game replays and small documents.
+//!
+//! An attachment's content is stored as its own entry, keyed by its hash, which keeps
+//! the blob from being garbage collected while the room is stored. Rooms are opened with
+//! a download policy skipping these entries, so peers only download an attachment when
+//! it is first fetched, from the node which attached it or anyone else syncing the room.
+//! In rooms with a key the content is sealed, and the hash is that of the sealed blob.
+
+use crate::{
+    error::app_error,
+    gossip::doc::{sealing::SEAL_OVERHEAD, SharedActivity, ATTACHMENTS_PREFIX},
+};
+use bytes::Bytes;
+use iroh::{NodeAddr, NodeId};
+use iroh_blobs::{
+    rpc::client::blobs::{BlobStatus, DownloadMode, DownloadOptions},
+    util::SetTagOption,
+    BlobFormat, Hash,
+};
+use iroh_docs::store::{DownloadPolicy, FilterKind, Query};
+use n0_future::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Largest file which can be attached to a message, in bytes.
+pub const MAX_ATTACHMENT_SIZE: u64 = 8 * 1024 * 1024;
+/// Most files attached to one message.
+pub const MAX_ATTACHMENTS: usize = 4;
+
+/// A file attached to a chat message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub hash: Hash,
+    pub name: String,
+    /// MIME type given by the sender, such as `image/png`
+    pub mime: String,
+    /// bytes, before sealing
+    pub size: u64,
+}
+
+/// Key of the entry holding an attachment's content.
+pub fn attachment_key(hash: &Hash) -> Vec<u8> {
+    [ATTACHMENTS_PREFIX, hash.as_bytes()].concat()
+}
+
+/// Download everything in the room but attachments, which are fetched on demand.
+pub fn download_policy() -> DownloadPolicy {
+    DownloadPolicy::EverythingExcept(vec![FilterKind::Prefix(ATTACHMENTS_PREFIX.into())])
+}
+
+impl SharedActivity {
+    /// Store a file in the room, ready to be sent with a message.
+    pub async fn attach(
+        &self,
+        name: &str,
+        mime: &str,
+        data: Vec<u8>,
+    ) -> anyhow::Result<Attachment> {
+        let size = data.len() as u64;
+        if size > MAX_ATTACHMENT_SIZE {
+            return Err(app_error!(
+                InvalidInput,
+                "Attachments can't be larger than {} MiB.",
+                MAX_ATTACHMENT_SIZE / 1024 / 1024
+            ));
+        }
+        if name.trim().is_empty() {
+            return Err(app_error!(InvalidInput, "Attachments need a file name."));
+        }
+        let data = self.seal_content(data)?;
+        let hash = Hash::new(&data);
+        self.write(attachment_key(&hash), data).await?;
+        info!("Attached {name}, {size} bytes");
+        Ok(Attachment {
+            hash,
+            name: name.trim().to_string(),
+            mime: mime.to_string(),
+            size,
+        })
+    }
+    /// Check attachments were stored by this session before sending them.
+    pub(super) async fn check_attachments(&self, attachments: &[Attachment]) -> anyhow::Result<()> {
+        if attachments.len() > MAX_ATTACHMENTS {
+            return Err(app_error!(
+                InvalidInput,
+                "A message can carry at most {MAX_ATTACHMENTS} attachments."
+            ));
+        }
+        for attachment in attachments {
+            let stored = self
+                .activity
+                .get_exact(self.author_id, attachment_key(&attachment.hash), false)
+                .await?;
+            if stored.is_none_or(|entry| entry.content_len() != self.stored_len(attachment.size)) {
+                return Err(app_error!(
+                    NotFound,
+                    "Attach {} before sending it.",
+                    attachment.name
+                ));
+            }
+        }
+        Ok(())
+    }
+    /// Return an attachment's content, downloading it first if we don't have it yet.
+    pub async fn fetch_attachment(&self, hash: Hash) -> anyhow::Result<Bytes> {
+        let admission = self.admission().await?;
+        let mut entries = self
+            .activity
+            .get_many(Query::key_exact(attachment_key(&hash)))
+            .await?;
+        let mut found = None;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if admission.honours(&entry.author()) {
+                found = Some(entry);
+                break;
+            }
+        }
+        let Some(entry) = found else {
+            return Err(app_error!(NotFound, "No attachment {hash} in this room."));
+        };
+        if entry.content_len() > self.stored_len(MAX_ATTACHMENT_SIZE) {
+            return Err(app_error!(
+                InvalidInput,
+                "Attachment {hash} is larger than {} MiB.",
+                MAX_ATTACHMENT_SIZE / 1024 / 1024
+            ));
+        }
+        if !matches!(
+            self.gossip.blobs.status(hash).await?,
+            BlobStatus::Complete { .. }
+        ) {
+            // ask whoever attached it first, then anyone else we sync the room with
+            let mut nodes: Vec<NodeId> = admission.node_of(&entry.author()).into_iter().collect();
+            for peer in self.activity.get_sync_peers().await?.unwrap_or_default() {
+                nodes.extend(NodeId::from_bytes(&peer).ok());
+            }
+            nodes.retain(|node| *node != self.node_id());
+            info!("Downloading attachment {hash} from {} nodes", nodes.len());
+            let tag = format!("attachment-{hash}");
+            let options = DownloadOptions {
+                format: BlobFormat::Raw,
+                nodes: nodes.into_iter().map(NodeAddr::from).collect(),
+                tag: SetTagOption::Named(tag.as_str().into()),
+                mode: DownloadMode::Queued,
+            };
+            self.gossip
+                .blobs
+                .download_with_opts(hash, options)
+                .await?
+                .finish()
+                .await
+                .map_err(|e| {
+                    app_error!(
+                        NetworkUnavailable,
+                        "Could not download attachment {hash}: {e}"
+                    )
+                })?;
+            // the room's entry keeps the blob from being collected from now on
+            self.gossip.blobs.tags().delete(tag).await?;
+        }
+        self.open_content(self.read_bytes(hash).await?)
+    }
+    /// How many bytes content of the given size takes once stored in this room.
+    fn stored_len(&self, size: u64) -> u64 {
+        match self.key {
+            Some(_) => size + SEAL_OVERHEAD,
+            None => size,
+        }
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
-    gossip::doc::{SharedActivity, MESSAGES_PREFIX},
+    gossip::doc::{
+        admission::Admission, attachment::Attachment, schema, SharedActivity, MESSAGES_PREFIX,
+    },
     utils::get_timestamp,
 };
 use iroh::NodeId;
+use iroh_blobs::Hash;
 use iroh_docs::{store::Query, AuthorId};
 use n0_future::StreamExt as _;
 use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, str::FromStr as _};
+use tracing::{debug, info, warn};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ChatMessage {
@@ -17,6 +22,73 @@ pub struct ChatMessage {
     pub content: String,
     /// milliseconds since EPOCH
     pub timestamp: u64,
+    /// Files sent with the message, downloaded when opened
+    pub attachments: Vec<Attachment>,
+}
+
+/// A message as written before attachments, up to version 1 of its encoding.
+#[derive(Deserialize)]
+pub(super) struct ChatMessageV1 {
+    sender: NodeId,
+    nickname: String,
+    content: String,
+    timestamp: u64,
+}
+
+impl From<ChatMessageV1> for ChatMessage {
+    fn from(message: ChatMessageV1) -> Self {
+        Self {
+            sender: message.sender,
+            nickname: message.nickname,
+            content: message.content,
+            timestamp: message.timestamp,
+            attachments: Vec::new(),
+        }
+    }
+}
+
+/// Someone a message calls out with an `@`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mention {
+    /// `@` followed by a node id
+    Node(NodeId),
+    /// `@` followed by a nickname, up to the first space
+    Nickname(String),
+}
+
+impl ChatMessage {
+    /// Parse the mentions in the message, in the order they appear.
+    pub fn mentions(&self) -> Vec<Mention> {
+        self.content
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('@'))
+            // leave out punctuation following the name, as in "@alice, your move", but
+            // not the likes of `_` or `-` which nicknames end with
+            .map(|name| name.trim_end_matches(is_trailing_punctuation))
+            .filter(|name| !name.is_empty())
+            .map(|name| match NodeId::from_str(name) {
+                Ok(node_id) => Mention::Node(node_id),
+                Err(_) => Mention::Nickname(name.to_string()),
+            })
+            .collect()
+    }
+
+    /// Whether the message mentions a node, by id or by nickname ignoring case.
+    pub fn mentions_node(&self, node_id: &NodeId, nickname: &str) -> bool {
+        // nicknames with spaces are matched by their first word
+        let nickname = nickname.split_whitespace().next().unwrap_or_default();
+        self.mentions().iter().any(|mention| match mention {
+            Mention::Node(mentioned) => mentioned == node_id,
+            Mention::Nickname(name) => {
+                !nickname.is_empty() && name.to_lowercase() == nickname.to_lowercase()
+            }
+        })
+    }
+}
+
+/// Whether a character ending a mentioned name belongs to the sentence around it.
+fn is_trailing_punctuation(c: char) -> bool {
+    matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | ')' | '\'' | '"')
 }
 
 // Helper to create unique, sortable message keys
@@ -29,8 +101,14 @@ fn message_key(timestamp_millis: u64, author_id: &AuthorId) -> Vec<u8> {
 }
 
 impl SharedActivity {
-    /// Add a message to the shared log.
-    pub async fn send_message(&self, name: &str, message_content: &str) -> anyhow::Result<()> {
+    /// Add a message to the shared log, with files stored by [`SharedActivity::attach`].
+    pub async fn send_message(
+        &self,
+        name: &str,
+        message_content: &str,
+        attachments: Vec<Attachment>,
+    ) -> anyhow::Result<()> {
+        self.check_attachments(&attachments).await?;
         let timestamp = get_timestamp(); // in millis
         let key = message_key(timestamp, &self.author_id);
         let chat_message = ChatMessage {
@@ -38,29 +116,82 @@ impl SharedActivity {
             sender: self.gossip.node_id(),
             nickname: name.to_string(),
             content: message_content.to_string(),
+            attachments,
         };
-        self.activity
-            .set_bytes(self.author_id, key, postcard::to_stdvec(&chat_message)?)
-            .await?;
+        let sealed = self.seal_content(schema::encode(&chat_message)?)?;
+        self.write(key, sealed).await?;
         Ok(())
     }
 
     /// Get all messages from the shared log, ordered by time.
     /// Returns a vector of (timestamp_millis, author_id_prefix, message_string).
     pub async fn get_messages(&self) -> anyhow::Result<Vec<ChatMessage>> {
+        self.read_messages(&self.admission().await?).await
+    }
+
+    /// Get the messages of the authors the given admission lets chat, ordered by time.
+    pub(crate) async fn read_messages(
+        &self,
+        admission: &Admission,
+    ) -> anyhow::Result<Vec<ChatMessage>> {
         let query = Query::key_prefix(MESSAGES_PREFIX);
         let mut entries = self.activity.get_many(query).await?;
         let mut messages: Vec<ChatMessage> = Vec::new();
 
         while let Some(Ok(entry)) = entries.next().await {
-            let bytes = self.read_bytes(entry.content_hash()).await?;
-            let message: ChatMessage = postcard::from_bytes(&bytes)?;
-            messages.push(message);
+            if !admission.can_chat(&entry.author()) {
+                continue;
+            }
+            match self.read_message(entry.content_hash()).await {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => debug!("Skipping message from a newer version"),
+                Err(e) => warn!("Skipping unreadable message: {e}"),
+            }
         }
         messages.sort_by_key(|m| m.timestamp);
         Ok(messages)
     }
 
+    /// Read a message, opening it with the room's key if it has one.
+    ///
+    /// Returns `None` if a newer version wrote it.
+    pub async fn read_message(&self, hash: Hash) -> anyhow::Result<Option<ChatMessage>> {
+        let bytes = self.read_bytes(hash).await?;
+        match self.open_content(bytes.clone()) {
+            Ok(bytes) => schema::decode(&bytes),
+            // written before the room had a key, until its author seals it
+            Err(e) => schema::decode(&bytes).map_err(|_| e),
+        }
+    }
+
+    /// Trim the room's chat to its latest `cap` messages, returning how many were deleted.
+    ///
+    /// Only the author of an entry can delete it, so this removes the older messages
+    /// written from this node, in this session or earlier ones. Messages from others are
+    /// left to their own clients' policies.
+    pub async fn prune_messages(&self, cap: usize) -> anyhow::Result<usize> {
+        let ours = self.our_authors().await?;
+        let query = Query::key_prefix(MESSAGES_PREFIX);
+        let mut entries = self.activity.get_many(query).await?;
+        let mut messages = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            messages.push((entry.timestamp(), entry.author(), entry.key().to_vec()));
+        }
+        messages.sort_by_key(|(timestamp, ..)| Reverse(*timestamp));
+
+        let mut pruned = 0;
+        for (_, author, key) in messages.into_iter().skip(cap) {
+            if ours.contains(&author) {
+                pruned += self.activity.del(author, key).await?;
+            }
+        }
+        if pruned > 0 {
+            info!("Pruned {pruned} chat messages beyond the last {cap}");
+        }
+        Ok(pruned)
+    }
+
     #[allow(dead_code)]
     fn parse_message_key(key: &[u8]) -> Option<(u64, Vec<u8>)> {
         if key.starts_with(MESSAGES_PREFIX) && key.len() > MESSAGES_PREFIX.len() + 8 + 1 {
@@ -74,3 +205,45 @@ impl SharedActivity {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn message(content: &str) -> ChatMessage {
+        ChatMessage {
+            sender: SecretKey::generate(rand::rngs::OsRng).public(),
+            nickname: "alice".to_string(),
+            content: content.to_string(),
+            timestamp: 0,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn mentions_leave_out_sentence_punctuation() {
+        let node_id = SecretKey::generate(rand::rngs::OsRng).public();
+        let content = format!("@Bob, @carol_ and @dave-? @ hi @{node_id}.");
+        assert_eq!(
+            message(&content).mentions(),
+            vec![
+                Mention::Nickname("Bob".to_string()),
+                Mention::Nickname("carol_".to_string()),
+                Mention::Nickname("dave-".to_string()),
+                Mention::Node(node_id),
+            ]
+        );
+    }
+
+    #[test]
+    fn mentions_match_nickname_or_node() {
+        let node_id = SecretKey::generate(rand::rngs::OsRng).public();
+        assert!(message("your move @BOB_!").mentions_node(&node_id, "bob_"));
+        assert!(!message("your move @bob").mentions_node(&node_id, "bob_"));
+        assert!(message("@bob: gg").mentions_node(&node_id, "Bob the Builder"));
+        assert!(message(&format!("gg @{node_id}")).mentions_node(&node_id, "bob"));
+        assert!(!message("mail bob@example.com").mentions_node(&node_id, "bob"));
+        assert!(!message("@ anyone").mentions_node(&node_id, ""));
+    }
+}
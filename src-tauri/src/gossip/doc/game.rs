@@ -0,0 +1,305 @@
+use crate::{
+    error::app_error,
+    game::{board::Piece, legacy::LegacyGame, Game, Move, PlayerType, Seat},
+    gossip::doc::{
+        admission::Admission, SharedActivity, DRAW_OFFER_KEY, DRAW_RESPONSE_KEY, GAME_STATE_KEY,
+        LEGACY_GAME_STATE_KEY, TAKEBACK_REQUEST_KEY, TAKEBACK_RESPONSE_KEY,
+    },
+    utils::get_timestamp,
+};
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// A request from a player to undo their last move.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TakebackRequest {
+    /// Node asking for the takeback
+    pub requester: NodeId,
+    /// Game version the request applies to
+    pub version: u64,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// The opponent's answer to a [`TakebackRequest`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TakebackResponse {
+    /// Node answering the request
+    pub responder: NodeId,
+    /// Game version the answered request applied to
+    pub version: u64,
+    pub accepted: bool,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// An offer from a player to end the game as a draw.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DrawOffer {
+    /// Node offering the draw
+    pub from: NodeId,
+    /// Game version the offer applies to
+    pub version: u64,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// The opponent's answer to a [`DrawOffer`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DrawResponse {
+    /// Node answering the offer
+    pub responder: NodeId,
+    /// Game version the answered offer applied to
+    pub version: u64,
+    pub accepted: bool,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+impl SharedActivity {
+    /// Get the current game state, if a game has been created.
+    ///
+    /// Falls back to migrating a game stored in the legacy shape.
+    pub async fn get_game(&self) -> anyhow::Result<Option<Game>> {
+        self.read_game(&self.admission().await?).await
+    }
+    /// Get the current game state as written by the given admitted authors.
+    pub(crate) async fn read_game(&self, admission: &Admission) -> anyhow::Result<Option<Game>> {
+        if let Some(game) = self.read_value::<Game>(admission, GAME_STATE_KEY).await? {
+            return Ok(Some(game));
+        }
+        let Some(entry) = self.read_latest(admission, LEGACY_GAME_STATE_KEY).await? else {
+            return Ok(None);
+        };
+        let legacy = self.decode_entry::<LegacyGame>(&entry).await?;
+        Ok(legacy.map(|legacy| legacy.migrate(admission.node_of(&entry.author()))))
+    }
+    /// Overwrite the shared game state.
+    pub(super) async fn set_game(&self, game: &Game) -> anyhow::Result<()> {
+        self.write_value(GAME_STATE_KEY, game).await?;
+        Ok(())
+    }
+    /// Get the current game state, failing if there is none.
+    async fn require_game(&self) -> anyhow::Result<Game> {
+        self.get_game()
+            .await?
+            .ok_or_else(|| app_error!(NotFound, "No game in this room."))
+    }
+    /// Take a seat in the game, creating the game if there isn't one yet.
+    pub async fn take_seat(&self, role: PlayerType, piece: Piece) -> anyhow::Result<Game> {
+        let mut game = self.get_game().await?.unwrap_or_else(Game::new);
+        game.take_seat(self.gossip.node_id(), role, piece)?;
+        info!("Took seat as {:?} playing {:?}", role, piece);
+        self.set_game(&game).await?;
+        Ok(game)
+    }
+    /// Start a fresh game with the seated players.
+    pub async fn start_game(&self) -> anyhow::Result<Game> {
+        let mut game = self.require_game().await?;
+        game.start_game()?;
+        self.set_game(&game).await?;
+        Ok(game)
+    }
+    /// Place our next piece on the board.
+    pub async fn play_move(&self, x: usize, y: usize) -> anyhow::Result<Move> {
+        let mut game = self.require_game().await?;
+        let played = game.play_move(x, y, self.gossip.node_id())?;
+        self.set_game(&game).await?;
+        Ok(played)
+    }
+    /// Concede the current game.
+    pub async fn resign(&self) -> anyhow::Result<Game> {
+        let mut game = self.require_game().await?;
+        game.resign(self.gossip.node_id())?;
+        info!("Resigning game at version {}", game.version);
+        self.set_game(&game).await?;
+        Ok(game)
+    }
+    /// Call off the current game before anyone has moved.
+    pub async fn abort_game(&self) -> anyhow::Result<Game> {
+        let mut game = self.require_game().await?;
+        game.abort(self.gossip.node_id())?;
+        info!("Aborting game at version {}", game.version);
+        self.set_game(&game).await?;
+        Ok(game)
+    }
+    /// Check that we hold a player seat in the game at the given version, so can answer
+    /// the other player's request made at it. Returns the game.
+    async fn require_opponent(&self, version: u64) -> anyhow::Result<Game> {
+        let game = self.require_game().await?;
+        match game.seat_of(&self.gossip.node_id()) {
+            Some(seat) if seat.is_player() => {}
+            _ => {
+                return Err(app_error!(
+                    NotPermitted,
+                    "Only the opponent can answer this."
+                ))
+            }
+        }
+        if game.version != version {
+            return Err(app_error!(IllegalMove, "The game has moved on since."));
+        }
+        Ok(game)
+    }
+    /// Ask the opponent to undo our last move.
+    pub async fn request_takeback(&self) -> anyhow::Result<TakebackRequest> {
+        let game = self.require_game().await?;
+        let node_id = self.gossip.node_id();
+        match game.last_move() {
+            Some(last) if last.by == node_id => {}
+            Some(_) => {
+                return Err(app_error!(
+                    IllegalMove,
+                    "Can only take back your own last move."
+                ))
+            }
+            None => return Err(app_error!(IllegalMove, "No moves to take back.")),
+        }
+        let request = TakebackRequest {
+            requester: node_id,
+            version: game.version,
+            timestamp: get_timestamp(),
+        };
+        info!("Requesting takeback at version {}", game.version);
+        self.write_value(TAKEBACK_REQUEST_KEY, &request).await?;
+        Ok(request)
+    }
+    /// Get the outstanding takeback request, if it still applies to the current game.
+    pub async fn get_takeback_request(&self) -> anyhow::Result<Option<TakebackRequest>> {
+        let admission = self.admission().await?;
+        let Some(game) = self.read_game(&admission).await? else {
+            return Ok(None);
+        };
+        let Some(request) = self
+            .read_value::<TakebackRequest>(&admission, TAKEBACK_REQUEST_KEY)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if request.version != game.version {
+            return Ok(None); // stale, the game has moved on
+        }
+        let response = self
+            .read_value::<TakebackResponse>(&admission, TAKEBACK_RESPONSE_KEY)
+            .await?;
+        if response.is_some_and(|response| response.version == request.version) {
+            return Ok(None); // already answered
+        }
+        Ok(Some(request))
+    }
+    /// Accept or decline the outstanding takeback request.
+    ///
+    /// On acceptance the game is rewound by one ply, which bumps its version.
+    pub async fn respond_takeback(&self, accept: bool) -> anyhow::Result<TakebackResponse> {
+        let Some(request) = self.get_takeback_request().await? else {
+            return Err(app_error!(NotFound, "No pending takeback request."));
+        };
+        let node_id = self.gossip.node_id();
+        if request.requester == node_id {
+            return Err(app_error!(
+                NotPermitted,
+                "Cannot answer your own takeback request."
+            ));
+        }
+        let mut game = self.require_opponent(request.version).await?;
+        // check the takeback applies before answering, so an accepted answer is never
+        // recorded without the game being rewound
+        if accept {
+            game.undo_move()?;
+        }
+        let response = TakebackResponse {
+            responder: node_id,
+            version: request.version,
+            accepted: accept,
+            timestamp: get_timestamp(),
+        };
+        info!(
+            "Takeback at version {} accepted: {}",
+            request.version, accept
+        );
+        self.write_value(TAKEBACK_RESPONSE_KEY, &response).await?;
+        if accept {
+            self.set_game(&game).await?;
+        }
+        Ok(response)
+    }
+    /// Offer the opponent a draw.
+    pub async fn offer_draw(&self) -> anyhow::Result<DrawOffer> {
+        let game = self.require_game().await?;
+        if !game
+            .seat_of(&self.gossip.node_id())
+            .is_some_and(Seat::is_player)
+        {
+            return Err(app_error!(
+                NotPermitted,
+                "You are not playing in this game."
+            ));
+        }
+        if game.result().is_some() {
+            return Err(app_error!(IllegalMove, "Game is already finished."));
+        }
+        let offer = DrawOffer {
+            from: self.gossip.node_id(),
+            version: game.version,
+            timestamp: get_timestamp(),
+        };
+        info!("Offering draw at version {}", game.version);
+        self.write_value(DRAW_OFFER_KEY, &offer).await?;
+        Ok(offer)
+    }
+    /// Get the outstanding draw offer, if it still applies to the current game.
+    pub async fn get_draw_offer(&self) -> anyhow::Result<Option<DrawOffer>> {
+        let admission = self.admission().await?;
+        let Some(game) = self.read_game(&admission).await? else {
+            return Ok(None);
+        };
+        let Some(offer) = self
+            .read_value::<DrawOffer>(&admission, DRAW_OFFER_KEY)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if offer.version != game.version {
+            return Ok(None); // stale, a move was played since
+        }
+        let response = self
+            .read_value::<DrawResponse>(&admission, DRAW_RESPONSE_KEY)
+            .await?;
+        if response.is_some_and(|response| response.version == offer.version) {
+            return Ok(None); // already answered
+        }
+        Ok(Some(offer))
+    }
+    /// Accept or decline the outstanding draw offer.
+    ///
+    /// On acceptance the game finishes as an agreed draw.
+    pub async fn respond_draw(&self, accept: bool) -> anyhow::Result<DrawResponse> {
+        let Some(offer) = self.get_draw_offer().await? else {
+            return Err(app_error!(NotFound, "No pending draw offer."));
+        };
+        let node_id = self.gossip.node_id();
+        if offer.from == node_id {
+            return Err(app_error!(
+                NotPermitted,
+                "Cannot answer your own draw offer."
+            ));
+        }
+        let mut game = self.require_opponent(offer.version).await?;
+        if accept {
+            game.agree_draw()?;
+        }
+        let response = DrawResponse {
+            responder: node_id,
+            version: offer.version,
+            accepted: accept,
+            timestamp: get_timestamp(),
+        };
+        info!("Draw at version {} accepted: {}", offer.version, accept);
+        self.write_value(DRAW_RESPONSE_KEY, &response).await?;
+        if accept {
+            self.set_game(&game).await?;
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,411 @@
+//! Moderation of disruptive participants by the host and the moderators it appoints.
+//!
+//! Roles and sanctions are signed doc entries, checked against the host from
+//! [`super::admission`]. They are replayed in the order they were made, so a sanction
+//! counts if its author could take it at the time, whatever their role became since.
+//! Kicks and bans are soft: the node keeps the capability from its
+//! ticket and can still write, everyone else just ignores its entries. Taking that
+//! capability away would mean moving the room to a new namespace.
+
+use crate::{
+    error::app_error,
+    gossip::doc::{SharedActivity, Signed, MODERATION_ACTIONS_PREFIX, MODERATION_ROLES_PREFIX},
+    utils::get_timestamp,
+};
+use iroh::NodeId;
+use iroh_docs::Entry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info};
+
+/// What a node may do in the room.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Host,
+    /// Can kick, ban and mute members
+    Moderator,
+    Member,
+}
+
+/// The host giving a node a role, only `Moderator` and `Member` can be granted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleGrant {
+    pub node_id: NodeId,
+    pub role: Role,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanction {
+    /// Drop the node's current session, it has to join again
+    Kick,
+    /// Ignore everything the node has written or will write
+    Ban,
+    Unban,
+    /// Hide the node's chat messages
+    Mute,
+    Unmute,
+}
+
+/// A sanction taken against a node by the host or a moderator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationAction {
+    pub node_id: NodeId,
+    pub sanction: Sanction,
+    pub by: NodeId,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// Moderation as seen by one particular node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerationView {
+    pub my_role: Role,
+    pub moderators: Vec<NodeId>,
+    pub banned: Vec<NodeId>,
+    pub muted: Vec<NodeId>,
+}
+
+fn role_key(grant: &RoleGrant) -> Vec<u8> {
+    [
+        MODERATION_ROLES_PREFIX,
+        grant.node_id.as_bytes(),
+        &grant.timestamp.to_be_bytes(),
+    ]
+    .concat()
+}
+
+fn action_key(action: &ModerationAction) -> Vec<u8> {
+    [
+        MODERATION_ACTIONS_PREFIX,
+        action.node_id.as_bytes(),
+        &action.timestamp.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// Roles and sanctions in force in the room, verified.
+#[derive(Debug, Default)]
+pub struct Moderation {
+    host: Option<NodeId>,
+    moderators: HashSet<NodeId>,
+    banned: HashSet<NodeId>,
+    muted: HashSet<NodeId>,
+    /// When each node was last kicked, in microseconds since EPOCH
+    kicked_at: HashMap<NodeId, u64>,
+}
+
+impl Moderation {
+    /// Build from the room's moderation entries, dropping any not signed by someone allowed.
+    pub(super) fn from_entries(entries: &[(Entry, Signed)], host: Option<NodeId>) -> Self {
+        let mut grants = Vec::new();
+        let mut actions = Vec::new();
+        for (entry, signed) in entries {
+            let key = entry.key();
+            if key.starts_with(MODERATION_ROLES_PREFIX) {
+                match signed.verify::<RoleGrant>() {
+                    Ok(grant) if Some(signed.signer) == host => grants.push(grant),
+                    _ => debug!("Ignoring role not granted by the host"),
+                }
+            } else if key.starts_with(MODERATION_ACTIONS_PREFIX) {
+                match signed.verify::<ModerationAction>() {
+                    Ok(action) if action.by == signed.signer => actions.push(action),
+                    _ => debug!("Ignoring invalid moderation action"),
+                }
+            }
+        }
+        Self::replay(host, grants, actions)
+    }
+
+    /// Apply verified role grants and sanctions in the order they were made, each
+    /// sanction checked against the roles in force at the time.
+    fn replay(
+        host: Option<NodeId>,
+        grants: Vec<RoleGrant>,
+        actions: Vec<ModerationAction>,
+    ) -> Self {
+        enum Change {
+            Grant(RoleGrant),
+            Action(ModerationAction),
+        }
+        let mut changes: Vec<(u64, Change)> = grants
+            .into_iter()
+            .map(|grant| (grant.timestamp, Change::Grant(grant)))
+            .chain(
+                actions
+                    .into_iter()
+                    .map(|action| (action.timestamp, Change::Action(action))),
+            )
+            .collect();
+        // a role granted in the same instant as a sanction is already in force
+        changes
+            .sort_by_key(|(timestamp, change)| (*timestamp, matches!(change, Change::Action(_))));
+
+        let mut moderation = Self {
+            host,
+            ..Default::default()
+        };
+        for (_, change) in changes {
+            match change {
+                Change::Grant(grant) => match grant.role {
+                    Role::Moderator => {
+                        moderation.moderators.insert(grant.node_id);
+                    }
+                    Role::Member => {
+                        moderation.moderators.remove(&grant.node_id);
+                    }
+                    Role::Host => debug!("Ignoring grant of the host role"),
+                },
+                Change::Action(action) => moderation.apply(action),
+            }
+        }
+        moderation
+    }
+
+    fn apply(&mut self, action: ModerationAction) {
+        if self.may_sanction(&action.by, &action.node_id).is_err() {
+            return debug!("Ignoring sanction by {} without authority", action.by);
+        }
+        let node_id = action.node_id;
+        match action.sanction {
+            Sanction::Kick => {
+                self.kicked_at.insert(node_id, action.timestamp);
+            }
+            Sanction::Ban => {
+                self.banned.insert(node_id);
+            }
+            Sanction::Unban => {
+                self.banned.remove(&node_id);
+            }
+            Sanction::Mute => {
+                self.muted.insert(node_id);
+            }
+            Sanction::Unmute => {
+                self.muted.remove(&node_id);
+            }
+        }
+    }
+
+    pub fn role(&self, node_id: &NodeId) -> Role {
+        if self.host.as_ref() == Some(node_id) {
+            Role::Host
+        } else if self.moderators.contains(node_id) {
+            Role::Moderator
+        } else {
+            Role::Member
+        }
+    }
+
+    /// Check one node may sanction another: the host anyone, moderators only members.
+    pub fn may_sanction(&self, by: &NodeId, target: &NodeId) -> anyhow::Result<()> {
+        match (self.role(by), self.role(target)) {
+            (Role::Member, _) => Err(app_error!(
+                NotPermitted,
+                "Only the host and moderators can do that."
+            )),
+            (_, Role::Host) => Err(app_error!(NotPermitted, "The host can't be sanctioned.")),
+            (Role::Moderator, Role::Moderator) => Err(app_error!(
+                NotPermitted,
+                "Moderators can't sanction each other."
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_banned(&self, node_id: &NodeId) -> bool {
+        self.banned.contains(node_id)
+    }
+
+    pub fn is_muted(&self, node_id: &NodeId) -> bool {
+        self.muted.contains(node_id)
+    }
+
+    /// Nodes whose messages aren't shown, being banned or muted.
+    pub fn silenced(&self) -> HashSet<NodeId> {
+        self.banned.union(&self.muted).copied().collect()
+    }
+
+    /// When the node was last kicked, if ever.
+    pub fn kicked_at(&self, node_id: &NodeId) -> Option<u64> {
+        self.kicked_at.get(node_id).copied()
+    }
+
+    /// Whether anyone has been kicked or banned, after which only bound authors count.
+    pub fn has_sanctions(&self) -> bool {
+        !self.banned.is_empty() || !self.kicked_at.is_empty()
+    }
+
+    /// Project moderation for display by the given node.
+    pub fn view_for(&self, viewer: &NodeId) -> ModerationView {
+        ModerationView {
+            my_role: self.role(viewer),
+            moderators: self.moderators.iter().copied().collect(),
+            banned: self.banned.iter().copied().collect(),
+            muted: self.muted.iter().copied().collect(),
+        }
+    }
+}
+
+impl SharedActivity {
+    /// Make a node a moderator or a plain member again, host only.
+    pub async fn set_role(&self, node_id: NodeId, role: Role) -> anyhow::Result<()> {
+        let admission = self.admission().await?;
+        if admission.host() != Some(self.node_id()) {
+            return Err(app_error!(
+                NotPermitted,
+                "Only the host can appoint moderators."
+            ));
+        }
+        if role == Role::Host || admission.host() == Some(node_id) {
+            return Err(app_error!(NotPermitted, "The host can't be changed."));
+        }
+        info!("Role of {node_id} set to {role:?}");
+        let grant = RoleGrant {
+            node_id,
+            role,
+            timestamp: get_timestamp(),
+        };
+        self.write_signed(role_key(&grant), &grant).await?;
+        Ok(())
+    }
+    /// Kick, ban or mute a node, or lift a ban or mute, as the host or a moderator.
+    pub async fn moderate(&self, node_id: NodeId, sanction: Sanction) -> anyhow::Result<()> {
+        let admission = self.admission().await?;
+        let by = self.node_id();
+        admission.moderation().may_sanction(&by, &node_id)?;
+        info!("{sanction:?} {node_id}");
+        let action = ModerationAction {
+            node_id,
+            sanction,
+            by,
+            timestamp: get_timestamp(),
+        };
+        self.write_signed(action_key(&action), &action).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn grant(node_id: NodeId, role: Role, timestamp: u64) -> RoleGrant {
+        RoleGrant {
+            node_id,
+            role,
+            timestamp,
+        }
+    }
+
+    fn action(by: NodeId, node_id: NodeId, sanction: Sanction, timestamp: u64) -> ModerationAction {
+        ModerationAction {
+            node_id,
+            sanction,
+            by,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn roles_and_who_may_sanction() {
+        let (host, moderator, member) = (node(), node(), node());
+        let moderation = Moderation::replay(
+            Some(host),
+            vec![
+                grant(moderator, Role::Moderator, 1),
+                grant(member, Role::Host, 2),
+            ],
+            Vec::new(),
+        );
+        assert_eq!(moderation.role(&host), Role::Host);
+        assert_eq!(moderation.role(&moderator), Role::Moderator);
+        // only the host hosts, whatever is granted
+        assert_eq!(moderation.role(&member), Role::Member);
+
+        assert!(moderation.may_sanction(&host, &moderator).is_ok());
+        assert!(moderation.may_sanction(&moderator, &member).is_ok());
+        assert!(moderation.may_sanction(&moderator, &host).is_err());
+        assert!(moderation.may_sanction(&host, &host).is_err());
+        assert!(moderation.may_sanction(&member, &moderator).is_err());
+        assert!(moderation.may_sanction(&member, &member).is_err());
+    }
+
+    #[test]
+    fn moderators_cannot_sanction_each_other() {
+        let (host, first, second) = (node(), node(), node());
+        let moderation = Moderation::replay(
+            Some(host),
+            vec![
+                grant(first, Role::Moderator, 1),
+                grant(second, Role::Moderator, 1),
+            ],
+            vec![
+                action(first, second, Sanction::Ban, 2),
+                action(second, first, Sanction::Mute, 3),
+            ],
+        );
+        assert!(moderation.may_sanction(&first, &second).is_err());
+        assert!(!moderation.is_banned(&second));
+        assert!(!moderation.is_muted(&first));
+    }
+
+    #[test]
+    fn sanctions_apply_in_order() {
+        let (host, member) = (node(), node());
+        let sanctions = |order: [u64; 2]| {
+            Moderation::replay(
+                Some(host),
+                Vec::new(),
+                vec![
+                    action(host, member, Sanction::Ban, order[0]),
+                    action(host, member, Sanction::Unban, order[1]),
+                ],
+            )
+        };
+        assert!(!sanctions([1, 2]).is_banned(&member));
+        assert!(sanctions([2, 1]).is_banned(&member));
+        assert!(!sanctions([2, 1]).is_muted(&member));
+
+        let kicked = Moderation::replay(
+            Some(host),
+            Vec::new(),
+            vec![action(host, member, Sanction::Kick, 5)],
+        );
+        assert_eq!(kicked.kicked_at(&member), Some(5));
+        assert!(kicked.has_sanctions());
+    }
+
+    #[test]
+    fn sanctions_count_with_the_roles_at_the_time() {
+        let (host, moderator, member, other) = (node(), node(), node(), node());
+        let moderation = Moderation::replay(
+            Some(host),
+            vec![
+                grant(moderator, Role::Moderator, 1),
+                grant(moderator, Role::Member, 3),
+                // promoted after writing a sanction it had no authority for
+                grant(member, Role::Moderator, 5),
+            ],
+            vec![
+                action(moderator, other, Sanction::Ban, 2),
+                action(moderator, other, Sanction::Mute, 4),
+                action(member, other, Sanction::Kick, 4),
+            ],
+        );
+        // the demoted moderator's ban stands, what it did after doesn't
+        assert!(moderation.is_banned(&other));
+        assert!(!moderation.is_muted(&other));
+        assert_eq!(moderation.kicked_at(&other), None);
+        assert_eq!(moderation.role(&moderator), Role::Member);
+        assert_eq!(moderation.role(&member), Role::Moderator);
+    }
+}
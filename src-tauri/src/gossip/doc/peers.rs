@@ -1,12 +1,19 @@
-use crate::gossip::doc::{SharedActivity, NICKNAME_KEY_SUFFIX, PEERS_PREFIX};
-use anyhow::anyhow;
-use iroh_docs::store::Query;
+use crate::{
+    error::app_error,
+    gossip::doc::{
+        admission::Admission, profile::Profile, schema::DocKey, SharedActivity,
+        NICKNAME_KEY_SUFFIX, PEERS_PREFIX,
+    },
+};
+use iroh::NodeAddr;
+use iroh_docs::{engine::LiveEvent, store::Query};
 use n0_future::StreamExt as _;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use std::time::Duration;
+use tracing::{info, warn};
 
 // Helper to create peer-specific nickname keys
-fn peer_nickname_key(node_id: &iroh::NodeId) -> Vec<u8> {
+pub(super) fn peer_nickname_key(node_id: &iroh::NodeId) -> Vec<u8> {
     [PEERS_PREFIX, node_id.as_bytes(), NICKNAME_KEY_SUFFIX].concat()
 }
 
@@ -15,6 +22,8 @@ pub enum PeerStatus {
     Online,
     Offline,
     Unknown,
+    /// Waiting for the host to let them in
+    Pending,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -23,6 +32,28 @@ pub struct PeerInfo {
     pub nickname: String,
     pub status: PeerStatus,
     pub ready: bool,
+    pub profile: Profile,
+}
+
+/// Peer info as written before profiles, up to version 1 of its encoding.
+#[derive(Deserialize)]
+pub(super) struct PeerInfoV1 {
+    id: iroh::NodeId,
+    nickname: String,
+    status: PeerStatus,
+    ready: bool,
+}
+
+impl From<PeerInfoV1> for PeerInfo {
+    fn from(peer: PeerInfoV1) -> Self {
+        Self {
+            id: peer.id,
+            nickname: peer.nickname,
+            status: peer.status,
+            ready: peer.ready,
+            profile: Profile::default(),
+        }
+    }
 }
 
 impl SharedActivity {
@@ -37,13 +68,14 @@ impl SharedActivity {
                 nickname: nickname.to_string(),
                 status: PeerStatus::Online,
                 ready: false,
+                profile: Profile::default(),
             },
             Some(mut peer) => {
                 peer.nickname = nickname.to_string();
                 peer
             }
         };
-        self.write(key, postcard::to_stdvec(&peer_info)?).await?;
+        self.write_value(key, &peer_info).await?;
         Ok(())
     }
     /// Set our status
@@ -52,10 +84,10 @@ impl SharedActivity {
         let node_id = self.gossip.node_id();
         let key = peer_nickname_key(&node_id);
         let Some(mut peer) = self.get_peer_info(&node_id).await? else {
-            return Err(anyhow!("Peer not found"));
+            return Err(app_error!(NotFound, "Peer not found"));
         };
         peer.status = status;
-        self.write(key, postcard::to_stdvec(&peer)?).await?;
+        self.write_value(key, &peer).await?;
         Ok(())
     }
     /// Get the peer info for a given node_id.
@@ -63,22 +95,90 @@ impl SharedActivity {
         let key = peer_nickname_key(node_id);
         match self.read_unique(key).await? {
             None => Ok(None),
-            Some(entry) => {
-                let bytes = self.read_bytes(entry.content_hash()).await?;
-                Ok(Some(postcard::from_bytes(&bytes)?))
-            }
+            Some(entry) => self.decode_entry(&entry).await,
         }
     }
-    /// Get all peers that have been registered in this document.
+    /// Get all peers that have been registered in this document, as admitted by the host.
     pub async fn get_all_peer_info(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        self.read_all_peer_info(&self.admission().await?).await
+    }
+    /// Get all peers the given admission lets in.
+    pub(crate) async fn read_all_peer_info(
+        &self,
+        admission: &Admission,
+    ) -> anyhow::Result<Vec<PeerInfo>> {
         let query = Query::key_prefix(PEERS_PREFIX);
         let mut entries = self.activity.get_many(query).await?;
         let mut peers = Vec::new();
         while let Some(Ok(entry)) = entries.next().await {
-            let bytes = self.read_bytes(entry.content_hash()).await?;
-            let peer_info: PeerInfo = postcard::from_bytes(&bytes)?;
-            peers.push(peer_info);
+            // avatars and anything else kept per peer share the prefix
+            if !matches!(DocKey::parse(entry.key()), DocKey::Peer(_)) {
+                continue;
+            }
+            match self.decode_entry::<PeerInfo>(&entry).await {
+                Ok(peer_info) => peers.extend(
+                    peer_info.and_then(|info| admission.review_peer(info, &entry.author())),
+                ),
+                Err(e) => warn!("Skipping peer info: {e}"),
+            }
         }
         Ok(peers)
     }
+    /// Sync the document with the peers still online, waiting until at least one of them
+    /// has our latest entries. Returns whether that happened before the timeout.
+    pub async fn sync_with_online_peers(&self, timeout: Duration) -> anyhow::Result<bool> {
+        let node_id = self.gossip.node_id();
+        let peers: Vec<NodeAddr> = self
+            .get_all_peer_info()
+            .await?
+            .into_iter()
+            .filter(|peer| peer.id != node_id && peer.status == PeerStatus::Online)
+            .map(|peer| NodeAddr::new(peer.id))
+            .collect();
+        if peers.is_empty() {
+            return Ok(false);
+        }
+        let mut events = self.activity.subscribe().await?;
+        self.activity.start_sync(peers).await?;
+        let synced = tokio::time::timeout(timeout, async {
+            while let Some(Ok(event)) = events.next().await {
+                if let LiveEvent::SyncFinished(sync) = event {
+                    match sync.result {
+                        Ok(_) => return true,
+                        Err(e) => warn!("Sync with {} failed: {e}", sync.peer),
+                    }
+                }
+            }
+            false
+        })
+        .await;
+        Ok(synced.unwrap_or(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::{doc::profile::Profile, testing};
+
+    #[tokio::test]
+    async fn peer_info_is_read_alongside_avatars() {
+        let (node, _dir) = testing::spawn_node().await;
+        let activity = SharedActivity::new(None, node.clone()).await.unwrap();
+        activity.set_nickname("alice").await.unwrap();
+        let avatar = node
+            .store_avatar(b"not really a png".to_vec(), "image/png")
+            .await
+            .unwrap();
+        let profile = Profile {
+            avatar: Some(avatar),
+            ..Profile::default()
+        };
+        activity.set_profile(&profile).await.unwrap();
+
+        let peers = activity.get_all_peer_info().await.unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].nickname, "alice");
+        assert_eq!(peers[0].profile, profile);
+    }
 }
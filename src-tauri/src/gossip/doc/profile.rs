@@ -0,0 +1,135 @@
+//! Profile cards shown next to a player's nickname.
+//!
+//! A profile is kept by the app and published in our [`super::peers::PeerInfo`] on joining a room. The
+//! avatar image is stored once in the blob store under a named tag, then referenced from
+//! each room by an entry of its own, so peers download it along with the rest of the
+//! room and keep it for as long as they keep the room.
+
+use crate::{
+    error::app_error,
+    game::board::Piece,
+    gossip::{
+        doc::{peers::peer_nickname_key, SharedActivity, AVATAR_KEY_SUFFIX, PEERS_PREFIX},
+        GossipNode,
+    },
+};
+use bytes::Bytes;
+use iroh::NodeId;
+use iroh_blobs::{rpc::client::blobs::BlobStatus, Hash};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Largest avatar image, in bytes.
+pub const MAX_AVATAR_SIZE: u64 = 256 * 1024;
+pub const MAX_BIO_LEN: usize = 280;
+/// Blob store tag keeping our own avatar between rooms.
+const AVATAR_TAG: &str = "profile/avatar";
+
+/// An avatar image stored as a blob.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct Avatar {
+    pub hash: Hash,
+    /// MIME type of the image, such as `image/png`
+    pub mime: String,
+    /// bytes
+    pub size: u64,
+}
+
+/// What other players see about us besides the nickname.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub bio: String,
+    /// The side the player prefers to play, `Empty` for no preference
+    pub favourite_side: Piece,
+    /// Self-reported rating
+    pub rating: Option<u32>,
+    pub avatar: Option<Avatar>,
+}
+
+impl Profile {
+    /// Check the fields are fit to show to other players.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.bio.chars().count() > MAX_BIO_LEN {
+            return Err(app_error!(
+                InvalidInput,
+                "Bio can't be longer than {MAX_BIO_LEN} characters."
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Key of the entry referencing a peer's avatar.
+pub fn avatar_key(node_id: &NodeId) -> Vec<u8> {
+    [PEERS_PREFIX, node_id.as_bytes(), AVATAR_KEY_SUFFIX].concat()
+}
+
+impl GossipNode {
+    /// Store our avatar image, replacing the previous one.
+    pub async fn store_avatar(&self, data: Vec<u8>, mime: &str) -> anyhow::Result<Avatar> {
+        let size = data.len() as u64;
+        if size > MAX_AVATAR_SIZE {
+            return Err(app_error!(
+                InvalidInput,
+                "Avatars can't be larger than {} KiB.",
+                MAX_AVATAR_SIZE / 1024
+            ));
+        }
+        if !mime.starts_with("image/") {
+            return Err(app_error!(InvalidInput, "Avatars must be images."));
+        }
+        let outcome = self.blobs.add_bytes_named(data, AVATAR_TAG).await?;
+        info!("Stored avatar {}, {size} bytes", outcome.hash);
+        Ok(Avatar {
+            hash: outcome.hash,
+            mime: mime.to_string(),
+            size,
+        })
+    }
+    /// Forget our avatar image, leaving it to the garbage collector once no room uses it.
+    pub async fn remove_avatar(&self) -> anyhow::Result<()> {
+        self.blobs.tags().delete(AVATAR_TAG).await
+    }
+    /// Read an avatar image, if it has been downloaded.
+    pub async fn read_avatar(&self, avatar: &Avatar) -> anyhow::Result<Option<Bytes>> {
+        match self.blobs.status(avatar.hash).await? {
+            BlobStatus::Complete { .. } => Ok(Some(self.blobs.read_to_bytes(avatar.hash).await?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl SharedActivity {
+    /// Publish our profile in the room, along with the avatar image it refers to.
+    pub async fn set_profile(&self, profile: &Profile) -> anyhow::Result<()> {
+        profile.validate()?;
+        let node_id = self.gossip.node_id();
+        let Some(mut peer) = self.get_peer_info(&node_id).await? else {
+            return Err(app_error!(NotFound, "Peer not found"));
+        };
+        if let Some(avatar) = &profile.avatar {
+            self.activity
+                .set_hash(
+                    self.author_id,
+                    avatar_key(&node_id),
+                    avatar.hash,
+                    avatar.size,
+                )
+                .await?;
+        }
+        peer.profile = profile.clone();
+        self.write_value(peer_nickname_key(&node_id), &peer).await?;
+        Ok(())
+    }
+    /// Get a peer's profile, as admitted by the host.
+    pub async fn get_profile(&self, node_id: &NodeId) -> anyhow::Result<Option<Profile>> {
+        Ok(self
+            .get_all_peer_info()
+            .await?
+            .into_iter()
+            .find(|peer| peer.id == *node_id)
+            .map(|peer| peer.profile))
+    }
+}
@@ -0,0 +1,140 @@
+//! Human readable description of a room, kept up to date by its host.
+//!
+//! The metadata is a single signed entry, only honoured when signed by the room's host
+//! as established by [`super::admission`].
+
+use crate::{
+    error::app_error,
+    gossip::doc::{admission::Admission, schema, SharedActivity, Signed, ROOM_META_KEY},
+    utils::get_timestamp,
+};
+use iroh::NodeId;
+use iroh_docs::store::Query;
+use n0_future::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// Game variant of rooms which don't pick one.
+pub const DEFAULT_VARIANT: &str = "standard";
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+
+/// What a room is called and how it plays.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomMeta {
+    pub name: String,
+    pub description: String,
+    /// microseconds since EPOCH
+    pub created_at: u64,
+    pub host: NodeId,
+    pub variant: String,
+    /// House rules, shown to joiners and in the lobby
+    pub rules: String,
+}
+
+/// Name for a room its host hasn't named yet.
+pub fn default_room_name(nickname: &str) -> String {
+    format!("{nickname}'s room")
+        .chars()
+        .take(MAX_NAME_LEN)
+        .collect()
+}
+
+impl RoomMeta {
+    /// Check the fields are fit to show to other players.
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(app_error!(InvalidInput, "Room name can't be empty."));
+        }
+        if self.name.chars().count() > MAX_NAME_LEN {
+            return Err(app_error!(
+                InvalidInput,
+                "Room name can't be longer than {MAX_NAME_LEN} characters."
+            ));
+        }
+        if self.description.chars().count() > MAX_DESCRIPTION_LEN {
+            return Err(app_error!(
+                InvalidInput,
+                "Room description can't be longer than {MAX_DESCRIPTION_LEN} characters."
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SharedActivity {
+    /// Return the room's metadata, if its host has published any.
+    pub async fn room_meta(&self) -> anyhow::Result<Option<RoomMeta>> {
+        self.read_room_meta(&self.admission().await?).await
+    }
+    /// Return the metadata published by the host the given admission names.
+    pub(crate) async fn read_room_meta(
+        &self,
+        admission: &Admission,
+    ) -> anyhow::Result<Option<RoomMeta>> {
+        let Some(host) = admission.host() else {
+            return Ok(None);
+        };
+        let mut entries = self
+            .activity
+            .get_many(Query::key_exact(ROOM_META_KEY))
+            .await?;
+        let mut latest: Option<(u64, RoomMeta)> = None;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let bytes = self.read_bytes(entry.content_hash()).await?;
+            let meta = schema::decode::<Signed>(&bytes)
+                .ok()
+                .flatten()
+                .filter(|signed| signed.signer == host)
+                .and_then(|signed| signed.verify::<RoomMeta>().ok());
+            let Some(meta) = meta else {
+                debug!("Ignoring room metadata not signed by the host");
+                continue;
+            };
+            if latest
+                .as_ref()
+                .is_none_or(|(timestamp, _)| entry.timestamp() > *timestamp)
+            {
+                latest = Some((entry.timestamp(), meta));
+            }
+        }
+        Ok(latest.map(|(_, meta)| meta))
+    }
+    /// Describe the room, host only.
+    ///
+    /// The creation time is kept from any earlier metadata.
+    pub async fn set_room_meta(
+        &self,
+        name: &str,
+        description: &str,
+        variant: &str,
+        rules: &str,
+    ) -> anyhow::Result<RoomMeta> {
+        let host = self.node_id();
+        let admission = self.admission().await?;
+        if admission.host() != Some(host) {
+            return Err(app_error!(
+                NotPermitted,
+                "Only the host can describe the room."
+            ));
+        }
+        let created_at = match self.read_room_meta(&admission).await? {
+            Some(meta) => meta.created_at,
+            None => get_timestamp(),
+        };
+        let meta = RoomMeta {
+            name: name.trim().to_string(),
+            description: description.trim().to_string(),
+            created_at,
+            host,
+            variant: variant.to_string(),
+            rules: rules.to_string(),
+        };
+        meta.validate()?;
+        info!("Room described as {}", meta.name);
+        self.write_signed(ROOM_META_KEY, &meta).await?;
+        Ok(meta)
+    }
+}
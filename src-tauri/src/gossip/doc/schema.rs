@@ -0,0 +1,333 @@
+//! Typed keys and versioned values for the shared document.
+//!
+//! Every value is stored in an envelope recording the schema it was written under and the
+//! version of its own encoding, so a struct can change shape without breaking rooms
+//! created by older clients. Older encodings are migrated forward as they are read, and
+//! entries a newer client wrote in a shape we don't know are reported as such rather than
+//! failing to decode.
+
+use crate::{
+    game::{
+        legacy::LegacyGame,
+        tournament::{MatchId, MatchResult, Tournament},
+        Game,
+    },
+    gossip::doc::{
+        admission::{
+            AdmissionChallenge, AdmissionDecision, AdmissionSettings, AuthorBinding,
+            ChallengeResponse, JoinRequest, JoinRequestV1,
+        },
+        chat::{ChatMessage, ChatMessageV1},
+        game::{DrawOffer, DrawResponse, TakebackRequest, TakebackResponse},
+        moderation::{ModerationAction, RoleGrant},
+        peers::{PeerInfo, PeerInfoV1},
+        room::RoomMeta,
+        tournament::parse_match_key,
+        Signed, ADMISSION_AUTHORS_PREFIX, ADMISSION_CHALLENGES_PREFIX, ADMISSION_DECISIONS_PREFIX,
+        ADMISSION_REQUESTS_PREFIX, ADMISSION_RESPONSES_PREFIX, ADMISSION_SETTINGS_KEY,
+        ATTACHMENTS_PREFIX, AVATAR_KEY_SUFFIX, DRAW_OFFER_KEY, DRAW_RESPONSE_KEY, GAME_STATE_KEY,
+        LEGACY_GAME_STATE_KEY, MESSAGES_PREFIX, MODERATION_ACTIONS_PREFIX, MODERATION_ROLES_PREFIX,
+        NICKNAME_KEY_SUFFIX, PEERS_PREFIX, ROOM_META_KEY, TAKEBACK_REQUEST_KEY,
+        TAKEBACK_RESPONSE_KEY, TOURNAMENT_GAMES_PREFIX, TOURNAMENT_KEY, TOURNAMENT_RESULTS_PREFIX,
+    },
+};
+use anyhow::anyhow;
+use iroh::NodeId;
+use iroh_blobs::Hash;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Bump when the envelope or the key layout changes.
+pub const SCHEMA_VERSION: u8 = 1;
+/// Marks an enveloped value, as opposed to bare postcard written before versioning.
+const ENVELOPE_MAGIC: &[u8] = b"SV";
+
+/// A value stored in the document.
+pub trait DocValue: DeserializeOwned {
+    /// Version of the current encoding, bump it when the type changes shape and teach
+    /// [`DocValue::migrate`] to read the previous one.
+    const VERSION: u16 = 1;
+
+    /// Decode a value written in an older encoding.
+    ///
+    /// Version 0 is the bare postcard written before values were versioned.
+    fn migrate(version: u16, payload: &[u8]) -> anyhow::Result<Self> {
+        match version {
+            0 => Ok(postcard::from_bytes(payload)?),
+            _ => Err(anyhow!(
+                "no migration for {} version {version}",
+                std::any::type_name::<Self>()
+            )),
+        }
+    }
+}
+
+impl DocValue for PeerInfo {
+    const VERSION: u16 = 2;
+
+    fn migrate(version: u16, payload: &[u8]) -> anyhow::Result<Self> {
+        match version {
+            0 | 1 => Ok(postcard::from_bytes::<PeerInfoV1>(payload)?.into()),
+            _ => Err(anyhow!("no migration for PeerInfo version {version}")),
+        }
+    }
+}
+impl DocValue for ChatMessage {
+    const VERSION: u16 = 2;
+
+    fn migrate(version: u16, payload: &[u8]) -> anyhow::Result<Self> {
+        match version {
+            0 | 1 => Ok(postcard::from_bytes::<ChatMessageV1>(payload)?.into()),
+            _ => Err(anyhow!("no migration for ChatMessage version {version}")),
+        }
+    }
+}
+impl DocValue for Game {}
+impl DocValue for LegacyGame {}
+impl DocValue for TakebackRequest {}
+impl DocValue for TakebackResponse {}
+impl DocValue for DrawOffer {}
+impl DocValue for DrawResponse {}
+impl DocValue for Tournament {}
+impl DocValue for MatchResult {}
+impl DocValue for Signed {}
+impl DocValue for AdmissionSettings {}
+impl DocValue for AuthorBinding {}
+impl DocValue for JoinRequest {
+    const VERSION: u16 = 2;
+
+    fn migrate(version: u16, payload: &[u8]) -> anyhow::Result<Self> {
+        match version {
+            0 | 1 => Ok(postcard::from_bytes::<JoinRequestV1>(payload)?.into()),
+            _ => Err(anyhow!("no migration for JoinRequest version {version}")),
+        }
+    }
+}
+impl DocValue for AdmissionChallenge {}
+impl DocValue for ChallengeResponse {}
+impl DocValue for AdmissionDecision {}
+impl DocValue for RoleGrant {}
+impl DocValue for ModerationAction {}
+impl DocValue for RoomMeta {}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    schema: u8,
+    version: u16,
+    payload: Vec<u8>,
+}
+
+/// Encode a value for storing in the document.
+pub fn encode<T: DocValue + Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    let envelope = Envelope {
+        schema: SCHEMA_VERSION,
+        version: T::VERSION,
+        payload: postcard::to_stdvec(value)?,
+    };
+    Ok([ENVELOPE_MAGIC, &postcard::to_stdvec(&envelope)?].concat())
+}
+
+/// Decode a value read from the document, migrating it from an older encoding if needed.
+///
+/// Returns `None` when a newer client wrote the value in an encoding we don't know.
+pub fn decode<T: DocValue>(bytes: &[u8]) -> anyhow::Result<Option<T>> {
+    let envelope = bytes
+        .strip_prefix(ENVELOPE_MAGIC)
+        .and_then(|rest| postcard::from_bytes::<Envelope>(rest).ok());
+    let Some(envelope) = envelope else {
+        return T::migrate(0, bytes).map(Some);
+    };
+    if envelope.schema > SCHEMA_VERSION || envelope.version > T::VERSION {
+        return Ok(None);
+    }
+    if envelope.version == T::VERSION {
+        return Ok(Some(postcard::from_bytes(&envelope.payload)?));
+    }
+    T::migrate(envelope.version, &envelope.payload).map(Some)
+}
+
+/// What an entry in the document holds, going by its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocKey {
+    Peer(NodeId),
+    /// Raw image content, not enveloped
+    Avatar(NodeId),
+    Message,
+    GameState,
+    LegacyGameState,
+    TakebackRequest,
+    TakebackResponse,
+    DrawOffer,
+    DrawResponse,
+    Tournament,
+    TournamentGame(MatchId),
+    TournamentResult(MatchId),
+    AdmissionSettings,
+    AdmissionAuthor,
+    AdmissionRequest,
+    AdmissionDecision,
+    AdmissionChallenge,
+    AdmissionResponse,
+    ModerationRole,
+    ModerationAction,
+    RoomMeta,
+    /// Raw file content, not enveloped
+    Attachment(Hash),
+    /// Written by a newer client, or malformed
+    Unknown,
+}
+
+impl DocKey {
+    pub fn parse(key: &[u8]) -> Self {
+        let exact = [
+            (GAME_STATE_KEY, Self::GameState),
+            (LEGACY_GAME_STATE_KEY, Self::LegacyGameState),
+            (TAKEBACK_REQUEST_KEY, Self::TakebackRequest),
+            (TAKEBACK_RESPONSE_KEY, Self::TakebackResponse),
+            (DRAW_OFFER_KEY, Self::DrawOffer),
+            (DRAW_RESPONSE_KEY, Self::DrawResponse),
+            (TOURNAMENT_KEY, Self::Tournament),
+            (ADMISSION_SETTINGS_KEY, Self::AdmissionSettings),
+            (ROOM_META_KEY, Self::RoomMeta),
+        ];
+        if let Some((_, parsed)) = exact.into_iter().find(|(exact, _)| key == *exact) {
+            return parsed;
+        }
+        if let Some(rest) = key.strip_prefix(PEERS_PREFIX) {
+            let node_id = |bytes: &[u8]| NodeId::from_bytes(bytes.try_into().ok()?).ok();
+            if let Some(bytes) = rest.strip_suffix(NICKNAME_KEY_SUFFIX) {
+                return node_id(bytes).map_or(Self::Unknown, Self::Peer);
+            }
+            return rest
+                .strip_suffix(AVATAR_KEY_SUFFIX)
+                .and_then(node_id)
+                .map_or(Self::Unknown, Self::Avatar);
+        }
+        if let Some(hash) = key.strip_prefix(ATTACHMENTS_PREFIX) {
+            return <[u8; 32]>::try_from(hash).map_or(Self::Unknown, |hash| {
+                Self::Attachment(Hash::from_bytes(hash))
+            });
+        }
+        if key.starts_with(TOURNAMENT_GAMES_PREFIX) {
+            return parse_match_key(TOURNAMENT_GAMES_PREFIX, key)
+                .map_or(Self::Unknown, Self::TournamentGame);
+        }
+        if key.starts_with(TOURNAMENT_RESULTS_PREFIX) {
+            return parse_match_key(TOURNAMENT_RESULTS_PREFIX, key)
+                .map_or(Self::Unknown, Self::TournamentResult);
+        }
+        let prefixed = [
+            (MESSAGES_PREFIX, Self::Message),
+            (ADMISSION_AUTHORS_PREFIX, Self::AdmissionAuthor),
+            (ADMISSION_REQUESTS_PREFIX, Self::AdmissionRequest),
+            (ADMISSION_DECISIONS_PREFIX, Self::AdmissionDecision),
+            (ADMISSION_CHALLENGES_PREFIX, Self::AdmissionChallenge),
+            (ADMISSION_RESPONSES_PREFIX, Self::AdmissionResponse),
+            (MODERATION_ROLES_PREFIX, Self::ModerationRole),
+            (MODERATION_ACTIONS_PREFIX, Self::ModerationAction),
+        ];
+        prefixed
+            .into_iter()
+            .find(|(prefix, _)| key.starts_with(prefix))
+            .map_or(Self::Unknown, |(_, parsed)| parsed)
+    }
+
+    /// Whether the entry is part of admission, which decides which other entries count.
+    pub fn is_admission(&self) -> bool {
+        matches!(
+            self,
+            Self::AdmissionSettings
+                | Self::AdmissionAuthor
+                | Self::AdmissionRequest
+                | Self::AdmissionDecision
+                | Self::AdmissionChallenge
+                | Self::AdmissionResponse
+        )
+    }
+
+    pub fn is_moderation(&self) -> bool {
+        matches!(self, Self::ModerationRole | Self::ModerationAction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Before {
+        value: u32,
+    }
+    impl DocValue for Before {}
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct After {
+        value: u32,
+        label: String,
+    }
+    impl DocValue for After {
+        const VERSION: u16 = 2;
+
+        fn migrate(version: u16, payload: &[u8]) -> anyhow::Result<Self> {
+            match version {
+                0 | 1 => {
+                    let before: Before = postcard::from_bytes(payload)?;
+                    Ok(Self {
+                        value: before.value,
+                        label: String::new(),
+                    })
+                }
+                _ => Err(anyhow!("no migration for After version {version}")),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Newer {
+        value: u32,
+    }
+    impl DocValue for Newer {
+        const VERSION: u16 = 3;
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let value = After {
+            value: 7,
+            label: "seven".to_string(),
+        };
+        let bytes = encode(&value).unwrap();
+        assert!(bytes.starts_with(ENVELOPE_MAGIC));
+        assert_eq!(decode::<After>(&bytes).unwrap(), Some(value));
+    }
+
+    #[test]
+    fn bare_values_migrate_from_version_zero() {
+        let bytes = postcard::to_stdvec(&Before { value: 7 }).unwrap();
+        assert_eq!(decode::<Before>(&bytes).unwrap(), Some(Before { value: 7 }));
+        let migrated = decode::<After>(&bytes).unwrap().unwrap();
+        assert_eq!(migrated.value, 7);
+        assert!(migrated.label.is_empty());
+    }
+
+    #[test]
+    fn older_versions_migrate() {
+        let bytes = encode(&Before { value: 7 }).unwrap();
+        let migrated = decode::<After>(&bytes).unwrap().unwrap();
+        assert_eq!(migrated.value, 7);
+        assert!(migrated.label.is_empty());
+    }
+
+    #[test]
+    fn newer_encodings_are_not_read() {
+        let bytes = encode(&Newer { value: 7 }).unwrap();
+        assert_eq!(decode::<After>(&bytes).unwrap(), None);
+
+        let envelope = Envelope {
+            schema: SCHEMA_VERSION + 1,
+            version: 1,
+            payload: postcard::to_stdvec(&Before { value: 7 }).unwrap(),
+        };
+        let bytes = [ENVELOPE_MAGIC, &postcard::to_stdvec(&envelope).unwrap()].concat();
+        assert_eq!(decode::<Before>(&bytes).unwrap(), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Chat content encrypted with a key shared by the room's members.
+//!
+//! Messages and attachments in a room with a key are sealed before they are written, so
+//! the blob store only ever holds their ciphertext, whether the app crashed or not. The
+//! key is handed out inside the room's tickets, see [`super::ticket`], and kept in the
+//! app's store, sealed with the vault when encryption is enabled.
+//!
+//! Rooms created before keys existed have none, nor a host. The node which created one,
+//! the first to announce its nickname in it, takes it over when it next opens the room:
+//! it generates a key and claims the room as its host, so the tickets it hands out from
+//! then on carry both. Each member seals the messages it wrote in plaintext once it has
+//! the key, as only an entry's author can replace it, and until then those are still
+//! read as they are. Members who joined before need one of the new tickets to read what
+//! is sealed.
+
+use crate::{
+    gossip::doc::{
+        chat::ChatMessage,
+        schema::{self, DocKey},
+        SharedActivity, MESSAGES_PREFIX, PEERS_PREFIX,
+    },
+    vault,
+};
+use anyhow::anyhow;
+use iroh_docs::store::Query;
+use n0_future::StreamExt as _;
+use rand::RngCore as _;
+use ring::aead;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+use tracing::{debug, info};
+
+pub const ROOM_KEY_LEN: usize = 32;
+/// How much longer sealed content is than its plaintext, for the nonce and the tag.
+pub const SEAL_OVERHEAD: u64 = 12 + 16;
+
+/// The key a room's chat content is sealed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoomKey([u8; ROOM_KEY_LEN]);
+
+impl RoomKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; ROOM_KEY_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    fn aead_key(&self) -> aead::LessSafeKey {
+        let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &self.0)
+            .expect("room keys have the length ChaCha20-Poly1305 expects");
+        aead::LessSafeKey::new(key)
+    }
+
+    pub fn seal(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        vault::seal(&self.aead_key(), data)
+    }
+
+    pub fn open(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        vault::open(&self.aead_key(), data)
+    }
+}
+
+impl fmt::Display for RoomKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RoomKey(..)")
+    }
+}
+
+impl FromStr for RoomKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = hex::decode(s)?
+            .try_into()
+            .map_err(|_| anyhow!("room keys are {ROOM_KEY_LEN} bytes"))?;
+        Ok(Self(key))
+    }
+}
+
+impl Serialize for RoomKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RoomKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl SharedActivity {
+    /// Take over a room created before keys and hosts existed if we created it, giving it
+    /// a key and pinning us as its host. Returns whether the room was taken over, after
+    /// which the caller claims the host role in the document.
+    pub async fn adopt_legacy_room(&mut self) -> anyhow::Result<bool> {
+        if self.read_only || self.key.is_some() || self.host.is_some() {
+            return Ok(false);
+        }
+        let mut entries = self
+            .activity
+            .get_many(Query::key_prefix(PEERS_PREFIX))
+            .await?;
+        let mut first = None;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if let DocKey::Peer(node_id) = DocKey::parse(entry.key()) {
+                if first.is_none_or(|(timestamp, _)| entry.timestamp() < timestamp) {
+                    first = Some((entry.timestamp(), node_id));
+                }
+            }
+        }
+        let me = self.node_id();
+        if first.map(|(_, node_id)| node_id) != Some(me) {
+            debug!("Room without a key was created by another node");
+            return Ok(false);
+        }
+        info!("Taking over room {} created before room keys", self.id());
+        self.key = Some(RoomKey::generate());
+        self.host = Some(me);
+        Ok(true)
+    }
+
+    /// Seal the messages we wrote before the room had a key, in this session or earlier
+    /// ones, returning how many were sealed.
+    pub async fn seal_legacy_messages(&self) -> anyhow::Result<usize> {
+        let Some(key) = &self.key else {
+            return Ok(0);
+        };
+        if self.read_only {
+            return Ok(0);
+        }
+        let ours = self.our_authors().await?;
+        let query = Query::key_prefix(MESSAGES_PREFIX);
+        let mut entries = self.activity.get_many(query).await?;
+        let mut plaintext = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            if !ours.contains(&entry.author()) {
+                continue;
+            }
+            let bytes = self.read_bytes(entry.content_hash()).await?;
+            if key.open(bytes.to_vec()).is_err() && schema::decode::<ChatMessage>(&bytes).is_ok() {
+                plaintext.push((entry.author(), entry.key().to_vec(), bytes));
+            }
+        }
+        let sealed = plaintext.len();
+        for (author, entry_key, bytes) in plaintext {
+            // replaces the entry, leaving its plaintext to garbage collection
+            let bytes = key.seal(bytes.to_vec())?;
+            self.activity.set_bytes(author, entry_key, bytes).await?;
+        }
+        if sealed > 0 {
+            info!("Sealed {sealed} messages written before the room had a key");
+        }
+        Ok(sealed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::{
+        doc::{attachment::attachment_key, SharedActivity, MESSAGES_PREFIX},
+        testing,
+    };
+    use iroh_docs::store::Query;
+
+    #[test]
+    fn sealed_content_round_trips() {
+        let key = RoomKey::generate();
+        let sealed = key.seal(b"hello room".to_vec()).unwrap();
+        assert_eq!(sealed.len() as u64, 10 + SEAL_OVERHEAD);
+        assert!(!sealed.windows(10).any(|window| window == b"hello room"));
+        assert_eq!(key.open(sealed.clone()).unwrap(), b"hello room");
+        assert!(RoomKey::generate().open(sealed).is_err());
+    }
+
+    #[tokio::test]
+    async fn chat_content_is_stored_sealed() {
+        let (node, _dir) = testing::spawn_node().await;
+        let activity = SharedActivity::new(None, node.clone()).await.unwrap();
+        let attachment = activity
+            .attach("notes.txt", "text/plain", b"secret notes".to_vec())
+            .await
+            .unwrap();
+        activity
+            .send_message("alice", "secret message", vec![attachment.clone()])
+            .await
+            .unwrap();
+
+        let message = activity
+            .get_one(Query::key_prefix(MESSAGES_PREFIX))
+            .await
+            .unwrap()
+            .unwrap();
+        let stored = activity.read_bytes(message.content_hash()).await.unwrap();
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+        let stored = activity.read_bytes(attachment.hash).await.unwrap();
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+        assert!(activity
+            .get_one(Query::key_exact(attachment_key(&attachment.hash)))
+            .await
+            .unwrap()
+            .is_some());
+
+        let messages = activity.get_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "secret message");
+        assert_eq!(messages[0].attachments, vec![attachment.clone()]);
+        let content = activity.fetch_attachment(attachment.hash).await.unwrap();
+        assert_eq!(&content[..], b"secret notes");
+
+        // the history of a room we left is read with its stored key
+        let stored = SharedActivity::open_stored(activity.id(), node, &activity.credentials())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.get_messages().await.unwrap(), messages);
+    }
+
+    #[tokio::test]
+    async fn creators_take_over_rooms_without_keys() {
+        let (node, _dir) = testing::spawn_node().await;
+        let mut activity = SharedActivity::new(None, node.clone()).await.unwrap();
+        // as a room was before keys and hosts
+        activity.key = None;
+        activity.host = None;
+        activity.set_nickname("alice").await.unwrap();
+        activity
+            .send_message("alice", "secret message", Vec::new())
+            .await
+            .unwrap();
+        let message = activity
+            .get_one(Query::key_prefix(MESSAGES_PREFIX))
+            .await
+            .unwrap()
+            .unwrap();
+        let stored = activity.read_bytes(message.content_hash()).await.unwrap();
+        assert!(stored.windows(6).any(|window| window == b"secret"));
+        assert_eq!(activity.seal_legacy_messages().await.unwrap(), 0);
+
+        assert!(activity.adopt_legacy_room().await.unwrap());
+        assert!(activity.key.is_some());
+        assert_eq!(activity.host, Some(node.node_id()));
+        // plaintext messages are still read until they are sealed
+        assert_eq!(activity.get_messages().await.unwrap().len(), 1);
+        assert_eq!(activity.seal_legacy_messages().await.unwrap(), 1);
+        assert_eq!(activity.seal_legacy_messages().await.unwrap(), 0);
+
+        let message = activity
+            .get_one(Query::key_prefix(MESSAGES_PREFIX))
+            .await
+            .unwrap()
+            .unwrap();
+        let stored = activity.read_bytes(message.content_hash()).await.unwrap();
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+        let messages = activity.get_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "secret message");
+        // a room which has a key is left alone
+        assert!(!activity.adopt_legacy_room().await.unwrap());
+    }
+}
@@ -0,0 +1,127 @@
+//! Invites to a room and what we keep to rejoin it.
+//!
+//! An invite is the document's ticket followed by what a member needs beyond the
+//! capability, as `<doc ticket>#key=<room key>&host=<node id>`. The room key opens its
+//! chat content, see [`super::sealing`], and the host pins the node whose signed entries
+//! decide admission, see [`super::admission`]. Both are left out for rooms created
+//! before they existed, until their creator takes them over, see [`super::sealing`].
+
+use crate::{error::app_error, gossip::doc::sealing::RoomKey};
+use anyhow::anyhow;
+use iroh::NodeId;
+use iroh_docs::DocTicket;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Separates the document's ticket from the fields we add to it.
+const FIELDS_SEPARATOR: char = '#';
+const FIELD_SEPARATOR: char = '&';
+
+/// An invite to a room.
+#[derive(Debug, Clone)]
+pub struct RoomTicket {
+    pub doc: DocTicket,
+    pub key: Option<RoomKey>,
+    pub host: Option<NodeId>,
+}
+
+impl fmt::Display for RoomTicket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.doc)?;
+        let mut fields = Vec::new();
+        if let Some(key) = &self.key {
+            fields.push(format!("key={key}"));
+        }
+        if let Some(host) = &self.host {
+            fields.push(format!("host={host}"));
+        }
+        if !fields.is_empty() {
+            write!(
+                f,
+                "{FIELDS_SEPARATOR}{}",
+                fields.join(&FIELD_SEPARATOR.to_string())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RoomTicket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |e: anyhow::Error| app_error!(InvalidTicket, "Invalid activity ticket: {}", e);
+        let (doc, fields) = s
+            .trim()
+            .split_once(FIELDS_SEPARATOR)
+            .unwrap_or((s.trim(), ""));
+        let mut ticket = Self {
+            doc: DocTicket::from_str(doc).map_err(|e| invalid(anyhow!(e)))?,
+            key: None,
+            host: None,
+        };
+        for field in fields
+            .split(FIELD_SEPARATOR)
+            .filter(|field| !field.is_empty())
+        {
+            match field.split_once('=') {
+                Some(("key", key)) => ticket.key = Some(key.parse().map_err(invalid)?),
+                Some(("host", host)) => {
+                    ticket.host = Some(host.parse().map_err(|e| invalid(anyhow!("{e}")))?)
+                }
+                // fields added by newer versions
+                _ => {}
+            }
+        }
+        Ok(ticket)
+    }
+}
+
+/// What we keep about a room to read its history and rejoin it with an older ticket.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomCredentials {
+    pub key: Option<RoomKey>,
+    pub host: Option<NodeId>,
+    /// Password joiners prove to be let in, only kept for rooms we host
+    pub password: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::{NodeAddr, SecretKey};
+    use iroh_docs::{Capability, NamespaceSecret};
+
+    fn doc_ticket() -> DocTicket {
+        let namespace = NamespaceSecret::new(&mut rand::rngs::OsRng);
+        let node = NodeAddr::new(SecretKey::generate(rand::rngs::OsRng).public());
+        DocTicket::new(Capability::Read(namespace.id()), vec![node])
+    }
+
+    #[test]
+    fn tickets_carry_the_key_and_host() {
+        let doc = doc_ticket();
+        let ticket = RoomTicket {
+            doc: doc.clone(),
+            key: Some(RoomKey::generate()),
+            host: Some(SecretKey::generate(rand::rngs::OsRng).public()),
+        };
+        let parsed = RoomTicket::from_str(&ticket.to_string()).unwrap();
+        assert_eq!(parsed.doc.to_string(), doc.to_string());
+        assert_eq!(parsed.key, ticket.key);
+        assert_eq!(parsed.host, ticket.host);
+    }
+
+    #[test]
+    fn older_tickets_still_parse() {
+        let doc = doc_ticket();
+        let parsed = RoomTicket::from_str(&doc.to_string()).unwrap();
+        assert_eq!((parsed.key, parsed.host), (None, None));
+        let key = RoomKey::generate();
+        let parsed = RoomTicket::from_str(&format!("{doc}#key={key}&colour=red")).unwrap();
+        assert_eq!((parsed.key, parsed.host), (Some(key), None));
+        assert!(RoomTicket::from_str(&format!("{doc}#key=abcd")).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    error::app_error,
+    game::{
+        tournament::{MatchId, MatchResult, Pairing, Tournament, TournamentFormat, TournamentView},
+        Game, Move,
+    },
+    gossip::doc::{
+        admission::Admission, peers::PeerStatus, SharedActivity, TOURNAMENT_GAMES_PREFIX,
+        TOURNAMENT_KEY, TOURNAMENT_RESULTS_PREFIX,
+    },
+    utils::get_timestamp,
+};
+use iroh_docs::store::Query;
+use n0_future::StreamExt as _;
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+// Helper to create keys scoped to one tournament, so a new tournament starts with a clean slate
+fn tournament_prefix(prefix: &[u8], tournament: &Tournament) -> Vec<u8> {
+    [prefix, &tournament.created.to_be_bytes()].concat()
+}
+
+// Helper to create the key for one match's game or result
+fn match_key(prefix: &[u8], tournament: &Tournament, id: MatchId) -> Vec<u8> {
+    [
+        tournament_prefix(prefix, tournament).as_slice(),
+        &id.round.to_be_bytes(),
+        &id.index.to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// Recover the match a tournament key refers to.
+pub fn parse_match_key(prefix: &[u8], key: &[u8]) -> Option<MatchId> {
+    let rest = key.strip_prefix(prefix)?.get(8..)?; // skip the tournament timestamp
+    let round = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+    let index = u32::from_be_bytes(rest.get(4..8)?.try_into().ok()?);
+    Some(MatchId { round, index })
+}
+
+impl SharedActivity {
+    /// Start a tournament between everyone currently online in the room.
+    pub async fn start_tournament(
+        &self,
+        format: TournamentFormat,
+    ) -> anyhow::Result<TournamentView> {
+        let admission = self.admission().await?;
+        self.require_admitted(&admission)?;
+        let roster: Vec<_> = self
+            .read_all_peer_info(&admission)
+            .await?
+            .into_iter()
+            .filter(|peer| peer.status == PeerStatus::Online)
+            .map(|peer| peer.id)
+            .collect();
+        if roster.len() < 2 {
+            return Err(app_error!(
+                IllegalMove,
+                "A tournament needs at least two players online."
+            ));
+        }
+        info!(
+            "Starting {:?} tournament for {} players",
+            format,
+            roster.len()
+        );
+        let tournament = Tournament::new(format, roster, get_timestamp());
+        self.write_value(TOURNAMENT_KEY, &tournament).await?;
+        Ok(tournament.view(HashMap::new()))
+    }
+    /// Get the room's tournament settings as written by the given admitted authors.
+    async fn read_tournament(&self, admission: &Admission) -> anyhow::Result<Option<Tournament>> {
+        self.read_value(admission, TOURNAMENT_KEY).await
+    }
+    /// Get the room's tournament with its bracket, results and standings.
+    pub async fn get_tournament_view(&self) -> anyhow::Result<Option<TournamentView>> {
+        self.read_tournament_view(&self.admission().await?).await
+    }
+    /// Get the tournament view as written by the given admitted authors.
+    pub(crate) async fn read_tournament_view(
+        &self,
+        admission: &Admission,
+    ) -> anyhow::Result<Option<TournamentView>> {
+        let Some(tournament) = self.read_tournament(admission).await? else {
+            return Ok(None);
+        };
+        let results = self.get_match_results(admission, &tournament).await?;
+        Ok(Some(tournament.view(results)))
+    }
+    /// Collect the latest result recorded by an admitted author for each match of the
+    /// given tournament.
+    async fn get_match_results(
+        &self,
+        admission: &Admission,
+        tournament: &Tournament,
+    ) -> anyhow::Result<HashMap<MatchId, MatchResult>> {
+        let prefix = tournament_prefix(TOURNAMENT_RESULTS_PREFIX, tournament);
+        let mut entries = self.activity.get_many(Query::key_prefix(prefix)).await?;
+        let mut latest: HashMap<MatchId, (u64, MatchResult)> = HashMap::new();
+        while let Some(Ok(entry)) = entries.next().await {
+            if !admission.honours(&entry.author()) {
+                debug!("Ignoring match result from an author which is not admitted");
+                continue;
+            }
+            let result = match self.decode_entry::<MatchResult>(&entry).await {
+                Ok(Some(result)) => result,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Skipping unreadable match result {e}");
+                    continue;
+                }
+            };
+            if latest
+                .get(&result.id)
+                .is_none_or(|(timestamp, _)| entry.timestamp() > *timestamp)
+            {
+                latest.insert(result.id, (entry.timestamp(), result));
+            }
+        }
+        Ok(latest
+            .into_iter()
+            .map(|(id, (_, result))| (id, result))
+            .collect())
+    }
+    /// Get the game being played for a tournament match, if it has begun.
+    pub async fn get_match_game(&self, id: MatchId) -> anyhow::Result<Option<Game>> {
+        let admission = self.admission().await?;
+        let Some(tournament) = self.read_tournament(&admission).await? else {
+            return Err(app_error!(NotFound, "No tournament in this room."));
+        };
+        let key = match_key(TOURNAMENT_GAMES_PREFIX, &tournament, id);
+        self.read_value(&admission, key).await
+    }
+    /// Find one of our own undecided pairings in the current bracket.
+    async fn require_open_pairing(
+        &self,
+        admission: &Admission,
+        tournament: &Tournament,
+        id: MatchId,
+    ) -> anyhow::Result<Pairing> {
+        self.require_admitted(admission)?;
+        let results = self.get_match_results(admission, tournament).await?;
+        let Some(pairing) = tournament
+            .rounds(&results)
+            .into_iter()
+            .flatten()
+            .find(|pairing| pairing.id == id)
+        else {
+            return Err(app_error!(NotFound, "Match {id:?} has not been drawn."));
+        };
+        if !pairing.involves(&self.gossip.node_id()) {
+            return Err(app_error!(
+                NotPermitted,
+                "You are not playing in match {id:?}."
+            ));
+        }
+        if pairing.is_bye() || results.contains_key(&id) {
+            return Err(app_error!(IllegalMove, "Match {id:?} is already decided."));
+        }
+        Ok(pairing)
+    }
+    /// Read a match's game, or set one up between the pairing if nobody has moved yet.
+    async fn load_match_game(
+        &self,
+        admission: &Admission,
+        key: &[u8],
+        pairing: &Pairing,
+    ) -> anyhow::Result<Game> {
+        if let Some(game) = self.read_value::<Game>(admission, key.to_vec()).await? {
+            return Ok(game);
+        }
+        match pairing.naught {
+            Some(naught) => Game::between(pairing.cross, naught),
+            None => Err(app_error!(IllegalMove, "Match {:?} is a bye.", pairing.id)),
+        }
+    }
+    /// Place our next piece in a tournament match, starting its game if needed.
+    pub async fn play_match_move(&self, id: MatchId, x: usize, y: usize) -> anyhow::Result<Move> {
+        let admission = self.admission().await?;
+        let Some(tournament) = self.read_tournament(&admission).await? else {
+            return Err(app_error!(NotFound, "No tournament in this room."));
+        };
+        let pairing = self
+            .require_open_pairing(&admission, &tournament, id)
+            .await?;
+        let key = match_key(TOURNAMENT_GAMES_PREFIX, &tournament, id);
+        let mut game = self.load_match_game(&admission, &key, &pairing).await?;
+        let played = game.play_move(x, y, self.gossip.node_id())?;
+        self.write_value(key, &game).await?;
+        self.record_match_result(&tournament, id, &game).await?;
+        Ok(played)
+    }
+    /// Concede a tournament match.
+    pub async fn resign_match(&self, id: MatchId) -> anyhow::Result<Game> {
+        let admission = self.admission().await?;
+        let Some(tournament) = self.read_tournament(&admission).await? else {
+            return Err(app_error!(NotFound, "No tournament in this room."));
+        };
+        let pairing = self
+            .require_open_pairing(&admission, &tournament, id)
+            .await?;
+        let key = match_key(TOURNAMENT_GAMES_PREFIX, &tournament, id);
+        let mut game = self.load_match_game(&admission, &key, &pairing).await?;
+        game.resign(self.gossip.node_id())?;
+        self.write_value(key, &game).await?;
+        self.record_match_result(&tournament, id, &game).await?;
+        Ok(game)
+    }
+    /// Publish a match's result once its game has finished.
+    async fn record_match_result(
+        &self,
+        tournament: &Tournament,
+        id: MatchId,
+        game: &Game,
+    ) -> anyhow::Result<()> {
+        let Some(result) = game.result() else {
+            return Ok(());
+        };
+        let result = MatchResult {
+            id,
+            winner: game.winner(),
+            result: result.clone(),
+        };
+        info!("Match {:?} finished: {:?}", id, result);
+        let key = match_key(TOURNAMENT_RESULTS_PREFIX, tournament, id);
+        self.write_value(key, &result).await?;
+        Ok(())
+    }
+}
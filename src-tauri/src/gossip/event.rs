@@ -1,153 +1,698 @@
+mod progress;
+
 use iroh::NodeId;
 use iroh_blobs::Hash;
-use iroh_docs::{engine::LiveEvent, ContentStatus};
+use iroh_docs::{engine::LiveEvent, ContentStatus, Entry};
 use n0_future::{boxed::BoxStream, task::AbortOnDropHandle, StreamExt as _};
-use serde::Serialize;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{any::type_name, sync::Arc, time::Duration};
 use tauri::{AppHandle, Emitter as _};
-use tokio::{sync::Mutex as TokioMutex, time::sleep};
+use tokio::{
+    sync::Mutex as TokioMutex,
+    time::{sleep, timeout_at, Instant},
+};
 use tracing::{debug, error, info};
 
 use crate::{
-    gossip::doc::{chat::ChatMessage, peers::PeerInfo, MESSAGES_PREFIX, PEERS_PREFIX},
+    game::{
+        tournament::{MatchId, TournamentView},
+        Game, GameResult, GameView,
+    },
+    gossip::diagnostics::PeerConnection,
+    gossip::doc::{
+        admission::{Admission, AdmissionView, JoinRequest},
+        chat::ChatMessage,
+        game::{DrawOffer, DrawResponse, TakebackRequest, TakebackResponse},
+        moderation::ModerationView,
+        peers::PeerInfo,
+        room::RoomMeta,
+        schema::{self, DocKey, DocValue},
+        SharedActivity, Signed,
+    },
+    gossip::lobby::RoomAdvert,
+    gossip::notify::{self, Notification, NotificationHook, UnreadCount},
+    gossip::search::ChatIndex,
+    gossip::voice::VoiceView,
     state::ActiveChannel,
 };
+use progress::{SyncProgress, SyncTracker};
+
+/// How long the event stream must stay quiet before a burst of events is emitted.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+/// Longest a burst is held back while events keep arriving.
+const MAX_BATCH_DELAY: Duration = Duration::from_millis(250);
 
-#[derive(Debug, Clone, Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Event {
     #[serde(rename_all = "camelCase")]
-    NeighborUp {
-        node_id: NodeId,
+    Errorred {
+        message: String,
+    },
+    Disconnected,
+    Delta {
+        delta: Delta,
+    },
+    TakebackRequested {
+        request: TakebackRequest,
+    },
+    TakebackAnswered {
+        response: TakebackResponse,
+    },
+    DrawOffered {
+        offer: DrawOffer,
+    },
+    DrawAnswered {
+        response: DrawResponse,
+    },
+    GameFinished {
+        result: GameResult,
+    },
+    TournamentUpdate {
+        tournament: TournamentView,
     },
     #[serde(rename_all = "camelCase")]
-    NeighborDown {
-        node_id: NodeId,
+    MatchUpdate {
+        match_id: MatchId,
+        game: GameView,
+    },
+    LobbyUpdate {
+        rooms: Vec<RoomAdvert>,
+    },
+    JoinRequested {
+        request: JoinRequest,
+    },
+    AdmissionUpdate {
+        admission: AdmissionView,
+    },
+    ModerationUpdate {
+        moderation: ModerationView,
+    },
+    RoomUpdated {
+        room: RoomMeta,
+    },
+    AttachmentReady {
+        hash: Hash,
     },
     #[serde(rename_all = "camelCase")]
-    Errorred {
-        message: String,
+    AvatarReady {
+        node_id: NodeId,
+        hash: Hash,
     },
-    Disconnected,
-    SyncFinished,
-    PeerUpdate {
-        info: PeerInfo,
+    ConnectionUpdate {
+        peers: Vec<PeerConnection>,
     },
-    NewMessage {
-        message: ChatMessage,
+    VoiceUpdate {
+        voice: VoiceView,
     },
-    ContentReady,
-    PendingContentReady,
+    UnreadUpdate {
+        room: String,
+        unread: UnreadCount,
+    },
+    Notification {
+        notification: Notification,
+    },
+    SyncProgress {
+        progress: SyncProgress,
+    },
+    /// Every blob missing from the room was fetched
+    SyncSummary {
+        summary: SyncProgress,
+    },
+}
+
+/// Changes to the room merged from a burst of events.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delta {
+    /// Number of this delta, counting from one in each room
+    pub seq: u64,
+    /// New or edited messages, in the order they arrived
+    pub messages: Vec<ChatMessage>,
+    /// Latest info of every peer which changed
+    pub peers: Vec<PeerInfo>,
+    /// Direct neighbors in the swarm which connected and are still connected
+    pub neighbors_up: Vec<NodeId>,
+    /// Direct neighbors in the swarm which dropped and are still gone
+    pub neighbors_down: Vec<NodeId>,
+    /// The room's game as seen by this node, if it changed
+    pub game: Option<GameView>,
+    /// Whether a set-reconciliation sync with a peer finished
+    pub sync_finished: bool,
+}
+
+impl Delta {
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+            && self.peers.is_empty()
+            && self.neighbors_up.is_empty()
+            && self.neighbors_down.is_empty()
+            && self.game.is_none()
+            && !self.sync_finished
+    }
+
+    fn peer_updated(&mut self, info: PeerInfo) {
+        match self.peers.iter_mut().find(|peer| peer.id == info.id) {
+            Some(peer) => *peer = info,
+            None => self.peers.push(info),
+        }
+    }
+
+    fn neighbor_changed(&mut self, node_id: NodeId, up: bool) {
+        let (added, removed) = if up {
+            (&mut self.neighbors_up, &mut self.neighbors_down)
+        } else {
+            (&mut self.neighbors_down, &mut self.neighbors_up)
+        };
+        removed.retain(|node| *node != node_id);
+        if !added.contains(&node_id) {
+            added.push(node_id);
+        }
+    }
 }
 
-/// Helper function to process an entry and emit specific update events.
-async fn process_entry_for_updates(
-    key: &[u8],
-    hash: Hash,
-    channel: &ActiveChannel,
-    app: &AppHandle,
-) {
-    info!("Processing entry: {:?}", key.to_ascii_lowercase());
-    if key.starts_with(PEERS_PREFIX) {
-        debug!("Processing peers entry");
-        match channel.activity.read_bytes(hash).await {
-            Ok(bytes) => match postcard::from_bytes::<PeerInfo>(&bytes) {
-                Ok(info) => {
-                    info!("Peer info updated/received: {:?}", info);
-                    if let Err(e) = app.emit("chat-event", Event::PeerUpdate { info }) {
-                        error!("Failed to emit peer-update event: {e:?}");
+/// Number of the last delta sent for a room. It stays locked while a burst is read and
+/// sent, so a snapshot always falls between two deltas.
+#[derive(Clone, Default)]
+pub struct DeltaCursor(Arc<TokioMutex<u64>>);
+
+impl DeltaCursor {
+    /// Read the room as of the last delta sent, later deltas only carry newer state.
+    pub async fn snapshot(&self, activity: &SharedActivity) -> anyhow::Result<RoomSnapshot> {
+        let cursor = self.0.lock().await;
+        let admission = activity.admission().await?;
+        Ok(RoomSnapshot {
+            cursor: *cursor,
+            peers: activity.read_all_peer_info(&admission).await?,
+            messages: activity.read_messages(&admission).await?,
+            game: activity
+                .read_game(&admission)
+                .await?
+                .map(|game| game.view_for(activity.node_id())),
+            room: activity.read_room_meta(&admission).await?,
+        })
+    }
+}
+
+/// Everything the frontend shows of a room, to start from before applying deltas.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSnapshot {
+    /// Number of the last delta included, deltas up to it can be dropped
+    pub cursor: u64,
+    pub peers: Vec<PeerInfo>,
+    pub messages: Vec<ChatMessage>,
+    pub game: Option<GameView>,
+    pub room: Option<RoomMeta>,
+}
+
+/// The settings of the active channel the listener needs, read before a burst is
+/// processed so the context isn't locked while its entries are read.
+#[derive(Default)]
+struct ChannelSettings {
+    nickname: String,
+    /// The room password, which we check joiners against as the host or prove to it
+    room_password: Option<String>,
+}
+
+/// Everything to send the frontend for a burst of events.
+#[derive(Default)]
+struct Batch {
+    delta: Delta,
+    /// Messages counted as unread, stored in one go when the batch is sent
+    unread: UnreadCount,
+    /// Events which are not merged, in the order they happened
+    events: Vec<Event>,
+    channel: ChannelSettings,
+    /// Who is admitted as of the entries processed so far, read at most once between
+    /// admission or moderation entries
+    admission: Option<Arc<Admission>>,
+    admission_changed: bool,
+    moderation_changed: bool,
+}
+
+impl Batch {
+    fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Send the batch, numbering its delta after the last one sent.
+    fn emit(mut self, cursor: &mut u64, room: String, app: &AppHandle) {
+        if !self.delta.is_empty() {
+            *cursor += 1;
+            self.delta.seq = *cursor;
+            emit_event(app, Event::Delta { delta: self.delta });
+        }
+        if self.unread.messages > 0 {
+            if let Some(unread) = notify::record_unread(&room, self.unread, app) {
+                emit_event(app, Event::UnreadUpdate { room, unread });
+            }
+        }
+        for event in self.events {
+            emit_event(app, event);
+        }
+    }
+}
+
+/// What the event listener needs of the room. Entries are read from its own handle on the
+/// document, the context is only locked for the few settings kept in the active channel.
+struct Listener {
+    activity: SharedActivity,
+    cursor: DeltaCursor,
+    active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
+    chat_index: Arc<TokioMutex<ChatIndex>>,
+    notifier: Arc<dyn NotificationHook>,
+    app: AppHandle,
+}
+
+/// Helper function to read and decode the content of an entry, logging any failure.
+///
+/// Values a newer version wrote in an encoding we don't know are skipped.
+async fn decode_entry<T: DocValue>(hash: Hash, activity: &SharedActivity) -> Option<T> {
+    match activity.read_bytes(hash).await {
+        Ok(bytes) => match schema::decode::<T>(&bytes) {
+            Ok(Some(value)) => Some(value),
+            Ok(None) => {
+                debug!("Skipping {} from a newer version", type_name::<T>());
+                None
+            }
+            Err(e) => {
+                error!("Failed to deserialize {} {e:?}", type_name::<T>());
+                None
+            }
+        },
+        Err(e) => {
+            error!("Failed to read bytes for {} entry {e:?}", type_name::<T>());
+            None
+        }
+    }
+}
+
+/// Helper function to read a chat message, which is sealed in rooms with a key, logging
+/// any failure.
+async fn read_message(hash: Hash, activity: &SharedActivity) -> Option<ChatMessage> {
+    match activity.read_message(hash).await {
+        Ok(Some(message)) => Some(message),
+        Ok(None) => {
+            debug!("Skipping ChatMessage from a newer version");
+            None
+        }
+        Err(e) => {
+            error!("Failed to read chat message {e:?}");
+            None
+        }
+    }
+}
+
+/// Helper function to send an event to the frontend, logging any failure.
+fn emit_event(app: &AppHandle, event: Event) {
+    if let Err(e) = app.emit("chat-event", &event) {
+        error!("Failed to emit {event:?}: {e}");
+    }
+}
+
+impl Listener {
+    async fn channel_settings(&self) -> ChannelSettings {
+        let channel = self.active_channel.lock().await;
+        channel
+            .as_ref()
+            .map(|channel| ChannelSettings {
+                nickname: channel.nickname().to_string(),
+                room_password: channel.room_password.clone(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// Who is admitted as of the entries processed so far in the batch.
+    async fn admission(&self, batch: &mut Batch) -> anyhow::Result<Arc<Admission>> {
+        if let Some(admission) = &batch.admission {
+            return Ok(admission.clone());
+        }
+        let admission = Arc::new(self.activity.admission().await?);
+        batch.admission = Some(admission.clone());
+        Ok(admission)
+    }
+
+    /// Send who is admitted and moderated once per batch, however many of their entries
+    /// it held. Returns the admission if moderation changed, to check voice peers against.
+    async fn admission_updates(&self, batch: &mut Batch) -> Option<Arc<Admission>> {
+        if !batch.admission_changed && !batch.moderation_changed {
+            return None;
+        }
+        let admission = match self.admission(batch).await {
+            Ok(admission) => admission,
+            Err(e) => {
+                error!("Failed to read admission {e:?}");
+                return None;
+            }
+        };
+        let me = self.activity.node_id();
+        if batch.moderation_changed {
+            let silenced = admission.moderation().silenced();
+            self.chat_index
+                .lock()
+                .await
+                .silence(&self.activity.id(), silenced);
+            let moderation = admission.moderation().view_for(&me);
+            batch.push(Event::ModerationUpdate { moderation });
+        }
+        let view = admission.view_for(&me);
+        batch.push(Event::AdmissionUpdate { admission: view });
+        batch.moderation_changed.then_some(admission)
+    }
+
+    /// Drop voice peers which are no longer admitted or were muted.
+    async fn retain_voice_peers(&self, admission: &Admission) {
+        let channel = self.active_channel.lock().await;
+        if let Some(voice) = channel.as_ref().and_then(|channel| channel.voice.as_ref()) {
+            voice.retain(|peer| {
+                admission.honours_node(peer) && !admission.moderation().is_muted(peer)
+            });
+        }
+    }
+
+    /// Helper function to record a game update as seen by this node, and its result if finished.
+    fn game_updated(&self, game: Game, title: &str, batch: &mut Batch) {
+        info!("Game state updated: version {}", game.version);
+        notify::game_updated(
+            &game,
+            title,
+            &self.activity,
+            self.notifier.as_ref(),
+            &self.app,
+        );
+        batch.delta.game = Some(game.view_for(self.activity.node_id()));
+        if let Some(result) = game.result().cloned() {
+            info!("Game finished: {:?}", result);
+            batch.push(Event::GameFinished { result });
+        }
+    }
+
+    /// Helper function to handle a change to the room's admission entries.
+    async fn process_admission_entry(&self, key: DocKey, hash: Hash, batch: &mut Batch) {
+        match key {
+            DocKey::AdmissionAuthor => return, // bindings only matter once their author writes
+            DocKey::AdmissionRequest => {
+                let request = decode_entry::<Signed>(hash, &self.activity)
+                    .await
+                    .and_then(|signed| signed.verify::<JoinRequest>().ok());
+                if let Some(request) = request {
+                    info!("Join requested by {}", request.node_id);
+                    if batch.channel.room_password.is_some() {
+                        if let Err(e) = self.activity.challenge_joiner(request.node_id).await {
+                            debug!("Not challenging join request: {e}");
+                        }
                     }
+                    batch.push(Event::JoinRequested { request });
                 }
-                Err(e) => error!("Failed to deserialize PeerInfo {e:?}"),
-            },
-            Err(e) => error!("Failed to read bytes for peer entry {e:?}"),
+            }
+            DocKey::AdmissionChallenge => {
+                if let Some(password) = &batch.channel.room_password {
+                    if let Err(e) = self.activity.answer_challenge(password).await {
+                        error!("Failed to answer the host's challenge {e:?}");
+                    }
+                }
+            }
+            DocKey::AdmissionResponse => {
+                let joiner = decode_entry::<Signed>(hash, &self.activity)
+                    .await
+                    .map(|signed| signed.signer);
+                if let (Some(joiner), Some(password)) = (joiner, &batch.channel.room_password) {
+                    if let Err(e) = self
+                        .activity
+                        .review_challenge_response(joiner, password)
+                        .await
+                    {
+                        debug!("Not reviewing challenge response: {e}");
+                    }
+                }
+            }
+            _ => {}
+        }
+        batch.admission = None;
+        batch.admission_changed = true;
+    }
+
+    /// Helper function to handle a role or sanction, which can change what else is honoured.
+    fn process_moderation_entry(&self, batch: &mut Batch) {
+        batch.admission = None;
+        batch.moderation_changed = true;
+    }
+
+    /// Helper function to process an entry, record its updates and index new messages.
+    async fn process_entry_for_updates(&self, entry: &Entry, batch: &mut Batch) {
+        let activity = &self.activity;
+        let hash = entry.content_hash();
+        let key = DocKey::parse(entry.key());
+        info!("Processing {:?} entry", key);
+        if key == DocKey::Unknown {
+            return debug!("Ignoring unknown entry, possibly written by a newer version");
+        }
+        if key.is_admission() {
+            return self.process_admission_entry(key, hash, batch).await;
+        }
+        if key.is_moderation() {
+            return self.process_moderation_entry(batch);
         }
-    } else if key.starts_with(MESSAGES_PREFIX) {
-        debug!("Processing message entry");
-        match channel.activity.read_bytes(hash).await {
-            Ok(bytes) => match postcard::from_bytes::<ChatMessage>(&bytes) {
-                Ok(message) => {
+        let admission = match self.admission(batch).await {
+            Ok(admission) => admission,
+            Err(e) => return error!("Failed to read admission {e:?}"),
+        };
+        if let DocKey::Peer(_) = key {
+            let info = decode_entry::<PeerInfo>(hash, activity)
+                .await
+                .and_then(|info| admission.review_peer(info, &entry.author()));
+            if let Some(info) = info {
+                info!("Peer info updated/received: {:?}", info);
+                batch.delta.peer_updated(info);
+            }
+            return;
+        }
+        if !admission.honours(&entry.author()) {
+            return debug!("Ignoring entry from an author which is not admitted or was removed");
+        }
+        match key {
+            DocKey::Message => {
+                if !admission.can_chat(&entry.author()) {
+                    return debug!("Ignoring message from a muted author");
+                }
+                if let Some(message) = read_message(hash, activity).await {
                     info!("New/updated chat message: {:?}", message);
-                    if let Err(e) = app.emit("chat-event", Event::NewMessage { message }) {
-                        error!("Failed to emit new-message event: {}", e);
-                    }
+                    self.chat_index
+                        .lock()
+                        .await
+                        .insert(activity.id(), message.clone());
+                    let nickname = &batch.channel.nickname;
+                    let notifier = self.notifier.as_ref();
+                    let unread =
+                        notify::message_received(&message, activity, nickname, notifier, &self.app);
+                    batch.unread.messages += unread.messages;
+                    batch.unread.mentions += unread.mentions;
+                    batch.delta.messages.push(message);
                 }
-                Err(e) => error!("Failed to deserialize ChatMessage {e:?}",),
+            }
+            DocKey::RoomMeta => match activity.read_room_meta(&admission).await {
+                Ok(Some(room)) => {
+                    info!("Room metadata updated: {}", room.name);
+                    batch.push(Event::RoomUpdated { room });
+                }
+                Ok(None) => {}
+                Err(e) => error!("Failed to read room metadata {e:?}"),
+            },
+            DocKey::Avatar(node_id) => {
+                if admission.node_of(&entry.author()) != Some(node_id) {
+                    return debug!("Ignoring avatar written for another node");
+                }
+                info!("Avatar of {node_id} ready: {hash}");
+                batch.push(Event::AvatarReady { node_id, hash });
+            }
+            DocKey::Attachment(_) => {
+                info!("Attachment ready: {hash}");
+                batch.push(Event::AttachmentReady { hash });
+            }
+            DocKey::GameState => {
+                if let Some(game) = decode_entry::<Game>(hash, activity).await {
+                    self.game_updated(game, "Your turn", batch);
+                }
+            }
+            DocKey::LegacyGameState => match activity.read_game(&admission).await {
+                Ok(Some(game)) => self.game_updated(game, "Your turn", batch),
+                Ok(None) => {}
+                Err(e) => error!("Failed to read migrated game state {e:?}"),
             },
-            Err(e) => error!("Failed to read bytes for message entry {e:?}",),
+            DocKey::TakebackRequest => {
+                if let Some(request) = decode_entry::<TakebackRequest>(hash, activity).await {
+                    info!("Takeback requested: {:?}", request);
+                    batch.push(Event::TakebackRequested { request });
+                }
+            }
+            DocKey::TakebackResponse => {
+                if let Some(response) = decode_entry::<TakebackResponse>(hash, activity).await {
+                    info!("Takeback answered: {:?}", response);
+                    batch.push(Event::TakebackAnswered { response });
+                }
+            }
+            DocKey::DrawOffer => {
+                if let Some(offer) = decode_entry::<DrawOffer>(hash, activity).await {
+                    info!("Draw offered: {:?}", offer);
+                    batch.push(Event::DrawOffered { offer });
+                }
+            }
+            DocKey::DrawResponse => {
+                if let Some(response) = decode_entry::<DrawResponse>(hash, activity).await {
+                    info!("Draw answered: {:?}", response);
+                    batch.push(Event::DrawAnswered { response });
+                }
+            }
+            DocKey::TournamentGame(match_id) => {
+                if let Some(game) = decode_entry::<Game>(hash, activity).await {
+                    info!("Match {:?} updated: version {}", match_id, game.version);
+                    let title = "Your turn in the tournament";
+                    notify::game_updated(&game, title, activity, self.notifier.as_ref(), &self.app);
+                    let game = game.view_for(activity.node_id());
+                    batch.push(Event::MatchUpdate { match_id, game });
+                }
+            }
+            // settings or a result changed, the bracket and standings are derived from both
+            DocKey::Tournament | DocKey::TournamentResult(_) => {
+                match activity.read_tournament_view(&admission).await {
+                    Ok(Some(tournament)) => {
+                        info!("Tournament updated: complete {}", tournament.complete);
+                        batch.push(Event::TournamentUpdate { tournament });
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to read tournament {e:?}"),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fold a live event into the batch, holding back entries whose content is still missing.
+    async fn process_event(&self, event: LiveEvent, sync: &mut SyncTracker, batch: &mut Batch) {
+        debug!("Received LiveEvent: {:?}", &event);
+        match event {
+            LiveEvent::InsertLocal { entry } => {
+                self.process_entry_for_updates(&entry, batch).await;
+            }
+            LiveEvent::InsertRemote {
+                from,
+                entry,
+                content_status,
+            } => {
+                // Content might be missing initially. read_bytes should attempt to fetch.
+                // If it fails, ContentReady should signal availability later.
+                match content_status {
+                    ContentStatus::Complete => {
+                        sync.received(from, &entry);
+                        self.process_entry_for_updates(&entry, batch).await
+                    }
+                    // attachments are only downloaded once fetched
+                    ContentStatus::Incomplete | ContentStatus::Missing
+                        if matches!(DocKey::parse(entry.key()), DocKey::Attachment(_)) => {}
+                    ContentStatus::Incomplete | ContentStatus::Missing => {
+                        sync.received_pending(from, entry);
+                    }
+                }
+            }
+            LiveEvent::ContentReady { hash } => {
+                debug!("Content ready for hash: {:?}.", hash);
+                let ready = sync.content_ready(&hash);
+                if ready.is_empty() {
+                    // This might happen if ContentReady is for content that wasn't tracked as pending
+                    // (e.g., it was already complete, or it's a blob not directly tied to a doc entry key we track).
+                    debug!("ContentReady for hash {:?} but no matching entry is pending. It might have been processed already or is not a tracked document entry.", hash);
+                }
+                for entry in ready {
+                    self.process_entry_for_updates(&entry, batch).await;
+                }
+            }
+            LiveEvent::SyncFinished(_sync_event) => {
+                batch.delta.sync_finished = true;
+            }
+            LiveEvent::NeighborUp(node_id) => {
+                info!("Neighbor up: {}", node_id);
+                batch.delta.neighbor_changed(node_id, true);
+            }
+            LiveEvent::NeighborDown(node_id) => {
+                info!("Neighbor down: {}", node_id);
+                batch.delta.neighbor_changed(node_id, false);
+            }
+            LiveEvent::PendingContentReady => {
+                info!("{} entries still pending after sync", sync.pending());
+            }
         }
     }
 }
 
 /// Spawns a background task to listen for chat events and emit them to the frontend.
+///
+/// Events are gathered until the stream has been quiet for [`DEBOUNCE`], or for at most
+/// [`MAX_BATCH_DELAY`], then read and sent as a single [`Event::Delta`] numbered by the
+/// cursor, followed by the events which don't merge.
 pub fn spawn_event_listener(
     app: tauri::AppHandle,
+    activity: SharedActivity,
+    cursor: DeltaCursor,
     mut events: BoxStream<anyhow::Result<LiveEvent>>,
     active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
+    chat_index: Arc<TokioMutex<ChatIndex>>,
+    notifier: Arc<dyn NotificationHook>,
 ) -> AbortOnDropHandle<()> {
     AbortOnDropHandle::new(n0_future::task::spawn(async move {
         while active_channel.lock().await.is_none() {
             info!("Waiting for active channel...");
             sleep(Duration::from_secs(1)).await;
         }
-        let mut pending_entries: HashMap<Hash, Vec<u8>> = HashMap::new();
+        let room = activity.id().to_string();
+        let listener = Listener {
+            activity,
+            cursor,
+            active_channel,
+            chat_index,
+            notifier,
+            app,
+        };
+        let mut sync = SyncTracker::default();
+        let mut closed = false;
         while let Some(Ok(event)) = events.next().await {
-            info!("Received LiveEvent: {:?}", &event);
-            let active_channel_guard = active_channel.lock().await;
-            let Some(channel) = active_channel_guard.as_ref() else {
-                panic!("No active channel, should never occur.");
-            };
-            info!("{} pending entries", pending_entries.len());
-            match &event {
-                LiveEvent::InsertLocal { entry } => {
-                    let (key, hash) = (entry.key(), entry.content_hash());
-                    process_entry_for_updates(key, hash, channel, &app).await;
-                }
-                LiveEvent::InsertRemote {
-                    entry,
-                    content_status,
-                    ..
-                } => {
-                    // Content might be missing initially. read_bytes should attempt to fetch.
-                    // If it fails, ContentReady should signal availability later.
-                    let (key, hash) = (entry.key(), entry.content_hash());
-                    match content_status {
-                        ContentStatus::Complete => {
-                            process_entry_for_updates(key, hash, channel, &app).await
-                        }
-                        ContentStatus::Incomplete | ContentStatus::Missing => {
-                            pending_entries.insert(hash, key.to_vec());
-                        }
-                    }
-                }
-                LiveEvent::ContentReady { hash } => {
-                    info!("Content ready for hash: {:?}.", hash);
-                    app.emit("chat-event", Event::ContentReady).ok();
-                    if let Some(key) = pending_entries.remove(hash) {
-                        process_entry_for_updates(&key, *hash, channel, &app).await;
-                    } else {
-                        // This might happen if ContentReady is for content that wasn't tracked as pending
-                        // (e.g., it was already complete, or it's a blob not directly tied to a doc entry key we track).
-                        debug!("ContentReady for hash {:?} but no matching key found in pending_entries. It might have been processed already or is not a tracked document entry.", hash);
+            let mut burst = vec![event];
+            let deadline = Instant::now() + MAX_BATCH_DELAY;
+            loop {
+                let quiet = deadline.min(Instant::now() + DEBOUNCE);
+                match timeout_at(quiet, events.next()).await {
+                    Ok(Some(Ok(event))) => burst.push(event),
+                    Ok(_) => {
+                        closed = true;
+                        break;
                     }
+                    Err(_) => break,
                 }
-                LiveEvent::SyncFinished(_sync_event) => {
-                    app.emit("chat-event", Event::SyncFinished).ok();
-                }
-                LiveEvent::NeighborUp(node_id) => {
-                    info!("Neighbor up: {}", node_id);
-                    let payload = Event::NeighborUp { node_id: *node_id };
-                    app.emit("chat-event", &payload).ok();
-                }
-                LiveEvent::NeighborDown(node_id) => {
-                    info!("Neighbor down: {}", node_id);
-                    let payload = Event::NeighborDown { node_id: *node_id };
-                    app.emit("chat-event", &payload).ok();
-                }
-                LiveEvent::PendingContentReady => {
-                    info!("Pending content ready event received. System is fetching data.");
-                    app.emit("chat-event", Event::PendingContentReady).ok();
-                }
+            }
+            let channel = listener.channel_settings().await;
+            let mut cursor = listener.cursor.0.lock().await;
+            let mut batch = Batch {
+                channel,
+                ..Batch::default()
+            };
+            for event in burst {
+                listener.process_event(event, &mut sync, &mut batch).await;
+            }
+            let moderated = listener.admission_updates(&mut batch).await;
+            if let Some(progress) = sync.take_progress() {
+                batch.push(Event::SyncProgress { progress });
+            }
+            if let Some(summary) = sync.take_summary() {
+                info!("Fetched every missing blob: {summary:?}");
+                batch.push(Event::SyncSummary { summary });
+            }
+            batch.emit(&mut cursor, room.clone(), &listener.app);
+            drop(cursor);
+            if let Some(admission) = moderated {
+                listener.retain_voice_peers(&admission).await;
+            }
+            if closed {
+                break;
             }
         }
     }))
@@ -0,0 +1,252 @@
+//! How far we are in fetching the content of the entries peers sent us.
+//!
+//! Joining a room with a long history, entries arrive long before their content. Each
+//! entry is counted against the peer which sent it, and its size against what is still
+//! outstanding until the content is ready.
+
+use iroh::NodeId;
+use iroh_blobs::Hash;
+use iroh_docs::{AuthorId, Entry};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// An entry's place in the document, which a newer entry from the same author replaces.
+type EntryId = (AuthorId, Vec<u8>);
+
+fn entry_id(entry: &Entry) -> EntryId {
+    (entry.author(), entry.key().to_vec())
+}
+
+/// Entries a peer sent us and how much of their content we have.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerSyncProgress {
+    pub node_id: NodeId,
+    pub entries_received: u64,
+    /// Entries whose content we have, whether it came with them or later
+    pub content_fetched: u64,
+    pub bytes_fetched: u64,
+    /// Blobs we are still waiting for
+    pub blobs_outstanding: u64,
+    pub bytes_outstanding: u64,
+}
+
+impl PeerSyncProgress {
+    fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            entries_received: 0,
+            content_fetched: 0,
+            bytes_fetched: 0,
+            blobs_outstanding: 0,
+            bytes_outstanding: 0,
+        }
+    }
+}
+
+/// Progress of fetching the room's content from every peer which sent us entries.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub peers: Vec<PeerSyncProgress>,
+    pub blobs_outstanding: u64,
+    pub bytes_outstanding: u64,
+}
+
+/// Counts what peers sent us, holding back entries until their content is ready.
+#[derive(Default)]
+pub struct SyncTracker {
+    peers: HashMap<NodeId, PeerSyncProgress>,
+    /// Entries whose content is missing, along with the peer which sent them
+    pending_entries: HashMap<EntryId, (Entry, NodeId)>,
+    /// The pending entries waiting for each blob, as different keys can hold the same content
+    awaiting_content: HashMap<Hash, HashSet<EntryId>>,
+    /// Whether anything was counted since progress was last taken
+    changed: bool,
+    /// Whether content went missing since the last summary
+    awaiting: bool,
+}
+
+impl SyncTracker {
+    fn peer(&mut self, node_id: NodeId) -> &mut PeerSyncProgress {
+        self.changed = true;
+        self.peers
+            .entry(node_id)
+            .or_insert_with(|| PeerSyncProgress::new(node_id))
+    }
+
+    /// Count an entry which arrived along with its content.
+    pub fn received(&mut self, from: NodeId, entry: &Entry) {
+        self.supersede(entry);
+        let peer = self.peer(from);
+        peer.entries_received += 1;
+        peer.content_fetched += 1;
+        peer.bytes_fetched += entry.content_len();
+    }
+
+    /// Hold back an entry until its content is ready.
+    pub fn received_pending(&mut self, from: NodeId, entry: Entry) {
+        let peer = self.peer(from);
+        peer.entries_received += 1;
+        peer.blobs_outstanding += 1;
+        peer.bytes_outstanding += entry.content_len();
+        self.awaiting = true;
+        self.supersede(&entry);
+        let id = entry_id(&entry);
+        self.awaiting_content
+            .entry(entry.content_hash())
+            .or_default()
+            .insert(id.clone());
+        self.pending_entries.insert(id, (entry, from));
+    }
+
+    /// Stop waiting for an older entry the given one replaces, from the same author under
+    /// the same key.
+    fn supersede(&mut self, entry: &Entry) {
+        let Some((old, from)) = self.pending_entries.remove(&entry_id(entry)) else {
+            return;
+        };
+        self.stop_awaiting(&old);
+        let peer = self.peer(from);
+        peer.blobs_outstanding -= 1;
+        peer.bytes_outstanding -= old.content_len();
+    }
+
+    fn stop_awaiting(&mut self, entry: &Entry) {
+        let hash = entry.content_hash();
+        if let Some(waiting) = self.awaiting_content.get_mut(&hash) {
+            waiting.remove(&entry_id(entry));
+            if waiting.is_empty() {
+                self.awaiting_content.remove(&hash);
+            }
+        }
+    }
+
+    /// Count the content as fetched, returning the entries which were waiting for it.
+    pub fn content_ready(&mut self, hash: &Hash) -> Vec<Entry> {
+        let ids = self.awaiting_content.remove(hash).unwrap_or_default();
+        let mut ready = Vec::new();
+        for id in ids {
+            let Some((entry, from)) = self.pending_entries.remove(&id) else {
+                continue;
+            };
+            let peer = self.peer(from);
+            peer.blobs_outstanding -= 1;
+            peer.bytes_outstanding -= entry.content_len();
+            peer.content_fetched += 1;
+            peer.bytes_fetched += entry.content_len();
+            ready.push(entry);
+        }
+        ready
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending_entries.len()
+    }
+
+    fn progress(&self) -> SyncProgress {
+        let mut peers: Vec<_> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|peer| peer.node_id);
+        SyncProgress {
+            blobs_outstanding: peers.iter().map(|peer| peer.blobs_outstanding).sum(),
+            bytes_outstanding: peers.iter().map(|peer| peer.bytes_outstanding).sum(),
+            peers,
+        }
+    }
+
+    /// Progress so far, if anything was counted since it was last taken.
+    pub fn take_progress(&mut self) -> Option<SyncProgress> {
+        std::mem::take(&mut self.changed).then(|| self.progress())
+    }
+
+    /// Once every missing blob has been fetched, what was fetched since the last summary.
+    /// Counting then starts over for the next sync.
+    pub fn take_summary(&mut self) -> Option<SyncProgress> {
+        if !self.awaiting || !self.pending_entries.is_empty() {
+            return None;
+        }
+        let summary = self.progress();
+        self.peers.clear();
+        self.awaiting = false;
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+    use iroh_docs::{
+        sync::{Record, RecordIdentifier},
+        NamespaceId,
+    };
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn entry(author: u8, key: &str, content: &[u8], timestamp: u64) -> Entry {
+        let id = RecordIdentifier::new(
+            NamespaceId::from([0; 32]),
+            AuthorId::from([author; 32]),
+            key,
+        );
+        let record = Record::new(Hash::new(content), content.len() as u64, timestamp);
+        Entry::new(id, record)
+    }
+
+    #[test]
+    fn entries_with_the_same_content_all_wait_for_it() {
+        let peer = node();
+        let mut sync = SyncTracker::default();
+        sync.received_pending(peer, entry(1, "peers/a", b"online", 1));
+        sync.received_pending(peer, entry(1, "peers/b", b"online", 2));
+        assert_eq!(sync.pending(), 2);
+        let progress = sync.take_progress().unwrap();
+        assert_eq!(progress.blobs_outstanding, 2);
+        assert_eq!(progress.bytes_outstanding, 12);
+        assert!(sync.take_summary().is_none());
+
+        let mut ready = sync.content_ready(&Hash::new(b"online"));
+        ready.sort_by_key(|entry| entry.key().to_vec());
+        let keys: Vec<_> = ready.iter().map(|entry| entry.key().to_vec()).collect();
+        assert_eq!(keys, vec![b"peers/a".to_vec(), b"peers/b".to_vec()]);
+        assert!(sync.content_ready(&Hash::new(b"online")).is_empty());
+        assert_eq!(sync.pending(), 0);
+
+        let summary = sync.take_summary().unwrap();
+        assert_eq!(summary.blobs_outstanding, 0);
+        assert_eq!(summary.peers[0].entries_received, 2);
+        assert_eq!(summary.peers[0].content_fetched, 2);
+        assert_eq!(summary.peers[0].bytes_fetched, 12);
+        assert!(sync.take_summary().is_none());
+    }
+
+    #[test]
+    fn newer_entries_replace_pending_ones() {
+        let (first, second) = (node(), node());
+        let mut sync = SyncTracker::default();
+        sync.received_pending(first, entry(1, "game/state", b"old", 1));
+        // the same key from another author is a separate entry
+        sync.received_pending(first, entry(2, "game/state", b"other", 1));
+        sync.received_pending(second, entry(1, "game/state", b"newer", 2));
+        assert_eq!(sync.pending(), 2);
+        assert!(sync.content_ready(&Hash::new(b"old")).is_empty());
+        let progress = sync.take_progress().unwrap();
+        assert_eq!(progress.blobs_outstanding, 2);
+        assert_eq!(progress.bytes_outstanding, 10);
+
+        // a complete entry replaces a pending one too
+        sync.received(first, &entry(2, "game/state", b"complete", 3));
+        assert!(sync.content_ready(&Hash::new(b"other")).is_empty());
+        let ready = sync.content_ready(&Hash::new(b"newer"));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].timestamp(), 2);
+
+        let summary = sync.take_summary().unwrap();
+        assert_eq!(summary.bytes_outstanding, 0);
+        let received: u64 = summary.peers.iter().map(|peer| peer.entries_received).sum();
+        let fetched: u64 = summary.peers.iter().map(|peer| peer.content_fetched).sum();
+        assert_eq!((received, fetched), (4, 2));
+    }
+}
@@ -0,0 +1,221 @@
+//! Opt-in public directory of rooms, shared over a well known gossip topic.
+//!
+//! Hosts periodically broadcast a signed [`RoomAdvert`], and everyone in the lobby keeps
+//! the latest advert from each host until it stops being refreshed.
+
+use crate::{gossip::GossipNode, utils::get_timestamp};
+use anyhow::{anyhow, Context as _};
+use bytes::Bytes;
+use iroh::NodeId;
+use iroh_base::Signature;
+use iroh_gossip::{
+    net::{Event as GossipNetEvent, GossipEvent, GossipSender},
+    proto::TopicId,
+};
+use n0_future::{task::AbortOnDropHandle, StreamExt as _};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter as _};
+use tokio::time::interval;
+use tracing::{debug, info, warn};
+
+use super::event::Event;
+
+/// Name hashed into the lobby's topic id, bump the version if adverts change shape.
+const LOBBY_TOPIC_NAME: &[u8] = b"tauri-multiplayer-game/lobby/v1";
+/// How often a host re-broadcasts its advert.
+pub const ADVERT_INTERVAL: Duration = Duration::from_secs(10);
+/// How long an advert is listed after it was last refreshed.
+pub const ADVERT_TTL: Duration = Duration::from_secs(30);
+
+/// A room offered to the public by its host.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomAdvert {
+    /// Node hosting the room, which signed this advert
+    pub host: NodeId,
+    pub name: String,
+    pub rules: String,
+    pub seats_free: u8,
+    /// Ticket for joining the room as a participant
+    pub ticket: String,
+    /// Read only ticket for watching the room
+    pub spectator_ticket: String,
+    /// microseconds since EPOCH
+    pub timestamp: u64,
+}
+
+/// Wire format for an advert, the signature covers the encoded advert bytes.
+#[derive(Serialize, Deserialize)]
+struct SignedAdvert {
+    advert: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SignedAdvert {
+    fn sign(advert: &RoomAdvert, node: &GossipNode) -> anyhow::Result<Self> {
+        let advert = postcard::to_stdvec(advert)?;
+        let signature = node.sign(&advert).to_bytes().to_vec();
+        Ok(Self { advert, signature })
+    }
+    /// Decode the advert, checking it was signed by the host it names and is still fresh.
+    fn verify(&self) -> anyhow::Result<RoomAdvert> {
+        let advert: RoomAdvert = postcard::from_bytes(&self.advert)?;
+        let signature: [u8; Signature::BYTE_SIZE] = self
+            .signature
+            .as_slice()
+            .try_into()
+            .context("malformed advert signature")?;
+        advert
+            .host
+            .verify(&self.advert, &Signature::from_bytes(&signature))
+            .map_err(|_| anyhow!("advert signature does not match host {}", advert.host))?;
+        let age = get_timestamp().saturating_sub(advert.timestamp);
+        if age > ADVERT_TTL.as_micros() as u64 {
+            return Err(anyhow!("advert from {} has expired", advert.host));
+        }
+        Ok(advert)
+    }
+}
+
+type Listings = Arc<Mutex<HashMap<NodeId, (RoomAdvert, Instant)>>>;
+
+/// Our membership of the lobby topic.
+pub struct Lobby {
+    node: GossipNode,
+    sender: GossipSender,
+    listings: Listings,
+    #[allow(unused)]
+    receiver_handle: AbortOnDropHandle<()>,
+    advertiser: Option<AbortOnDropHandle<()>>,
+}
+
+impl Lobby {
+    /// Join the lobby, bootstrapping from the given nodes.
+    pub async fn join(
+        node: GossipNode,
+        bootstrap: Vec<NodeId>,
+        app: AppHandle,
+    ) -> anyhow::Result<Self> {
+        let topic = TopicId::from_bytes(*blake3::hash(LOBBY_TOPIC_NAME).as_bytes());
+        let (sender, mut receiver) = node.gossip().subscribe(topic, bootstrap)?.split();
+        info!("Joined lobby topic {}", topic);
+        let listings: Listings = Default::default();
+        let receiver_handle = AbortOnDropHandle::new(n0_future::task::spawn({
+            let listings = listings.clone();
+            async move {
+                let mut prune = interval(ADVERT_INTERVAL);
+                loop {
+                    let changed = tokio::select! {
+                        event = receiver.next() => match event {
+                            Some(Ok(GossipNetEvent::Gossip(GossipEvent::Received(message)))) => {
+                                receive_advert(&message.content, &listings)
+                            }
+                            Some(Ok(event)) => {
+                                debug!("Lobby event: {:?}", event);
+                                false
+                            }
+                            Some(Err(e)) => {
+                                warn!("Lobby receiver error: {e}");
+                                false
+                            }
+                            None => break,
+                        },
+                        _ = prune.tick() => prune_listings(&listings),
+                    };
+                    if changed {
+                        let rooms = current_rooms(&listings);
+                        app.emit("chat-event", Event::LobbyUpdate { rooms }).ok();
+                    }
+                }
+                info!("Lobby receiver closed");
+            }
+        }));
+        Ok(Self {
+            node,
+            sender,
+            listings,
+            receiver_handle,
+            advertiser: None,
+        })
+    }
+    /// Return every room currently listed in the lobby.
+    pub fn rooms(&self) -> Vec<RoomAdvert> {
+        prune_listings(&self.listings);
+        current_rooms(&self.listings)
+    }
+    /// Replace the task refreshing our advert, `None` stops advertising.
+    pub fn set_advertiser(&mut self, advertiser: Option<AbortOnDropHandle<()>>) {
+        self.advertiser = advertiser;
+    }
+    /// Return a handle for publishing from a background task.
+    pub fn publisher(&self) -> LobbyPublisher {
+        LobbyPublisher {
+            node: self.node.clone(),
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+/// Cloneable handle for publishing adverts to the lobby.
+#[derive(Clone)]
+pub struct LobbyPublisher {
+    node: GossipNode,
+    sender: GossipSender,
+}
+
+impl LobbyPublisher {
+    /// Sign and broadcast an advert for one of our rooms.
+    pub async fn publish(&self, advert: &RoomAdvert) -> anyhow::Result<()> {
+        let signed = SignedAdvert::sign(advert, &self.node)?;
+        self.sender
+            .broadcast(Bytes::from(postcard::to_stdvec(&signed)?))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Record a received advert, returning whether the listings changed.
+fn receive_advert(content: &[u8], listings: &Listings) -> bool {
+    let advert = match postcard::from_bytes::<SignedAdvert>(content)
+        .map_err(anyhow::Error::from)
+        .and_then(|signed| signed.verify())
+    {
+        Ok(advert) => advert,
+        Err(e) => {
+            warn!("Ignoring lobby advert: {e}");
+            return false;
+        }
+    };
+    let mut listings = listings.lock().expect("lobby listings poisoned");
+    let changed = listings
+        .get(&advert.host)
+        .is_none_or(|(existing, _)| existing.timestamp < advert.timestamp);
+    if changed {
+        debug!("Lobby advert from {}: {}", advert.host, advert.name);
+        listings.insert(advert.host, (advert, Instant::now()));
+    }
+    changed
+}
+
+/// Drop adverts which haven't been refreshed in time, returning whether any were removed.
+fn prune_listings(listings: &Listings) -> bool {
+    let mut listings = listings.lock().expect("lobby listings poisoned");
+    let before = listings.len();
+    listings.retain(|_, (_, received)| received.elapsed() < ADVERT_TTL);
+    listings.len() != before
+}
+
+fn current_rooms(listings: &Listings) -> Vec<RoomAdvert> {
+    let listings = listings.lock().expect("lobby listings poisoned");
+    let mut rooms: Vec<RoomAdvert> = listings
+        .values()
+        .map(|(advert, _)| advert.clone())
+        .collect();
+    rooms.sort_by(|a, b| a.name.cmp(&b.name));
+    rooms
+}
@@ -0,0 +1,141 @@
+//! Keeping the player aware of the room while the app is in the background.
+//!
+//! Messages from others are counted as unread per room, kept in the app's store until
+//! the frontend marks the room read, which it does for the room it shows. While no window
+//! has focus, mentions of us and our turn coming up in a game are also handed to a
+//! [`NotificationHook`], so the app can alert the player in whatever way the platform
+//! allows.
+
+use crate::{
+    game::{Game, PlayerType},
+    gossip::doc::{chat::ChatMessage, SharedActivity},
+    utils::AppStore,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter as _, Manager as _};
+use tracing::{info, warn};
+
+use super::event::Event;
+
+/// Messages in a room which arrived while we were away.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UnreadCount {
+    pub messages: u32,
+    /// Of those messages, how many mention us
+    pub mentions: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// A message mentioning us by nickname or node id
+    Mention,
+    /// A move is awaited from us, in the room's game or a tournament match
+    YourTurn,
+}
+
+/// Something worth interrupting the player for.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    pub kind: NotificationKind,
+    /// ID of the room it happened in
+    pub room: String,
+    pub title: String,
+    pub body: String,
+}
+
+/// Shows notifications to the player.
+pub trait NotificationHook: Send + Sync {
+    fn notify(&self, app: &AppHandle, notification: Notification);
+}
+
+/// Passes notifications to the frontend as events, for it to show as desktop
+/// notifications.
+pub struct FrontendNotifier;
+
+impl NotificationHook for FrontendNotifier {
+    fn notify(&self, app: &AppHandle, notification: Notification) {
+        if let Err(e) = app.emit("chat-event", Event::Notification { notification }) {
+            warn!("Failed to emit notification: {e}");
+        }
+    }
+}
+
+/// Whether the player is looking at the app.
+fn window_focused(app: &AppHandle) -> bool {
+    app.get_webview_window("main")
+        .and_then(|window| window.is_focused().ok())
+        .unwrap_or(false)
+}
+
+/// Work out what a message adds to the room's unread count, nothing for our own, and
+/// notify if it mentions us while the player isn't looking.
+pub(super) fn message_received(
+    message: &ChatMessage,
+    activity: &SharedActivity,
+    nickname: &str,
+    notifier: &dyn NotificationHook,
+    app: &AppHandle,
+) -> UnreadCount {
+    let me = activity.node_id();
+    if message.sender == me {
+        return UnreadCount::default();
+    }
+    let mentioned = message.mentions_node(&me, nickname);
+    if mentioned && !window_focused(app) {
+        info!("Mentioned by {}", message.nickname);
+        notifier.notify(
+            app,
+            Notification {
+                kind: NotificationKind::Mention,
+                room: activity.id().to_string(),
+                title: format!("{} mentioned you", message.nickname),
+                body: message.content.clone(),
+            },
+        );
+    }
+    UnreadCount {
+        messages: 1,
+        mentions: u32::from(mentioned),
+    }
+}
+
+/// Add the messages counted in a burst to the room's stored unread count, returning the
+/// new count.
+pub(super) fn record_unread(
+    room: &str,
+    added: UnreadCount,
+    app: &AppHandle,
+) -> Option<UnreadCount> {
+    AppStore::acquire(app)
+        .and_then(|store| store.record_unread(room, added))
+        .inspect_err(|e| warn!("Failed to count unread messages: {e}"))
+        .ok()
+}
+
+/// Notify the player if a game now awaits their move, unless they are looking.
+pub(super) fn game_updated(
+    game: &Game,
+    title: &str,
+    activity: &SharedActivity,
+    notifier: &dyn NotificationHook,
+    app: &AppHandle,
+) {
+    let me = activity.node_id();
+    let awaits_us = game
+        .to_move()
+        .is_some_and(|seat| seat.node_id == me && seat.role == PlayerType::Human);
+    if !awaits_us || window_focused(app) {
+        return;
+    }
+    notifier.notify(
+        app,
+        Notification {
+            kind: NotificationKind::YourTurn,
+            room: activity.id().to_string(),
+            title: title.to_string(),
+            body: format!("Move {} is yours to play.", game.history.len() + 1),
+        },
+    );
+}
@@ -0,0 +1,375 @@
+//! Local full-text search over the chat history of the rooms stored on this node.
+//!
+//! A room's messages are indexed by the words they contain the first time it is searched,
+//! then kept up to date as messages arrive in the room we are in, so searching again
+//! doesn't read every message back from the blob store. The index lives in memory and
+//! is rebuilt after a restart.
+//!
+//! Messages from senders banned or muted after they were indexed stay in the index but
+//! aren't returned, so lifting the sanction shows them again.
+
+use crate::gossip::doc::chat::ChatMessage;
+use iroh::NodeId;
+use iroh_docs::NamespaceId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::Bound,
+};
+
+/// Matches returned when the query doesn't set a limit.
+pub const DEFAULT_LIMIT: usize = 50;
+/// Most messages returned either side of a match.
+pub const MAX_CONTEXT: usize = 10;
+
+// Messages are ordered by time, the sender telling apart messages sent at the same instant
+type MessageKey = (u64, NodeId);
+
+/// What to look for in the chat history.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchQuery {
+    /// Words which must all appear in a message, each matching the start of a word
+    pub text: String,
+    pub sender: Option<NodeId>,
+    /// Part of the nickname the message was sent under, ignoring case
+    pub nickname: Option<String>,
+    /// Earliest message timestamp, inclusive
+    pub since: Option<u64>,
+    /// Latest message timestamp, inclusive
+    pub until: Option<u64>,
+    /// Search every stored room rather than only the one we are in
+    pub all_rooms: bool,
+    /// Messages to return either side of each match
+    pub context: usize,
+    pub limit: Option<usize>,
+}
+
+impl SearchQuery {
+    /// Check a message against everything but the text.
+    fn accepts(&self, message: &ChatMessage) -> bool {
+        self.sender.is_none_or(|sender| sender == message.sender)
+            && self.nickname.as_ref().is_none_or(|nickname| {
+                message
+                    .nickname
+                    .to_lowercase()
+                    .contains(&nickname.to_lowercase())
+            })
+            && self.since.is_none_or(|since| message.timestamp >= since)
+            && self.until.is_none_or(|until| message.timestamp <= until)
+    }
+}
+
+/// A message matching a search, with the messages around it.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub room: String,
+    pub message: ChatMessage,
+    pub before: Vec<ChatMessage>,
+    pub after: Vec<ChatMessage>,
+}
+
+// Words as indexed, lower case and split on anything but letters and digits
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Default)]
+struct RoomIndex {
+    /// Whether the room's history has been read, rather than only messages since
+    loaded: bool,
+    messages: BTreeMap<MessageKey, ChatMessage>,
+    words: BTreeMap<String, BTreeSet<MessageKey>>,
+    /// Senders currently banned or muted, whose messages aren't returned
+    silenced: HashSet<NodeId>,
+}
+
+impl RoomIndex {
+    fn insert(&mut self, message: ChatMessage) {
+        let key = (message.timestamp, message.sender);
+        if self.messages.contains_key(&key) {
+            return;
+        }
+        for word in words(&message.content) {
+            self.words.entry(word).or_default().insert(key);
+        }
+        self.messages.insert(key, message);
+    }
+
+    fn shown(&self, message: &ChatMessage) -> bool {
+        !self.silenced.contains(&message.sender)
+    }
+
+    /// Messages containing a word which starts with the term.
+    fn matching(&self, term: String) -> BTreeSet<MessageKey> {
+        self.words
+            .range(term.clone()..)
+            .take_while(|(word, _)| word.starts_with(&term))
+            .flat_map(|(_, keys)| keys.iter().copied())
+            .collect()
+    }
+
+    fn search(&self, room: NamespaceId, query: &SearchQuery, limit: usize) -> Vec<SearchHit> {
+        let mut keys: Option<BTreeSet<MessageKey>> = None;
+        for term in words(&query.text) {
+            let matching = self.matching(term);
+            keys = Some(match keys {
+                None => matching,
+                Some(keys) => keys.intersection(&matching).copied().collect(),
+            });
+        }
+        let keys = keys.unwrap_or_else(|| self.messages.keys().copied().collect());
+        let context = query.context.min(MAX_CONTEXT);
+
+        keys.into_iter()
+            .rev()
+            .filter_map(|key| Some((key, self.messages.get(&key)?)))
+            .filter(|(_, message)| self.shown(message) && query.accepts(message))
+            .take(limit)
+            .map(|(key, message)| {
+                let mut before: Vec<_> = self
+                    .messages
+                    .range(..key)
+                    .rev()
+                    .filter(|(_, message)| self.shown(message))
+                    .take(context)
+                    .map(|(_, message)| message.clone())
+                    .collect();
+                before.reverse();
+                let after = self
+                    .messages
+                    .range((Bound::Excluded(key), Bound::Unbounded))
+                    .filter(|(_, message)| self.shown(message))
+                    .take(context)
+                    .map(|(_, message)| message.clone())
+                    .collect();
+                SearchHit {
+                    room: room.to_string(),
+                    message: message.clone(),
+                    before,
+                    after,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Chat messages of the rooms searched so far, indexed by the words they contain.
+#[derive(Default)]
+pub struct ChatIndex {
+    rooms: HashMap<NamespaceId, RoomIndex>,
+}
+
+impl ChatIndex {
+    /// Whether a room's history has been indexed.
+    pub fn is_loaded(&self, room: &NamespaceId) -> bool {
+        self.rooms.get(room).is_some_and(|index| index.loaded)
+    }
+    /// Start indexing a room, so messages arriving while its history is read are kept.
+    pub fn track(&mut self, room: NamespaceId) {
+        self.rooms.entry(room).or_default();
+    }
+    /// Add a room's history to its index.
+    pub fn load(&mut self, room: NamespaceId, history: Vec<ChatMessage>) {
+        let index = self.rooms.entry(room).or_default();
+        for message in history {
+            index.insert(message);
+        }
+        index.loaded = true;
+    }
+    /// Index a new message, unless its room isn't indexed.
+    pub fn insert(&mut self, room: NamespaceId, message: ChatMessage) {
+        if let Some(index) = self.rooms.get_mut(&room) {
+            index.insert(message);
+        }
+    }
+    /// Hide the messages of the senders now banned or muted in a room, showing those of
+    /// anyone no longer sanctioned again.
+    pub fn silence(&mut self, room: &NamespaceId, senders: HashSet<NodeId>) {
+        if let Some(index) = self.rooms.get_mut(room) {
+            index.silenced = senders;
+        }
+    }
+    pub fn forget(&mut self, room: &NamespaceId) {
+        self.rooms.remove(room);
+    }
+    /// Search the given rooms, newest matches first.
+    pub fn search(&self, rooms: &[NamespaceId], query: &SearchQuery) -> Vec<SearchHit> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let mut hits: Vec<_> = rooms
+            .iter()
+            .filter_map(|room| Some((*room, self.rooms.get(room)?)))
+            .flat_map(|(room, index)| index.search(room, query, limit))
+            .collect();
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.message.timestamp));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn message(sender: NodeId, timestamp: u64, content: &str) -> ChatMessage {
+        ChatMessage {
+            sender,
+            nickname: "alice".to_string(),
+            content: content.to_string(),
+            timestamp,
+            attachments: Vec::new(),
+        }
+    }
+
+    fn query(text: &str) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn contents(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter()
+            .map(|hit| hit.message.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn words_match_by_prefix_and_all_must_appear() {
+        let room = NamespaceId::from([1; 32]);
+        let sender = node();
+        let mut index = ChatIndex::default();
+        index.load(
+            room,
+            vec![
+                message(sender, 1, "Good game!"),
+                message(sender, 2, "a good move"),
+                message(sender, 3, "gone for lunch"),
+            ],
+        );
+
+        assert_eq!(
+            contents(&index.search(&[room], &query("go"))),
+            ["gone for lunch", "a good move", "Good game!"]
+        );
+        assert_eq!(
+            contents(&index.search(&[room], &query("GOOD gam"))),
+            ["Good game!"]
+        );
+        assert!(index.search(&[room], &query("goods")).is_empty());
+    }
+
+    #[test]
+    fn queries_filter_and_limit_matches() {
+        let room = NamespaceId::from([1; 32]);
+        let (alice, bob) = (node(), node());
+        let mut index = ChatIndex::default();
+        index.load(
+            room,
+            vec![
+                message(alice, 1, "hello"),
+                ChatMessage {
+                    nickname: "Bobby".to_string(),
+                    ..message(bob, 2, "hello")
+                },
+                message(alice, 3, "hello"),
+            ],
+        );
+
+        let from_bob = SearchQuery {
+            sender: Some(bob),
+            ..query("hello")
+        };
+        assert_eq!(index.search(&[room], &from_bob)[0].message.timestamp, 2);
+        let by_nickname = SearchQuery {
+            nickname: Some("bob".to_string()),
+            ..query("")
+        };
+        assert_eq!(index.search(&[room], &by_nickname)[0].message.sender, bob);
+        let between = SearchQuery {
+            since: Some(2),
+            until: Some(3),
+            limit: Some(1),
+            ..query("hello")
+        };
+        let hits = index.search(&[room], &between);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].message.timestamp, 3);
+    }
+
+    #[test]
+    fn matches_come_with_their_context() {
+        let room = NamespaceId::from([1; 32]);
+        let sender = node();
+        let mut index = ChatIndex::default();
+        index.load(
+            room,
+            (1..=5)
+                .map(|timestamp| message(sender, timestamp, &timestamp.to_string()))
+                .collect(),
+        );
+
+        let around = SearchQuery {
+            context: 1,
+            ..query("3")
+        };
+        let hit = &index.search(&[room], &around)[0];
+        assert_eq!(hit.before[0].content, "2");
+        assert_eq!(hit.after[0].content, "4");
+        assert_eq!((hit.before.len(), hit.after.len()), (1, 1));
+    }
+
+    #[test]
+    fn only_tracked_rooms_index_new_messages() {
+        let (tracked, other) = (NamespaceId::from([1; 32]), NamespaceId::from([2; 32]));
+        let sender = node();
+        let mut index = ChatIndex::default();
+        index.track(tracked);
+        assert!(!index.is_loaded(&tracked));
+        index.insert(tracked, message(sender, 2, "checkmate"));
+        index.insert(tracked, message(sender, 2, "checkmate"));
+        index.insert(other, message(sender, 1, "checkmate"));
+
+        let hits = index.search(&[tracked, other], &query("checkmate"));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].room, tracked.to_string());
+
+        index.forget(&tracked);
+        assert!(index.search(&[tracked], &query("checkmate")).is_empty());
+    }
+
+    #[test]
+    fn sanctioned_senders_are_hidden_until_lifted() {
+        let room = NamespaceId::from([1; 32]);
+        let (alice, mallory) = (node(), node());
+        let mut index = ChatIndex::default();
+        index.load(
+            room,
+            vec![
+                message(alice, 1, "hi all"),
+                message(mallory, 2, "spam all"),
+                message(alice, 3, "bye all"),
+            ],
+        );
+
+        index.silence(&room, HashSet::from([mallory]));
+        let around = SearchQuery {
+            context: 1,
+            ..query("all")
+        };
+        let hits = index.search(&[room], &around);
+        assert_eq!(contents(&hits), ["bye all", "hi all"]);
+        assert_eq!(hits[0].before[0].content, "hi all");
+
+        index.silence(&room, HashSet::new());
+        assert_eq!(index.search(&[room], &query("spam")).len(), 1);
+    }
+}
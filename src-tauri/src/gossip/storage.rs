@@ -0,0 +1,111 @@
+//! Disk usage of the rooms this node has joined, and the policies for reclaiming it.
+//!
+//! Every room joined leaves its document behind in the node's store. Blobs no document
+//! references any more, such as values superseded by a newer write, are collected by
+//! the blob store's garbage collector started in [`GossipNode::spawn`]; whole rooms are
+//! only removed by dropping their document.
+
+use crate::gossip::GossipNode;
+use iroh_docs::{store::Query, NamespaceId};
+use n0_future::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use tracing::info;
+
+/// How often the blob store collects blobs which are no longer referenced.
+pub const GC_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// What to keep on disk, applied when the app starts and when joining a room.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// Rooms not visited for this many days are deleted
+    pub room_max_age_days: Option<u32>,
+    /// Most chat messages of our own to keep per room
+    pub chat_history_cap: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub fn room_max_age(&self) -> Option<Duration> {
+        self.room_max_age_days
+            .map(|days| Duration::from_secs(u64::from(days) * 24 * 60 * 60))
+    }
+}
+
+/// Space taken by one room's document.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomUsage {
+    pub id: String,
+    /// Number of entries, across all authors
+    pub entries: u64,
+    /// Size of the entries' content, before deduplication
+    pub bytes: u64,
+    /// microseconds since EPOCH, if this client recorded a visit
+    pub last_visited: Option<u64>,
+    /// Whether this is the room currently joined, which can't be cleared
+    pub active: bool,
+}
+
+/// Space taken by the node's data.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageReport {
+    pub rooms: Vec<RoomUsage>,
+    /// Size of the node's data directory, including blobs shared between rooms
+    pub total_bytes: u64,
+}
+
+impl GossipNode {
+    /// Return the IDs of every room document stored on this node.
+    pub async fn rooms(&self) -> anyhow::Result<Vec<NamespaceId>> {
+        let mut docs = self.docs.list().await?;
+        let mut rooms = Vec::new();
+        while let Some(doc) = docs.next().await {
+            let (id, _) = doc?;
+            rooms.push(id);
+        }
+        Ok(rooms)
+    }
+
+    /// Count the entries of a room and the size of their content.
+    pub async fn room_usage(&self, id: NamespaceId) -> anyhow::Result<(u64, u64)> {
+        let Some(doc) = self.docs.open(id).await? else {
+            return Ok((0, 0));
+        };
+        let mut entries = doc.get_many(Query::all()).await?;
+        let (mut count, mut bytes) = (0, 0);
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            count += 1;
+            bytes += entry.content_len();
+        }
+        doc.close().await?;
+        Ok((count, bytes))
+    }
+
+    /// Delete a room's document, leaving its blobs to the garbage collector.
+    pub async fn drop_room(&self, id: NamespaceId) -> anyhow::Result<()> {
+        info!("Dropping room {id}");
+        self.docs.drop_doc(id).await
+    }
+
+    /// Return the size of the node's data directory.
+    pub async fn disk_usage(&self) -> anyhow::Result<u64> {
+        let path = self.path.clone();
+        Ok(tokio::task::spawn_blocking(move || dir_size(&path)).await??)
+    }
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += match metadata.is_dir() {
+            true => dir_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+    Ok(size)
+}
@@ -0,0 +1,663 @@
+//! Voice chat between the peers of a room, over a protocol of its own.
+//!
+//! Joining voice calls every online peer of the room on [`VOICE_ALPN`], and peers who
+//! join later call us. Each call opens with a handshake naming the room, which the
+//! callee checks against its own and the room's admission before answering. Audio then
+//! flows as Opus encoded frames in QUIC datagrams, where a late frame is no better than
+//! a lost one. Incoming frames are decoded per peer and mixed, leaving out the peers we
+//! muted; what we capture is only sent while push-to-talk is held or the mic is open.
+
+pub mod audio;
+mod mixer;
+
+use crate::{
+    gossip::{event::Event, GossipNode},
+    state::ActiveChannel,
+};
+use anyhow::anyhow;
+use audio::{AudioBackend, Frame};
+use audiopus::{
+    coder::{Decoder, Encoder},
+    packet::Packet,
+    Application, Channels, MutSignals, SampleRate,
+};
+use bytes::Bytes;
+use iroh::{
+    endpoint::{Connection, VarInt},
+    protocol::ProtocolHandler,
+    NodeId,
+};
+use iroh_docs::NamespaceId;
+use mixer::Mixer;
+use n0_future::{boxed::BoxFuture, task::AbortOnDropHandle};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tauri::{AppHandle, Emitter as _};
+use tokio::{
+    sync::{mpsc, Mutex as TokioMutex},
+    task::JoinSet,
+    time::{interval, timeout},
+};
+use tracing::{debug, info, warn};
+
+/// Protocol voice calls are made on, bump the version if frames change shape.
+pub const VOICE_ALPN: &[u8] = b"tauri-multiplayer-game/voice/0";
+pub const SAMPLE_RATE: u32 = 48_000;
+/// Samples in a frame, 20 ms of mono audio.
+pub const FRAME_SAMPLES: usize = SAMPLE_RATE as usize / 50;
+pub const FRAME_DURATION: Duration = Duration::from_millis(20);
+/// How long a peer has to name the room after calling us.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest Opus packet for a single frame.
+const MAX_PACKET: usize = 1275;
+/// Lost frames in a row filled in by the decoder, beyond that we wait for the next one.
+const MAX_CONCEALED: u32 = 2;
+/// Calls waiting to be answered by the session.
+const INCOMING_BUFFER: usize = 16;
+/// Reply of a callee letting the caller in.
+const ACCEPTED: &[u8] = b"ok";
+
+const CLOSE_LEFT: u32 = 0;
+const CLOSE_REFUSED: u32 = 1;
+const CLOSE_DUPLICATE: u32 = 2;
+
+/// When our microphone is sent to the room.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoiceMode {
+    /// Only while the talk key is held
+    #[default]
+    PushToTalk,
+    /// All the time
+    OpenMic,
+}
+
+/// A peer we are in a call with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VoicePeer {
+    pub node_id: NodeId,
+    /// Whether we muted them, which they can't tell
+    pub muted: bool,
+}
+
+/// Voice chat as seen by this node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceView {
+    pub mode: VoiceMode,
+    /// Whether our microphone is currently sent
+    pub transmitting: bool,
+    pub peers: Vec<VoicePeer>,
+}
+
+/// Wire format for a frame of audio.
+#[derive(Serialize, Deserialize)]
+struct VoiceFrame {
+    /// Counts up from one, telling lost and late frames apart
+    seq: u32,
+    opus: Vec<u8>,
+}
+
+/// Hands incoming calls to the voice session, refusing them when we aren't in voice.
+#[derive(Debug, Clone, Default)]
+pub struct VoiceProtocol {
+    incoming: Arc<Mutex<Option<mpsc::Sender<Connection>>>>,
+}
+
+impl VoiceProtocol {
+    /// Start taking calls, which arrive on the returned channel.
+    fn listen(&self) -> mpsc::Receiver<Connection> {
+        let (sender, receiver) = mpsc::channel(INCOMING_BUFFER);
+        *self.incoming.lock().expect("voice listener poisoned") = Some(sender);
+        receiver
+    }
+
+    fn stop_listening(&self) {
+        self.incoming
+            .lock()
+            .expect("voice listener poisoned")
+            .take();
+    }
+}
+
+impl ProtocolHandler for VoiceProtocol {
+    fn accept(&self, connection: Connection) -> BoxFuture<anyhow::Result<()>> {
+        let incoming = self
+            .incoming
+            .lock()
+            .expect("voice listener poisoned")
+            .clone();
+        Box::pin(async move {
+            match incoming {
+                Some(incoming) => incoming
+                    .send(connection)
+                    .await
+                    .map_err(|_| anyhow!("voice session ended"))?,
+                None => connection.close(VarInt::from_u32(CLOSE_REFUSED), b"not in voice"),
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+struct Controls {
+    mode: VoiceMode,
+    /// Whether the push-to-talk key is held
+    talking: bool,
+    muted: HashSet<NodeId>,
+}
+
+impl Controls {
+    fn transmitting(&self) -> bool {
+        self.mode == VoiceMode::OpenMic || self.talking
+    }
+}
+
+struct Link {
+    connection: Connection,
+    /// Whether we made the call, rather than answered it
+    dialled: bool,
+    _receiver: AbortOnDropHandle<()>,
+}
+
+/// Hands our view of the call to the frontend whenever it changes.
+type UpdateHook = Box<dyn Fn(VoiceView) + Send + Sync>;
+
+/// State shared between the session and its tasks.
+struct Voice {
+    node: GossipNode,
+    room: NamespaceId,
+    on_update: UpdateHook,
+    controls: Mutex<Controls>,
+    links: Mutex<HashMap<NodeId, Link>>,
+    mixer: Mutex<Mixer>,
+}
+
+impl Voice {
+    fn new(node: GossipNode, room: NamespaceId, mode: VoiceMode, on_update: UpdateHook) -> Self {
+        Self {
+            node,
+            room,
+            on_update,
+            controls: Mutex::new(Controls {
+                mode,
+                ..Default::default()
+            }),
+            links: Default::default(),
+            mixer: Default::default(),
+        }
+    }
+
+    fn view(&self) -> VoiceView {
+        let controls = self.controls.lock().expect("voice controls poisoned");
+        let links = self.links.lock().expect("voice links poisoned");
+        let mut peers: Vec<VoicePeer> = links
+            .keys()
+            .map(|node_id| VoicePeer {
+                node_id: *node_id,
+                muted: controls.muted.contains(node_id),
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.node_id);
+        VoiceView {
+            mode: controls.mode,
+            transmitting: controls.transmitting(),
+            peers,
+        }
+    }
+
+    fn emit_update(&self) {
+        (self.on_update)(self.view());
+    }
+
+    /// Start receiving from a peer, keeping one connection if we called each other.
+    fn link(self: &Arc<Self>, peer: NodeId, connection: Connection, dialled: bool) {
+        let mut links = self.links.lock().expect("voice links poisoned");
+        if let Some(existing) = links.get(&peer) {
+            // both ends keep the call made by the lower node id
+            let preferred = existing.dialled == (self.node.node_id() < peer);
+            if existing.connection.close_reason().is_none() && preferred {
+                connection.close(VarInt::from_u32(CLOSE_DUPLICATE), b"duplicate call");
+                return;
+            }
+            existing
+                .connection
+                .close(VarInt::from_u32(CLOSE_DUPLICATE), b"duplicate call");
+        }
+        info!("Voice call with {peer}");
+        let receiver = AbortOnDropHandle::new(n0_future::task::spawn(
+            self.clone().receive(peer, connection.clone()),
+        ));
+        links.insert(
+            peer,
+            Link {
+                connection,
+                dialled,
+                _receiver: receiver,
+            },
+        );
+        drop(links);
+        self.emit_update();
+    }
+
+    /// Forget a peer's connection, unless it has already been replaced.
+    fn unlink(&self, peer: NodeId, stable_id: usize) {
+        let removed = {
+            let mut links = self.links.lock().expect("voice links poisoned");
+            match links.get(&peer) {
+                Some(link) if link.connection.stable_id() == stable_id => links.remove(&peer),
+                _ => None,
+            }
+        };
+        if let Some(link) = removed {
+            info!("Voice call with {peer} ended");
+            link.connection
+                .close(VarInt::from_u32(CLOSE_LEFT), b"call ended");
+            self.mixer
+                .lock()
+                .expect("voice mixer poisoned")
+                .remove(&peer);
+            self.emit_update();
+        }
+    }
+
+    /// Call a peer, who answers if they are in voice and let us in.
+    async fn call(self: Arc<Self>, peer: NodeId) {
+        let answered = async {
+            let connection = self.node.connect(peer, VOICE_ALPN).await?;
+            let (mut send, mut recv) = connection.open_bi().await?;
+            send.write_all(self.room.as_bytes()).await?;
+            send.finish()?;
+            let reply = timeout(HANDSHAKE_TIMEOUT, recv.read_to_end(ACCEPTED.len())).await??;
+            match reply.as_slice() == ACCEPTED {
+                true => anyhow::Ok(connection),
+                false => Err(anyhow!("call refused")),
+            }
+        };
+        match answered.await {
+            Ok(connection) => self.link(peer, connection, true),
+            Err(e) => debug!("{peer} didn't answer voice call: {e}"),
+        }
+    }
+
+    /// Answer a call if it is for our room and the caller may speak in it.
+    async fn answer(
+        self: Arc<Self>,
+        connection: Connection,
+        active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
+    ) {
+        let answered = async {
+            let peer = connection.remote_node_id()?;
+            let (mut send, mut recv) = timeout(HANDSHAKE_TIMEOUT, connection.accept_bi()).await??;
+            let room = timeout(HANDSHAKE_TIMEOUT, recv.read_to_end(32)).await??;
+            if room.as_slice() != self.room.as_bytes() {
+                return Err(anyhow!("{peer} called for another room"));
+            }
+            if !admitted(&active_channel, self.room, &peer).await {
+                return Err(anyhow!("{peer} may not speak in this room"));
+            }
+            send.write_all(ACCEPTED).await?;
+            send.finish()?;
+            anyhow::Ok(peer)
+        };
+        match answered.await {
+            Ok(peer) => self.link(peer, connection, false),
+            Err(e) => {
+                debug!("Refused voice call: {e}");
+                connection.close(VarInt::from_u32(CLOSE_REFUSED), b"refused");
+            }
+        }
+    }
+
+    /// Decode a peer's frames into the mixer until the connection closes.
+    async fn receive(self: Arc<Self>, peer: NodeId, connection: Connection) {
+        let received: anyhow::Result<()> = async {
+            let mut decoder = Decoder::new(SampleRate::Hz48000, Channels::Mono)?;
+            let mut last_seq = 0;
+            loop {
+                let datagram = connection.read_datagram().await?;
+                let frame: VoiceFrame = match postcard::from_bytes(&datagram) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        debug!("Ignoring voice frame from {peer}: {e}");
+                        continue;
+                    }
+                };
+                if frame.seq <= last_seq {
+                    continue; // arrived too late
+                }
+                let lost = (frame.seq - last_seq - 1).min(MAX_CONCEALED);
+                last_seq = frame.seq;
+                for _ in 0..lost {
+                    if let Ok(pcm) = decode(&mut decoder, None) {
+                        self.mixer
+                            .lock()
+                            .expect("voice mixer poisoned")
+                            .push(peer, pcm);
+                    }
+                }
+                match decode(&mut decoder, Some(&frame.opus)) {
+                    Ok(pcm) => self
+                        .mixer
+                        .lock()
+                        .expect("voice mixer poisoned")
+                        .push(peer, pcm),
+                    Err(e) => debug!("Failed to decode voice frame from {peer}: {e}"),
+                }
+            }
+        }
+        .await;
+        if let Err(e) = received {
+            debug!("Voice link with {peer} closed: {e}");
+        }
+        self.unlink(peer, connection.stable_id());
+    }
+
+    /// Encode captured frames and send them to every peer while we are transmitting.
+    async fn transmit(self: Arc<Self>, mut captured: mpsc::Receiver<Frame>) {
+        let encoder = match Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip) {
+            Ok(encoder) => encoder,
+            Err(e) => {
+                warn!("Failed to create voice encoder: {e}");
+                return;
+            }
+        };
+        let mut packet = vec![0; MAX_PACKET];
+        let mut seq = 0;
+        while let Some(frame) = captured.recv().await {
+            if !self
+                .controls
+                .lock()
+                .expect("voice controls poisoned")
+                .transmitting()
+            {
+                continue;
+            }
+            let len = match encoder.encode(&frame, &mut packet) {
+                Ok(len) => len,
+                Err(e) => {
+                    warn!("Failed to encode voice frame: {e}");
+                    continue;
+                }
+            };
+            seq += 1;
+            let frame = VoiceFrame {
+                seq,
+                opus: packet[..len].to_vec(),
+            };
+            let datagram = match postcard::to_stdvec(&frame) {
+                Ok(datagram) => Bytes::from(datagram),
+                Err(e) => {
+                    warn!("Failed to encode voice frame: {e}");
+                    continue;
+                }
+            };
+            for (peer, link) in self.links.lock().expect("voice links poisoned").iter() {
+                if let Err(e) = link.connection.send_datagram(datagram.clone()) {
+                    debug!("Failed to send voice frame to {peer}: {e}");
+                }
+            }
+        }
+        info!("Voice capture ended");
+    }
+
+    /// Mix a frame every frame's duration and hand it to the speakers.
+    async fn play(self: Arc<Self>, output: mpsc::Sender<Frame>) {
+        let mut ticks = interval(FRAME_DURATION);
+        loop {
+            ticks.tick().await;
+            let frame = {
+                let controls = self.controls.lock().expect("voice controls poisoned");
+                self.mixer
+                    .lock()
+                    .expect("voice mixer poisoned")
+                    .mix(&controls.muted)
+            };
+            if output.send(frame).await.is_err() {
+                info!("Voice playback ended");
+                return;
+            }
+        }
+    }
+}
+
+fn decode(decoder: &mut Decoder, opus: Option<&[u8]>) -> anyhow::Result<Frame> {
+    let packet = opus.map(Packet::try_from).transpose()?;
+    let mut pcm = vec![0; FRAME_SAMPLES];
+    let len = decoder.decode(packet, MutSignals::try_from(&mut pcm[..])?, false)?;
+    pcm.truncate(len);
+    pcm.resize(FRAME_SAMPLES, 0);
+    Ok(pcm)
+}
+
+/// Whether a node may speak in the room we are in.
+async fn admitted(
+    active_channel: &TokioMutex<Option<ActiveChannel>>,
+    room: NamespaceId,
+    node_id: &NodeId,
+) -> bool {
+    let channel = active_channel.lock().await;
+    let Some(channel) = channel.as_ref().filter(|c| c.activity.id() == room) else {
+        return false;
+    };
+    match channel.activity.admission().await {
+        Ok(admission) => {
+            admission.honours_node(node_id) && !admission.moderation().is_muted(node_id)
+        }
+        Err(e) => {
+            warn!("Failed to read admission for voice call: {e}");
+            false
+        }
+    }
+}
+
+/// Our part in a room's voice chat, which ends when dropped.
+pub struct VoiceSession {
+    voice: Arc<Voice>,
+    protocol: VoiceProtocol,
+    _tasks: Vec<AbortOnDropHandle<()>>,
+}
+
+impl VoiceSession {
+    /// Join the room's voice chat, calling the given peers and answering anyone else who
+    /// calls while the session lasts.
+    pub fn start(
+        node: GossipNode,
+        room: NamespaceId,
+        peers: Vec<NodeId>,
+        mode: VoiceMode,
+        audio: &dyn AudioBackend,
+        active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
+        app: AppHandle,
+    ) -> anyhow::Result<Self> {
+        let captured = audio.capture()?;
+        let output = audio.playback()?;
+        let protocol = node.voice().clone();
+        let on_update: UpdateHook = Box::new(move |voice| {
+            if let Err(e) = app.emit("chat-event", &Event::VoiceUpdate { voice }) {
+                warn!("Failed to emit voice update: {e}");
+            }
+        });
+        let voice = Arc::new(Voice::new(node, room, mode, on_update));
+        let mut incoming = protocol.listen();
+        let answering = n0_future::task::spawn({
+            let voice = voice.clone();
+            async move {
+                // calls still being answered are abandoned along with the session
+                let mut answering = JoinSet::new();
+                while let Some(connection) = incoming.recv().await {
+                    answering.spawn(voice.clone().answer(connection, active_channel.clone()));
+                    while answering.try_join_next().is_some() {}
+                }
+            }
+        });
+        let mut tasks = vec![
+            AbortOnDropHandle::new(answering),
+            AbortOnDropHandle::new(n0_future::task::spawn(voice.clone().transmit(captured))),
+            AbortOnDropHandle::new(n0_future::task::spawn(voice.clone().play(output))),
+        ];
+        info!("Joined voice chat, calling {} peers", peers.len());
+        for peer in peers {
+            let call = n0_future::task::spawn(voice.clone().call(peer));
+            tasks.push(AbortOnDropHandle::new(call));
+        }
+        Ok(Self {
+            voice,
+            protocol,
+            _tasks: tasks,
+        })
+    }
+
+    pub fn view(&self) -> VoiceView {
+        self.voice.view()
+    }
+
+    pub fn set_mode(&self, mode: VoiceMode) {
+        self.voice
+            .controls
+            .lock()
+            .expect("voice controls poisoned")
+            .mode = mode;
+        self.voice.emit_update();
+    }
+
+    /// Hold or release the push-to-talk key.
+    pub fn set_talking(&self, talking: bool) {
+        self.voice
+            .controls
+            .lock()
+            .expect("voice controls poisoned")
+            .talking = talking;
+        self.voice.emit_update();
+    }
+
+    /// Stop or resume hearing a peer, which only affects what we play.
+    pub fn set_muted(&self, node_id: NodeId, muted: bool) {
+        let mut controls = self.voice.controls.lock().expect("voice controls poisoned");
+        match muted {
+            true => controls.muted.insert(node_id),
+            false => controls.muted.remove(&node_id),
+        };
+        drop(controls);
+        self.voice.emit_update();
+    }
+
+    /// Hang up on the peers who may no longer speak in the room.
+    pub fn retain(&self, mut admitted: impl FnMut(&NodeId) -> bool) {
+        let dropped: Vec<(NodeId, usize)> = self
+            .voice
+            .links
+            .lock()
+            .expect("voice links poisoned")
+            .iter()
+            .filter(|(peer, _)| !admitted(peer))
+            .map(|(peer, link)| (*peer, link.connection.stable_id()))
+            .collect();
+        for (peer, stable_id) in dropped {
+            self.voice.unlink(peer, stable_id);
+        }
+    }
+}
+
+impl Drop for VoiceSession {
+    fn drop(&mut self) {
+        self.protocol.stop_listening();
+        // the links' receivers hold on to the shared state, so they are dropped here
+        let links: Vec<Link> = self
+            .voice
+            .links
+            .lock()
+            .expect("voice links poisoned")
+            .drain()
+            .map(|(_, link)| link)
+            .collect();
+        for link in links {
+            link.connection
+                .close(VarInt::from_u32(CLOSE_LEFT), b"left voice");
+        }
+        info!("Left voice chat");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::testing::{eventually, introduce, spawn_node};
+    use audio::FileAudio;
+    use std::f32::consts::TAU;
+
+    /// Raw PCM of a loud tone, a second long.
+    fn tone() -> Vec<u8> {
+        (0..SAMPLE_RATE)
+            .map(|i| (f32::sin(i as f32 / SAMPLE_RATE as f32 * 440.0 * TAU) * 16_000.0) as i16)
+            .flat_map(i16::to_le_bytes)
+            .collect()
+    }
+
+    /// Run our side of a call with the given audio, as a session does.
+    fn run(voice: &Arc<Voice>, audio: &dyn AudioBackend) -> Vec<AbortOnDropHandle<()>> {
+        let transmit = voice.clone().transmit(audio.capture().unwrap());
+        let play = voice.clone().play(audio.playback().unwrap());
+        vec![
+            AbortOnDropHandle::new(n0_future::task::spawn(transmit)),
+            AbortOnDropHandle::new(n0_future::task::spawn(play)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn what_one_peer_says_the_other_hears() {
+        let (alice_node, _alice_dir) = spawn_node().await;
+        let (bob_node, _bob_dir) = spawn_node().await;
+        introduce(&alice_node, &bob_node).await;
+        let (alice_id, bob_id) = (alice_node.node_id(), bob_node.node_id());
+        let room = NamespaceId::from([7; 32]);
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("said.pcm"), dir.path().join("heard.pcm"));
+        std::fs::write(&input, tone()).unwrap();
+
+        let mut calls = bob_node.voice().listen();
+        let alice = Arc::new(Voice::new(
+            alice_node.clone(),
+            room,
+            VoiceMode::OpenMic,
+            Box::new(|_| {}),
+        ));
+        let bob = Arc::new(Voice::new(
+            bob_node.clone(),
+            room,
+            VoiceMode::OpenMic,
+            Box::new(|_| {}),
+        ));
+        let connection = alice_node.connect(bob_id, VOICE_ALPN).await.unwrap();
+        alice.link(bob_id, connection, true);
+        bob.link(alice_id, calls.recv().await.unwrap(), false);
+
+        // each side hears nothing or captures silence through the null backend
+        let speaking = FileAudio {
+            input: Some(input),
+            output: None,
+        };
+        let listening = FileAudio {
+            input: None,
+            output: Some(output.clone()),
+        };
+        let _alice_tasks = run(&alice, &speaking);
+        let _bob_tasks = run(&bob, &listening);
+
+        eventually(|| async {
+            let heard = std::fs::read(&output).ok()?;
+            heard
+                .chunks_exact(2)
+                .any(|sample| i16::from_le_bytes([sample[0], sample[1]]).abs() > 1_000)
+                .then_some(())
+        })
+        .await;
+        assert_eq!(alice.view().peers.len(), 1);
+        assert_eq!(bob.view().peers[0].node_id, alice_id);
+    }
+}
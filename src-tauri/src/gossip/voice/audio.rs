@@ -0,0 +1,138 @@
+//! Where voice chat audio comes from and goes to.
+//!
+//! Audio moves as frames of [`FRAME_SAMPLES`] mono samples at 48 kHz, whatever
+//! the device, so the transport and the mixer never see the sound card. Besides the
+//! system's devices, built with the `native-audio` feature, frames can be read from and
+//! written to raw PCM files or dropped, which is enough to run voice chat on a headless
+//! machine.
+
+#[cfg(feature = "native-audio")]
+mod device;
+
+use crate::gossip::voice::{FRAME_DURATION, FRAME_SAMPLES};
+#[cfg(feature = "native-audio")]
+pub use device::CpalAudio;
+use std::{path::PathBuf, sync::Arc};
+use tokio::{io::AsyncWriteExt as _, sync::mpsc, time::interval};
+use tracing::{info, warn};
+
+/// Mono 16 bit samples at 48 kHz, [`FRAME_SAMPLES`] long.
+pub type Frame = Vec<i16>;
+
+/// Frames buffered between a device and the voice session, 200 ms.
+const FRAME_BUFFER: usize = 10;
+/// Raw PCM file to capture from instead of the microphone.
+const INPUT_FILE_VAR: &str = "SURVIVAL_VOICE_INPUT";
+/// Raw PCM file to play to instead of the speakers.
+const OUTPUT_FILE_VAR: &str = "SURVIVAL_VOICE_OUTPUT";
+
+/// A source and a sink of audio frames.
+pub trait AudioBackend: Send + Sync {
+    /// Start capturing, frames arrive on the returned channel until it is dropped.
+    fn capture(&self) -> anyhow::Result<mpsc::Receiver<Frame>>;
+    /// Start playing the frames sent on the returned channel, until it is dropped.
+    fn playback(&self) -> anyhow::Result<mpsc::Sender<Frame>>;
+}
+
+/// Pick the audio backend, files if either variable names one, otherwise the system's
+/// devices when built with them.
+pub fn default_backend() -> Arc<dyn AudioBackend> {
+    let input = std::env::var_os(INPUT_FILE_VAR).map(PathBuf::from);
+    let output = std::env::var_os(OUTPUT_FILE_VAR).map(PathBuf::from);
+    if input.is_some() || output.is_some() {
+        info!("Voice audio from {input:?} to {output:?}");
+        return Arc::new(FileAudio { input, output });
+    }
+    #[cfg(feature = "native-audio")]
+    return Arc::new(CpalAudio);
+    #[cfg(not(feature = "native-audio"))]
+    {
+        info!("Built without native audio, voice chat is silent");
+        Arc::new(NullAudio)
+    }
+}
+
+/// Captures silence and plays nothing.
+pub struct NullAudio;
+
+impl AudioBackend for NullAudio {
+    fn capture(&self) -> anyhow::Result<mpsc::Receiver<Frame>> {
+        let (sender, receiver) = mpsc::channel(FRAME_BUFFER);
+        n0_future::task::spawn(async move {
+            let mut ticks = interval(FRAME_DURATION);
+            loop {
+                ticks.tick().await;
+                if sender.send(vec![0; FRAME_SAMPLES]).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    fn playback(&self) -> anyhow::Result<mpsc::Sender<Frame>> {
+        let (sender, mut receiver) = mpsc::channel::<Frame>(FRAME_BUFFER);
+        n0_future::task::spawn(async move { while receiver.recv().await.is_some() {} });
+        Ok(sender)
+    }
+}
+
+/// Reads and writes raw PCM files of mono 16 bit little endian samples at 48 kHz,
+/// standing in for whichever side has no file.
+pub struct FileAudio {
+    /// Played as if spoken into the microphone, once, in real time
+    pub input: Option<PathBuf>,
+    /// Everything we hear is appended to it
+    pub output: Option<PathBuf>,
+}
+
+impl AudioBackend for FileAudio {
+    fn capture(&self) -> anyhow::Result<mpsc::Receiver<Frame>> {
+        let Some(path) = &self.input else {
+            return NullAudio.capture();
+        };
+        let bytes = std::fs::read(path)?;
+        let samples: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        let (sender, receiver) = mpsc::channel(FRAME_BUFFER);
+        n0_future::task::spawn(async move {
+            let mut ticks = interval(FRAME_DURATION);
+            for chunk in samples.chunks(FRAME_SAMPLES) {
+                ticks.tick().await;
+                let mut frame = chunk.to_vec();
+                frame.resize(FRAME_SAMPLES, 0);
+                if sender.send(frame).await.is_err() {
+                    return;
+                }
+            }
+            info!("Voice input file played to the end");
+        });
+        Ok(receiver)
+    }
+
+    fn playback(&self) -> anyhow::Result<mpsc::Sender<Frame>> {
+        let Some(path) = &self.output else {
+            return NullAudio.playback();
+        };
+        let mut file = tokio::fs::File::from_std(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        );
+        let (sender, mut receiver) = mpsc::channel::<Frame>(FRAME_BUFFER);
+        n0_future::task::spawn(async move {
+            while let Some(frame) = receiver.recv().await {
+                let bytes: Vec<u8> = frame.iter().flat_map(|s| s.to_le_bytes()).collect();
+                if let Err(e) = file.write_all(&bytes).await {
+                    warn!("Failed to write voice output file: {e}");
+                    return;
+                }
+            }
+            file.flush().await.ok();
+        });
+        Ok(sender)
+    }
+}
@@ -0,0 +1,230 @@
+//! The system's default microphone and speakers, through cpal.
+//!
+//! Devices are opened in their default configuration, then downmixed and resampled to
+//! and from frames. Streams can't move between threads on every platform, so each one
+//! is built and kept on a thread of its own.
+
+use crate::{
+    error::app_error,
+    gossip::voice::{
+        audio::{AudioBackend, Frame, FRAME_BUFFER},
+        FRAME_SAMPLES, SAMPLE_RATE,
+    },
+};
+use anyhow::anyhow;
+use cpal::{
+    traits::{DeviceTrait as _, HostTrait as _, StreamTrait as _},
+    Device, FromSample, Sample as _, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tracing::{info, warn};
+
+/// How often a stream's thread checks whether the session still wants it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The operating system's default audio devices.
+pub struct CpalAudio;
+
+impl AudioBackend for CpalAudio {
+    fn capture(&self) -> anyhow::Result<mpsc::Receiver<Frame>> {
+        let (sender, receiver) = mpsc::channel(FRAME_BUFFER);
+        let stopped = {
+            let sender = sender.clone();
+            move || sender.is_closed()
+        };
+        spawn_stream(
+            "voice-capture",
+            move || {
+                let device = cpal::default_host()
+                    .default_input_device()
+                    .ok_or_else(|| app_error!(NotFound, "No microphone found."))?;
+                let supported = device.default_input_config()?;
+                info!("Capturing voice in {supported:?}");
+                let config = supported.config();
+                match supported.sample_format() {
+                    SampleFormat::F32 => input_stream::<f32>(&device, &config, sender),
+                    SampleFormat::I16 => input_stream::<i16>(&device, &config, sender),
+                    format => Err(anyhow!("Unsupported microphone sample format {format:?}")),
+                }
+            },
+            stopped,
+        )?;
+        Ok(receiver)
+    }
+
+    fn playback(&self) -> anyhow::Result<mpsc::Sender<Frame>> {
+        let (sender, receiver) = mpsc::channel(FRAME_BUFFER);
+        let closed = Arc::new(AtomicBool::new(false));
+        let stopped = {
+            let closed = closed.clone();
+            move || closed.load(Ordering::Relaxed)
+        };
+        spawn_stream(
+            "voice-playback",
+            move || {
+                let device = cpal::default_host()
+                    .default_output_device()
+                    .ok_or_else(|| app_error!(NotFound, "No speakers found."))?;
+                let supported = device.default_output_config()?;
+                info!("Playing voice in {supported:?}");
+                let config = supported.config();
+                match supported.sample_format() {
+                    SampleFormat::F32 => output_stream::<f32>(&device, &config, receiver, closed),
+                    SampleFormat::I16 => output_stream::<i16>(&device, &config, receiver, closed),
+                    format => Err(anyhow!("Unsupported speaker sample format {format:?}")),
+                }
+            },
+            stopped,
+        )?;
+        Ok(sender)
+    }
+}
+
+/// Build and play a stream on a thread of its own, keeping it until `stopped` says so.
+fn spawn_stream(
+    name: &str,
+    build: impl FnOnce() -> anyhow::Result<Stream> + Send + 'static,
+    stopped: impl Fn() -> bool + Send + 'static,
+) -> anyhow::Result<()> {
+    let (ready_sender, ready) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let started = build().and_then(|stream| {
+                stream.play()?;
+                Ok(stream)
+            });
+            // the stream plays for as long as it is kept
+            let _stream = match started {
+                Ok(stream) => stream,
+                Err(e) => {
+                    ready_sender.send(Err(e)).ok();
+                    return;
+                }
+            };
+            ready_sender.send(Ok(())).ok();
+            while !stopped() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        })?;
+    ready.recv()?
+}
+
+fn input_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    sender: mpsc::Sender<Frame>,
+) -> anyhow::Result<Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = usize::from(config.channels);
+    let mut resampler = Resampler::new(config.sample_rate.0, SAMPLE_RATE);
+    let (mut mono, mut resampled) = (Vec::new(), Vec::new());
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            mono.clear();
+            mono.extend(data.chunks(channels).map(|samples| {
+                samples.iter().map(|s| s.to_sample::<f32>()).sum::<f32>() / samples.len() as f32
+            }));
+            resampler.process(&mono, &mut resampled);
+            while resampled.len() >= FRAME_SAMPLES {
+                let frame = resampled
+                    .drain(..FRAME_SAMPLES)
+                    .map(|s| s.to_sample::<i16>())
+                    .collect();
+                // drop the frame rather than hold up the device if the session is behind
+                sender.try_send(frame).ok();
+            }
+        },
+        |e| warn!("Microphone error: {e}"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn output_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut receiver: mpsc::Receiver<Frame>,
+    closed: Arc<AtomicBool>,
+) -> anyhow::Result<Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = usize::from(config.channels);
+    let mut resampler = Resampler::new(SAMPLE_RATE, config.sample_rate.0);
+    let (mut samples, mut resampled) = (Vec::new(), Vec::new());
+    let mut queued = VecDeque::new();
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            while queued.len() < data.len() / channels {
+                match receiver.try_recv() {
+                    Ok(frame) => {
+                        samples.clear();
+                        samples.extend(frame.iter().map(|s| s.to_sample::<f32>()));
+                        resampler.process(&samples, &mut resampled);
+                        queued.extend(resampled.drain(..));
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        closed.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            }
+            // play silence when the session falls behind
+            for samples in data.chunks_mut(channels) {
+                samples.fill(T::from_sample(queued.pop_front().unwrap_or(0.0)));
+            }
+        },
+        |e| warn!("Speaker error: {e}"),
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Linear resampler for a stream of samples, keeping what it hasn't consumed yet.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample in `pending`
+    position: f64,
+    pending: Vec<f32>,
+}
+
+impl Resampler {
+    fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            step: f64::from(from_rate) / f64::from(to_rate),
+            position: 0.0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Resample the input, appending whatever can be produced so far to the output.
+    fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.pending.extend_from_slice(input);
+        while self.position + 1.0 < self.pending.len() as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (a, b) = (self.pending[index], self.pending[index + 1]);
+            output.push(a + (b - a) * fraction);
+            self.position += self.step;
+        }
+        let consumed = (self.position as usize).min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+}
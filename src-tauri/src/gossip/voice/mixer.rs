@@ -0,0 +1,145 @@
+//! Mixing the voices of several peers into the one frame we play.
+
+use crate::gossip::voice::{audio::Frame, FRAME_SAMPLES};
+use iroh::NodeId;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Frames buffered per peer before the oldest are dropped, 200 ms.
+const MAX_QUEUED_FRAMES: usize = 10;
+/// Frames buffered before a peer starts playing, smoothing over jitter.
+const JITTER_FRAMES: usize = 2;
+
+#[derive(Default)]
+struct PeerQueue {
+    frames: VecDeque<Frame>,
+    /// Whether the peer has buffered enough to play, reset when it runs dry
+    playing: bool,
+}
+
+/// Decoded frames from each peer, waiting to be played together.
+#[derive(Default)]
+pub struct Mixer {
+    peers: HashMap<NodeId, PeerQueue>,
+}
+
+impl Mixer {
+    /// Queue a frame from a peer, dropping its oldest if the peer is too far ahead.
+    pub fn push(&mut self, peer: NodeId, frame: Frame) {
+        let queue = self.peers.entry(peer).or_default();
+        if queue.frames.len() == MAX_QUEUED_FRAMES {
+            queue.frames.pop_front();
+        }
+        queue.frames.push_back(frame);
+    }
+
+    pub fn remove(&mut self, peer: &NodeId) {
+        self.peers.remove(peer);
+    }
+
+    /// Mix the next frame of every peer but the muted ones, silence if nobody is talking.
+    pub fn mix(&mut self, muted: &HashSet<NodeId>) -> Frame {
+        let mut mixed = vec![0i32; FRAME_SAMPLES];
+        for (peer, queue) in self.peers.iter_mut() {
+            if !queue.playing {
+                if queue.frames.len() < JITTER_FRAMES {
+                    continue;
+                }
+                queue.playing = true;
+            }
+            let Some(frame) = queue.frames.pop_front() else {
+                queue.playing = false;
+                continue;
+            };
+            // muted peers keep draining so they don't fall behind
+            if muted.contains(peer) {
+                continue;
+            }
+            for (sum, sample) in mixed.iter_mut().zip(&frame) {
+                *sum += i32::from(*sample);
+            }
+        }
+        mixed
+            .into_iter()
+            .map(|sum| sum.clamp(i16::MIN.into(), i16::MAX.into()) as i16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iroh::SecretKey;
+
+    fn peer() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn frame(sample: i16) -> Frame {
+        vec![sample; FRAME_SAMPLES]
+    }
+
+    /// Queue enough frames for a peer to start playing.
+    fn buffer(mixer: &mut Mixer, peer: NodeId, sample: i16) {
+        for _ in 0..JITTER_FRAMES {
+            mixer.push(peer, frame(sample));
+        }
+    }
+
+    #[test]
+    fn peers_are_summed_and_clamped() {
+        let (alice, bob) = (peer(), peer());
+        let mut mixer = Mixer::default();
+        buffer(&mut mixer, alice, 1_000);
+        buffer(&mut mixer, bob, -300);
+        assert_eq!(mixer.mix(&HashSet::new()), frame(700));
+
+        let mut mixer = Mixer::default();
+        buffer(&mut mixer, alice, 30_000);
+        buffer(&mut mixer, bob, 30_000);
+        assert_eq!(mixer.mix(&HashSet::new()), frame(i16::MAX));
+    }
+
+    #[test]
+    fn muted_peers_are_left_out_but_keep_draining() {
+        let (alice, bob) = (peer(), peer());
+        let mut mixer = Mixer::default();
+        buffer(&mut mixer, alice, 1_000);
+        buffer(&mut mixer, bob, 500);
+        let muted = HashSet::from([bob]);
+        for _ in 0..JITTER_FRAMES {
+            assert_eq!(mixer.mix(&muted), frame(1_000));
+        }
+        // unmuting doesn't play what was said while muted
+        assert_eq!(mixer.mix(&HashSet::new()), frame(0));
+    }
+
+    #[test]
+    fn peers_buffer_again_after_running_dry() {
+        let alice = peer();
+        let mut mixer = Mixer::default();
+        mixer.push(alice, frame(100));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(0), "still buffering");
+        mixer.push(alice, frame(200));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(100));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(200));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(0), "ran dry");
+
+        mixer.push(alice, frame(300));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(0), "buffering again");
+        mixer.push(alice, frame(400));
+        assert_eq!(mixer.mix(&HashSet::new()), frame(300));
+    }
+
+    #[test]
+    fn peers_too_far_ahead_lose_their_oldest_frames() {
+        let alice = peer();
+        let mut mixer = Mixer::default();
+        for sample in 0..MAX_QUEUED_FRAMES as i16 + 3 {
+            mixer.push(alice, frame(sample));
+        }
+        assert_eq!(mixer.mix(&HashSet::new()), frame(3));
+
+        mixer.remove(&alice);
+        assert_eq!(mixer.mix(&HashSet::new()), frame(0));
+    }
+}
@@ -0,0 +1,139 @@
+//! Backing up and restoring the node's secret key, which is the player's identity.
+//!
+//! Backups are JSON files holding the key sealed under the player's passphrase, the same
+//! way as [`crate::vault`] seals data at rest. The identity is kept in the app's store in
+//! the same shape while encryption is enabled.
+
+use crate::{
+    error::app_error,
+    vault::{self, Vault},
+};
+use iroh::{NodeId, SecretKey};
+use ring::aead;
+use serde::{Deserialize, Serialize};
+
+/// Bump when the backup layout or key derivation changes.
+const BACKUP_VERSION: u8 = 1;
+
+/// Contents of an identity backup file.
+#[derive(Serialize, Deserialize)]
+struct IdentityBackup {
+    version: u8,
+    /// Node the sealed key belongs to, to check against after decrypting
+    node_id: NodeId,
+    /// hex encoded
+    salt: String,
+    /// hex encoded
+    nonce: String,
+    /// hex encoded secret key followed by the authentication tag
+    sealed_key: String,
+}
+
+/// Encrypt the secret key with a passphrase, returning the contents of a backup file.
+pub fn export_backup(key: &SecretKey, passphrase: &str) -> anyhow::Result<String> {
+    seal_backup(key, &Vault::new(passphrase)?)
+}
+
+/// Decrypt the secret key from the contents of a backup file.
+pub fn import_backup(contents: &str, passphrase: &str) -> anyhow::Result<SecretKey> {
+    open_backup(contents, &Vault::new(passphrase)?)
+}
+
+/// Encrypt the secret key with the vault's key for the session.
+pub fn seal_backup(key: &SecretKey, vault: &Vault) -> anyhow::Result<String> {
+    let salt = vault.salt();
+    // the backup stores the nonce the sealed key starts with apart
+    let mut nonce = vault::seal(&*vault.key(&salt)?, key.to_bytes().to_vec())?;
+    let sealed = nonce.split_off(aead::NONCE_LEN);
+    let backup = IdentityBackup {
+        version: BACKUP_VERSION,
+        node_id: key.public(),
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        sealed_key: hex::encode(sealed),
+    };
+    Ok(serde_json::to_string_pretty(&backup)?)
+}
+
+/// Decrypt a secret key sealed with the vault's passphrase, in any session.
+pub fn open_backup(contents: &str, vault: &Vault) -> anyhow::Result<SecretKey> {
+    let backup: IdentityBackup = serde_json::from_str(contents)
+        .map_err(|_| app_error!(InvalidInput, "Not an identity backup file."))?;
+    if backup.version != BACKUP_VERSION {
+        return Err(app_error!(
+            UpdateRequired,
+            "Unsupported identity backup version {}.",
+            backup.version
+        ));
+    }
+    let corrupt = |_| app_error!(Corrupt, "Corrupt identity backup.");
+    let salt = hex::decode(&backup.salt).map_err(corrupt)?;
+    let nonce = hex::decode(&backup.nonce).map_err(corrupt)?;
+    let sealed = hex::decode(&backup.sealed_key).map_err(corrupt)?;
+    if nonce.len() != aead::NONCE_LEN {
+        return Err(app_error!(Corrupt, "Corrupt identity backup."));
+    }
+    let key = vault::open(&*vault.key(&salt)?, [nonce, sealed].concat()).map_err(|_| {
+        app_error!(
+            WrongPassphrase,
+            "Wrong passphrase or corrupt identity backup."
+        )
+    })?;
+    let key: [u8; 32] = key
+        .as_slice()
+        .try_into()
+        .map_err(|_| app_error!(Corrupt, "Corrupt identity backup."))?;
+    let key = SecretKey::from_bytes(&key);
+    if key.public() != backup.node_id {
+        return Err(app_error!(
+            Corrupt,
+            "Identity backup does not match its node id."
+        ));
+    }
+    Ok(key)
+}
+
+/// Short, human comparable digest of a node's public key.
+pub fn fingerprint(node_id: &NodeId) -> String {
+    let hash = blake3::hash(node_id.as_bytes());
+    hash.as_bytes()[..8]
+        .chunks(2)
+        .map(hex::encode)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{AppError, ErrorCode},
+        vault::check_passphrase,
+    };
+
+    fn code(error: anyhow::Error) -> ErrorCode {
+        AppError::from(error).code
+    }
+
+    #[test]
+    fn backups_report_why_they_fail() {
+        let key = SecretKey::generate(rand::rngs::OsRng);
+        let backup = export_backup(&key, "correct horse").unwrap();
+        assert_eq!(
+            import_backup(&backup, "correct horse").unwrap().public(),
+            key.public()
+        );
+
+        let wrong = import_backup(&backup, "battery staple").unwrap_err();
+        assert_eq!(code(wrong), ErrorCode::WrongPassphrase);
+        let not_backup = import_backup("{}", "correct horse").unwrap_err();
+        assert_eq!(code(not_backup), ErrorCode::InvalidInput);
+        let newer = backup.replace("\"version\": 1", "\"version\": 2");
+        let newer = import_backup(&newer, "correct horse").unwrap_err();
+        assert_eq!(code(newer), ErrorCode::UpdateRequired);
+        assert_eq!(
+            code(check_passphrase("short").unwrap_err()),
+            ErrorCode::InvalidInput
+        );
+    }
+}
@@ -1,15 +1,41 @@
-use std::str::FromStr;
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
 use crate::{
+    error::{app_error, IpcResult},
+    game::{
+        board::Piece,
+        tournament::{MatchId, TournamentFormat, TournamentView},
+        GameView, Move, PlayerType,
+    },
     gossip::{
-        doc::{chat::ChatMessage, peers::PeerInfo},
-        NodeId,
+        diagnostics::PeerConnection,
+        doc::{
+            admission::{AdmissionSettings, AdmissionView},
+            attachment::Attachment,
+            chat::ChatMessage,
+            game::{DrawOffer, DrawResponse, TakebackRequest, TakebackResponse},
+            moderation::{ModerationView, Role, Sanction},
+            peers::PeerInfo,
+            profile::Profile,
+            room::RoomMeta,
+            ticket::RoomTicket,
+        },
+        lobby::RoomAdvert,
+        notify::UnreadCount,
+        search::{SearchHit, SearchQuery},
+        storage::{RetentionPolicy, StorageReport},
+        voice::{VoiceMode, VoiceView},
+        NetworkMode, NodeId, RelaySettings, RoomSnapshot,
     },
+    identity,
     state::AppContext,
     utils::AppStore,
+    vault::Vault,
 };
-use anyhow::anyhow;
-use iroh_docs::DocTicket;
+use iroh::SecretKey;
+use iroh_blobs::Hash;
+use iroh_docs::NamespaceId;
+use tauri::Manager as _;
 
 #[tauri::command]
 /// Create a new room and return the information required to send
@@ -18,7 +44,7 @@ pub async fn create_room(
     nickname: String,
     state: tauri::State<'_, AppContext>,
     app: tauri::AppHandle,
-) -> tauri::Result<String> {
+) -> IpcResult<String> {
     // Leave any existing room first
     leave_room(state.clone(), app.clone()).await?;
 
@@ -26,7 +52,7 @@ pub async fn create_room(
     store.set_nickname(&nickname)?;
 
     // Store the active channel info
-    state.start_channel(None, &app, &nickname).await?;
+    state.start_channel(None, &app, &nickname, None).await?;
 
     // Get the topic_id from the established channel for logging
     let topic_id_str = state.get_topic_id().await?;
@@ -44,16 +70,18 @@ pub async fn create_room(
 pub async fn join_room(
     ticket: String,
     nickname: String,
+    password: Option<String>,
     state: tauri::State<'_, AppContext>,
     app: tauri::AppHandle,
-) -> tauri::Result<()> {
+) -> IpcResult<()> {
     // Leave any existing room first
     leave_room(state.clone(), app.clone()).await?;
 
-    let ticket =
-        DocTicket::from_str(&ticket).map_err(|e| anyhow!("Invalid activity ticket: {}", e))?;
+    let ticket = RoomTicket::from_str(&ticket)?;
     // Store the active channel info
-    state.start_channel(Some(ticket), &app, &nickname).await?;
+    state
+        .start_channel(Some(ticket), &app, &nickname, password.as_deref())
+        .await?;
 
     // Get the topic_id from the established channel for logging
     let topic_id_str = state.get_topic_id().await?;
@@ -68,17 +96,63 @@ pub async fn join_room(
 }
 
 #[tauri::command]
-/// Send a message to the room
+/// Send a message to the room, with any files attached beforehand
 pub async fn send_message(
     message: String,
+    attachments: Option<Vec<Attachment>>,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<()> {
+    Ok(state
+        .send_message(&message, attachments.unwrap_or_default())
+        .await?)
+}
+
+#[tauri::command]
+/// Store a file in the room, returning the attachment to send with a message
+pub async fn attach_file(
+    name: String,
+    mime: String,
+    data: Vec<u8>,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Attachment> {
+    Ok(state.attach_file(&name, &mime, data).await?)
+}
+
+#[tauri::command]
+/// Return an attachment's content as raw bytes, downloading it if needed
+pub async fn fetch_attachment(
+    hash: Hash,
     state: tauri::State<'_, AppContext>,
-) -> tauri::Result<()> {
-    Ok(state.send_message(&message).await?)
+) -> IpcResult<tauri::ipc::Response> {
+    let bytes = state.fetch_attachment(hash).await?;
+    Ok(tauri::ipc::Response::new(bytes.to_vec()))
+}
+
+#[tauri::command]
+/// Search the chat history of the current room, or of every stored room.
+pub async fn search_messages(
+    query: SearchQuery,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Vec<SearchHit>> {
+    Ok(state.search_messages(query).await?)
+}
+
+#[tauri::command]
+/// Get the number of unread messages and mentions in each room which has any.
+pub async fn get_unread_counts(app: tauri::AppHandle) -> IpcResult<HashMap<String, UnreadCount>> {
+    Ok(AppStore::acquire(&app)?.get_unread())
+}
+
+#[tauri::command]
+/// Mark every message of a room as read.
+pub async fn mark_room_read(id: String, app: tauri::AppHandle) -> IpcResult<()> {
+    AppStore::acquire(&app)?.mark_read(&id)?;
+    Ok(())
 }
 
 #[tauri::command]
 /// Set a new nickname for this node.
-pub async fn set_nickname(nickname: String, app: tauri::AppHandle) -> tauri::Result<()> {
+pub async fn set_nickname(nickname: String, app: tauri::AppHandle) -> IpcResult<()> {
     tracing::info!("Nickname set to: {}", &nickname);
     AppStore::acquire(&app)?.set_nickname(&nickname)?;
     Ok(())
@@ -86,15 +160,142 @@ pub async fn set_nickname(nickname: String, app: tauri::AppHandle) -> tauri::Res
 
 #[tauri::command]
 /// Get the stored nickname for this node.
-pub async fn get_nickname(app: tauri::AppHandle) -> tauri::Result<Option<String>> {
+pub async fn get_nickname(app: tauri::AppHandle) -> IpcResult<Option<String>> {
     Ok(AppStore::acquire(&app)?.get_nickname())
 }
 
 #[tauri::command]
-/// Get the stored room ticket string
-pub async fn get_latest_ticket(
+/// Get a player's profile in the current room, or our own when no node is given.
+pub async fn get_profile(
+    node_id: Option<NodeId>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<Option<Profile>> {
+    Ok(state
+        .get_profile(node_id, &AppStore::acquire(&app)?)
+        .await?)
+}
+
+#[tauri::command]
+/// Set our bio, favourite side and rating, shown in every room we join.
+pub async fn set_profile(
+    bio: String,
+    favourite_side: Piece,
+    rating: Option<u32>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<Profile> {
+    let store = AppStore::acquire(&app)?;
+    Ok(state
+        .set_profile(&bio, favourite_side, rating, &store)
+        .await?)
+}
+
+#[tauri::command]
+/// Replace our avatar with an image, or remove it when no data is given.
+pub async fn set_avatar(
+    data: Option<Vec<u8>>,
+    mime: Option<String>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<Profile> {
+    let image = data.map(|data| (data, mime.unwrap_or_default()));
+    Ok(state.set_avatar(image, &AppStore::acquire(&app)?).await?)
+}
+
+#[tauri::command]
+/// Get a player's avatar image as raw bytes, our own when no node is given.
+///
+/// Empty when they have no avatar or it hasn't been downloaded yet.
+pub async fn get_avatar(
+    node_id: Option<NodeId>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<tauri::ipc::Response> {
+    let image = state.get_avatar(node_id, &AppStore::acquire(&app)?).await?;
+    Ok(tauri::ipc::Response::new(
+        image.unwrap_or_default().to_vec(),
+    ))
+}
+
+#[tauri::command]
+/// Get the networking mode the node is currently running in.
+pub async fn get_network_mode(state: tauri::State<'_, AppContext>) -> IpcResult<NetworkMode> {
+    Ok(state.node().await.network_mode())
+}
+
+#[tauri::command]
+/// Choose the networking mode, which takes effect the next time the app starts.
+pub async fn set_network_mode(mode: NetworkMode, app: tauri::AppHandle) -> IpcResult<()> {
+    tracing::info!("Network mode set to: {:?}", mode);
+    AppStore::acquire(&app)?.set_network_mode(mode)?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get the relay servers the node is currently using.
+pub async fn get_relay_settings(state: tauri::State<'_, AppContext>) -> IpcResult<RelaySettings> {
+    Ok(state.node().await.relay_settings().clone())
+}
+
+#[tauri::command]
+/// Change the relay servers, restarting the node and leaving the current room.
+pub async fn set_relay_settings(
+    relays: RelaySettings,
     state: tauri::State<'_, AppContext>,
-) -> tauri::Result<Option<String>> {
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    tracing::info!("Relays set to: {:?}", relays);
+    state.set_relay_settings(relays.clone()).await?;
+    AppStore::acquire(&app)?.set_relay_settings(&relays)?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get what is kept on disk.
+pub async fn get_retention(app: tauri::AppHandle) -> IpcResult<RetentionPolicy> {
+    Ok(AppStore::acquire(&app)?.get_retention())
+}
+
+#[tauri::command]
+/// Change what is kept on disk, deleting rooms which fall outside the new policy.
+pub async fn set_retention(
+    policy: RetentionPolicy,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    tracing::info!("Retention set to: {:?}", policy);
+    let store = AppStore::acquire(&app)?;
+    store.set_retention(&policy)?;
+    state.apply_retention(&store).await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Report the disk space taken by each stored room.
+pub async fn get_storage_usage(
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<StorageReport> {
+    Ok(state.storage_usage(&AppStore::acquire(&app)?).await?)
+}
+
+#[tauri::command]
+/// Delete a stored room other than the one currently joined.
+pub async fn clear_room(
+    id: String,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    let id = NamespaceId::from_str(&id)
+        .map_err(|e| app_error!(InvalidInput, "Invalid room id: {}", e))?;
+    state.clear_room(id, &AppStore::acquire(&app)?).await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get the stored room ticket string
+pub async fn get_latest_ticket(state: tauri::State<'_, AppContext>) -> IpcResult<Option<String>> {
     let ticket = state.generate_ticket().await.ok();
     Ok(ticket)
 }
@@ -104,7 +305,7 @@ pub async fn get_latest_ticket(
 pub async fn leave_room(
     state: tauri::State<'_, AppContext>,
     _app: tauri::AppHandle,
-) -> tauri::Result<()> {
+) -> IpcResult<()> {
     if let Some(id) = state.drop_channel().await? {
         tracing::info!("Left room: {}", id);
     };
@@ -113,15 +314,149 @@ pub async fn leave_room(
 
 #[tauri::command]
 /// Returns the node id of this node
-pub async fn get_node_id(state: tauri::State<'_, AppContext>) -> tauri::Result<NodeId> {
-    Ok(state.node.node_id())
+pub async fn get_node_id(state: tauri::State<'_, AppContext>) -> IpcResult<NodeId> {
+    Ok(state.node_id)
 }
 
 #[tauri::command]
-/// Read Message Log
-pub async fn get_message_log(
+/// Returns a short fingerprint of this node's identity, for players to compare
+pub async fn get_identity_fingerprint(state: tauri::State<'_, AppContext>) -> IpcResult<String> {
+    Ok(identity::fingerprint(&state.node_id))
+}
+
+#[tauri::command]
+/// Save the stored identity key to a backup file, encrypted with the passphrase.
+/// After a rotation or import this is the key used from the next startup.
+pub async fn export_identity(
+    path: PathBuf,
+    passphrase: String,
     state: tauri::State<'_, AppContext>,
-) -> tauri::Result<Vec<ChatMessage>> {
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    let key = AppStore::acquire(&app)?.stored_secret_key(state.vault().await.as_ref())?;
+    // deriving the key from a new passphrase is slow, keep it off the runtime
+    let backup =
+        tauri::async_runtime::spawn_blocking(move || identity::export_backup(&key, &passphrase))
+            .await??;
+    tokio::fs::write(&path, backup).await?;
+    tracing::info!("Exported identity to {}", path.display());
+    Ok(())
+}
+
+#[tauri::command]
+/// Restore the identity key from a backup file, used from the next startup.
+/// The replaced key is kept, see [`restore_previous_identity`].
+/// Returns the fingerprint of the restored identity.
+pub async fn import_identity(
+    path: PathBuf,
+    passphrase: String,
+    app: tauri::AppHandle,
+) -> IpcResult<String> {
+    let contents = tokio::fs::read_to_string(&path).await?;
+    let key = tauri::async_runtime::spawn_blocking(move || {
+        identity::import_backup(&contents, &passphrase)
+    })
+    .await??;
+    AppStore::acquire(&app)?.replace_secret_key(&key, current_vault(&app).await?.as_ref())?;
+    tracing::info!("Imported identity {}", key.public());
+    Ok(identity::fingerprint(&key.public()))
+}
+
+#[tauri::command]
+/// Replace the identity key with a new one, used from the next startup.
+/// The replaced key is kept, see [`restore_previous_identity`].
+/// Returns the fingerprint of the new identity.
+pub async fn rotate_identity(app: tauri::AppHandle) -> IpcResult<String> {
+    let key = SecretKey::generate(rand::rngs::OsRng);
+    AppStore::acquire(&app)?.replace_secret_key(&key, current_vault(&app).await?.as_ref())?;
+    tracing::info!("Rotated identity to {}", key.public());
+    Ok(identity::fingerprint(&key.public()))
+}
+
+#[tauri::command]
+/// Undo the last rotation or import, used from the next startup.
+/// Returns the fingerprint of the restored identity.
+pub async fn restore_previous_identity(app: tauri::AppHandle) -> IpcResult<String> {
+    let store = AppStore::acquire(&app)?;
+    let key = store.restore_previous_secret_key(current_vault(&app).await?.as_ref())?;
+    tracing::info!("Restored previous identity {}", key.public());
+    Ok(identity::fingerprint(&key.public()))
+}
+
+/// Make a vault for the passphrase, deriving the key it seals with for the session off the
+/// runtime, as that is deliberately slow.
+async fn new_vault(passphrase: String) -> anyhow::Result<Vault> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let vault = Vault::new(&passphrase)?;
+        vault.key(&vault.salt())?;
+        Ok(vault)
+    })
+    .await?
+}
+
+/// The vault to seal a new identity key with, which needs the app unlocked when encrypted.
+async fn current_vault(app: &tauri::AppHandle) -> anyhow::Result<Option<Vault>> {
+    match app.try_state::<AppContext>() {
+        Some(state) => Ok(state.vault().await),
+        None if AppStore::acquire(app)?.is_encrypted() => Err(app_error!(
+            Locked,
+            "Unlock the app before changing its identity."
+        )),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+/// Whether the app is waiting for the passphrase before it can start.
+pub async fn is_locked(app: tauri::AppHandle) -> IpcResult<bool> {
+    Ok(app.try_state::<AppContext>().is_none() && AppStore::acquire(&app)?.is_encrypted())
+}
+
+#[tauri::command]
+/// Decrypt the identity and room keys with the passphrase, then start the node.
+pub async fn unlock(passphrase: String, app: tauri::AppHandle) -> IpcResult<()> {
+    if app.try_state::<AppContext>().is_some() {
+        return Err(app_error!(NotPermitted, "Already unlocked.").into());
+    }
+    let vault = new_vault(passphrase).await?;
+    let key = AppStore::acquire(&app)?.unlock_secret_key(&vault)?;
+    tracing::info!("Unlocked identity {}", key.public());
+    crate::start_node(&app, key, Some(vault)).await?;
+    Ok(())
+}
+
+#[tauri::command]
+/// Encrypt the identity and the keys of stored rooms with a passphrase.
+pub async fn enable_encryption(
+    passphrase: String,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    let vault = new_vault(passphrase).await?;
+    state
+        .set_vault(Some(vault), &AppStore::acquire(&app)?)
+        .await?;
+    tracing::info!("Encryption enabled");
+    Ok(())
+}
+
+#[tauri::command]
+/// Store the identity and room keys in plaintext again, after checking the passphrase.
+pub async fn disable_encryption(
+    passphrase: String,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    let store = AppStore::acquire(&app)?;
+    store.unlock_secret_key(&Vault::new(&passphrase)?)?;
+    state.set_vault(None, &store).await?;
+    tracing::info!("Encryption disabled");
+    Ok(())
+}
+
+#[tauri::command]
+/// Read Message Log
+pub async fn get_message_log(state: tauri::State<'_, AppContext>) -> IpcResult<Vec<ChatMessage>> {
     let msgs = state.get_message_log().await?;
     tracing::info!("message log: {:?}", msgs);
     Ok(msgs)
@@ -129,8 +464,343 @@ pub async fn get_message_log(
 
 #[tauri::command]
 /// Get the peers list
-pub async fn get_peers(state: tauri::State<'_, AppContext>) -> tauri::Result<Vec<PeerInfo>> {
+pub async fn get_peers(state: tauri::State<'_, AppContext>) -> IpcResult<Vec<PeerInfo>> {
     let peers = state.get_peers().await?;
     tracing::info!("peers: {:?}", peers);
     Ok(peers)
 }
+
+#[tauri::command]
+/// Get how joiners are admitted to the room, and who is waiting
+pub async fn get_admission(state: tauri::State<'_, AppContext>) -> IpcResult<AdmissionView> {
+    Ok(state.get_admission().await?)
+}
+
+#[tauri::command]
+/// Change how joiners are admitted to the room we host, with an optional room password
+pub async fn set_admission(
+    approval_required: bool,
+    password: Option<String>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<AdmissionSettings> {
+    let password = password.filter(|password| !password.is_empty());
+    Ok(state
+        .set_admission(approval_required, password, &AppStore::acquire(&app)?)
+        .await?)
+}
+
+#[tauri::command]
+/// Let a node waiting to join into the room we host
+pub async fn approve_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.decide_admission(node_id, true).await?)
+}
+
+#[tauri::command]
+/// Turn away a node waiting to join the room we host
+pub async fn reject_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.decide_admission(node_id, false).await?)
+}
+
+#[tauri::command]
+/// Get the name, description and rules of the room
+pub async fn get_room_meta(state: tauri::State<'_, AppContext>) -> IpcResult<Option<RoomMeta>> {
+    Ok(state.get_room_meta().await?)
+}
+
+#[tauri::command]
+/// Describe the room we host
+pub async fn set_room_meta(
+    name: String,
+    description: String,
+    variant: String,
+    rules: String,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<RoomMeta> {
+    Ok(state
+        .set_room_meta(&name, &description, &variant, &rules)
+        .await?)
+}
+
+#[tauri::command]
+/// Get the moderators and sanctioned nodes of the room, and our own role
+pub async fn get_moderation(state: tauri::State<'_, AppContext>) -> IpcResult<ModerationView> {
+    Ok(state.get_moderation().await?)
+}
+
+#[tauri::command]
+/// Appoint or dismiss a moderator of the room we host
+pub async fn set_moderator(
+    node_id: NodeId,
+    moderator: bool,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<()> {
+    let role = if moderator {
+        Role::Moderator
+    } else {
+        Role::Member
+    };
+    Ok(state.set_role(node_id, role).await?)
+}
+
+#[tauri::command]
+/// End a node's session in the room, it can join again unless approval is required
+pub async fn kick_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.moderate(node_id, Sanction::Kick).await?)
+}
+
+#[tauri::command]
+/// Ignore everything a node writes to the room until it is unbanned
+pub async fn ban_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.moderate(node_id, Sanction::Ban).await?)
+}
+
+#[tauri::command]
+/// Lift a ban
+pub async fn unban_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.moderate(node_id, Sanction::Unban).await?)
+}
+
+#[tauri::command]
+/// Hide a node's chat messages until it is unmuted
+pub async fn mute_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.moderate(node_id, Sanction::Mute).await?)
+}
+
+#[tauri::command]
+/// Lift a mute
+pub async fn unmute_peer(node_id: NodeId, state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.moderate(node_id, Sanction::Unmute).await?)
+}
+
+#[tauri::command]
+/// Get the connection quality to each peer in the room
+pub async fn get_connections(
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Vec<PeerConnection>> {
+    Ok(state.get_connections().await?)
+}
+
+#[tauri::command]
+/// Join the room's voice chat, or switch mode if already in it
+pub async fn join_voice(
+    mode: VoiceMode,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<VoiceView> {
+    Ok(state.join_voice(mode, &app).await?)
+}
+
+#[tauri::command]
+/// Hang up every voice call in the room
+pub async fn leave_voice(state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    state.leave_voice().await;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get the room's voice chat, if we joined it
+pub async fn get_voice(state: tauri::State<'_, AppContext>) -> IpcResult<Option<VoiceView>> {
+    Ok(state.get_voice().await?)
+}
+
+#[tauri::command]
+/// Choose between push-to-talk and an open mic
+pub async fn set_voice_mode(
+    mode: VoiceMode,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<VoiceView> {
+    Ok(state.set_voice_mode(mode).await?)
+}
+
+#[tauri::command]
+/// Press or release the push-to-talk key
+pub async fn set_talking(
+    talking: bool,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<VoiceView> {
+    Ok(state.set_talking(talking).await?)
+}
+
+#[tauri::command]
+/// Stop or resume hearing a peer in voice chat
+pub async fn mute_voice_peer(
+    node_id: NodeId,
+    muted: bool,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<VoiceView> {
+    Ok(state.mute_voice_peer(node_id, muted).await?)
+}
+
+#[tauri::command]
+/// Get the current game state
+pub async fn get_game(state: tauri::State<'_, AppContext>) -> IpcResult<Option<GameView>> {
+    Ok(state.get_game().await?)
+}
+
+#[tauri::command]
+/// Get the peers, messages, game and metadata of the current room at once, along with the
+/// number of the last delta event they include
+pub async fn get_room_snapshot(state: tauri::State<'_, AppContext>) -> IpcResult<RoomSnapshot> {
+    Ok(state.get_room_snapshot().await?)
+}
+
+#[tauri::command]
+/// Take a seat in the current room's game, as a player or spectator
+pub async fn take_seat(
+    role: PlayerType,
+    piece: Piece,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<GameView> {
+    Ok(state.take_seat(role, piece).await?)
+}
+
+#[tauri::command]
+/// Start a new game with the seated players
+pub async fn start_game(state: tauri::State<'_, AppContext>) -> IpcResult<GameView> {
+    Ok(state.start_game().await?)
+}
+
+#[tauri::command]
+/// Place our next piece on the board
+pub async fn play_move(x: usize, y: usize, state: tauri::State<'_, AppContext>) -> IpcResult<Move> {
+    Ok(state.play_move(x, y).await?)
+}
+
+#[tauri::command]
+/// Ask the opponent to undo our last move
+pub async fn request_takeback(state: tauri::State<'_, AppContext>) -> IpcResult<TakebackRequest> {
+    Ok(state.request_takeback().await?)
+}
+
+#[tauri::command]
+/// Get the outstanding takeback request, if any
+pub async fn get_takeback_request(
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Option<TakebackRequest>> {
+    Ok(state.get_takeback_request().await?)
+}
+
+#[tauri::command]
+/// Accept or decline the opponent's takeback request
+pub async fn respond_takeback(
+    accept: bool,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<TakebackResponse> {
+    Ok(state.respond_takeback(accept).await?)
+}
+
+#[tauri::command]
+/// Concede the current game
+pub async fn resign(state: tauri::State<'_, AppContext>) -> IpcResult<GameView> {
+    Ok(state.resign().await?)
+}
+
+#[tauri::command]
+/// Call off the current game before the first move
+pub async fn abort_game(state: tauri::State<'_, AppContext>) -> IpcResult<GameView> {
+    Ok(state.abort_game().await?)
+}
+
+#[tauri::command]
+/// Offer the opponent a draw
+pub async fn offer_draw(state: tauri::State<'_, AppContext>) -> IpcResult<DrawOffer> {
+    Ok(state.offer_draw().await?)
+}
+
+#[tauri::command]
+/// Get the outstanding draw offer, if any
+pub async fn get_draw_offer(state: tauri::State<'_, AppContext>) -> IpcResult<Option<DrawOffer>> {
+    Ok(state.get_draw_offer().await?)
+}
+
+#[tauri::command]
+/// Accept or decline the opponent's draw offer
+pub async fn respond_draw(
+    accept: bool,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<DrawResponse> {
+    Ok(state.respond_draw(accept).await?)
+}
+
+#[tauri::command]
+/// Start a tournament between everyone online in the current room
+pub async fn start_tournament(
+    format: TournamentFormat,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<TournamentView> {
+    Ok(state.start_tournament(format).await?)
+}
+
+#[tauri::command]
+/// Get the current room's tournament bracket and standings
+pub async fn get_tournament(
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Option<TournamentView>> {
+    Ok(state.get_tournament().await?)
+}
+
+#[tauri::command]
+/// Get the game for one tournament match
+pub async fn get_match_game(
+    match_id: MatchId,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Option<GameView>> {
+    Ok(state.get_match_game(match_id).await?)
+}
+
+#[tauri::command]
+/// Place our next piece in one of our tournament matches
+pub async fn play_match_move(
+    match_id: MatchId,
+    x: usize,
+    y: usize,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<Move> {
+    Ok(state.play_match_move(match_id, x, y).await?)
+}
+
+#[tauri::command]
+/// Concede one of our tournament matches
+pub async fn resign_match(
+    match_id: MatchId,
+    state: tauri::State<'_, AppContext>,
+) -> IpcResult<GameView> {
+    Ok(state.resign_match(match_id).await?)
+}
+
+#[tauri::command]
+/// Join the public room lobby, optionally bootstrapping from known node ids
+pub async fn join_lobby(
+    bootstrap: Vec<NodeId>,
+    state: tauri::State<'_, AppContext>,
+    app: tauri::AppHandle,
+) -> IpcResult<()> {
+    Ok(state.join_lobby(&app, bootstrap).await?)
+}
+
+#[tauri::command]
+/// Leave the public room lobby
+pub async fn leave_lobby(state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    state.leave_lobby().await;
+    Ok(())
+}
+
+#[tauri::command]
+/// Get the rooms currently advertised in the lobby
+pub async fn get_lobby_rooms(state: tauri::State<'_, AppContext>) -> IpcResult<Vec<RoomAdvert>> {
+    Ok(state.list_lobby_rooms().await?)
+}
+
+#[tauri::command]
+/// Advertise the current room in the lobby under its name and rules
+pub async fn advertise_room(state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    Ok(state.advertise_room().await?)
+}
+
+#[tauri::command]
+/// Stop advertising the current room in the lobby
+pub async fn stop_advertising(state: tauri::State<'_, AppContext>) -> IpcResult<()> {
+    state.stop_advertising().await;
+    Ok(())
+}
@@ -1,17 +1,46 @@
 use anyhow::anyhow;
+use iroh::SecretKey;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 use tauri::Manager as _;
+use tokio::time::timeout;
 use utils::AppStore;
 
-use crate::state::AppContext;
+use crate::{error::app_error, state::AppContext, vault::Vault};
 
+mod error;
 mod game;
 mod gossip;
+mod identity;
 mod ipc;
 mod state;
 mod utils;
+mod vault;
+
+/// Longest we wait for the node to shut down before exiting anyway.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Initialize the Application Context from disk.
+///
+/// With encryption enabled this waits for [`ipc::unlock`] to provide the passphrase.
 async fn init_context(app: tauri::AppHandle) -> tauri::Result<()> {
+    let store = AppStore::acquire(&app)?;
+    if store.is_encrypted() {
+        tracing::info!("Identity is encrypted, waiting to be unlocked.");
+        return Ok(());
+    }
+    let key = store.get_secret_key()?;
+    start_node(&app, key, None).await
+}
+
+/// Spawn the Iroh node and manage its context, opening the room credentials with the vault if given.
+pub(crate) async fn start_node(
+    app: &tauri::AppHandle,
+    key: SecretKey,
+    vault: Option<Vault>,
+) -> tauri::Result<()> {
     let data_root = app
         .path()
         .app_data_dir()
@@ -19,15 +48,21 @@ async fn init_context(app: tauri::AppHandle) -> tauri::Result<()> {
         .join("iroh_data");
 
     // Spawn the Iroh node
-    let key = AppStore::acquire(&app)?.get_secret_key()?;
-    let node = gossip::GossipNode::spawn(Some(key), data_root)
+    let store = AppStore::acquire(app)?;
+    let rooms = store.get_room_credentials(vault.as_ref())?;
+    let mode = store.get_network_mode();
+    let relays = store.get_relay_settings();
+    let node = gossip::GossipNode::spawn(Some(key), data_root, mode, relays)
         .await
-        .map_err(|e| anyhow!("Failed to spawn node: {}", e))?;
+        .map_err(|e| app_error!(NetworkUnavailable, "Failed to spawn node: {}", e))?;
 
-    app.manage(AppContext::new(node));
+    app.manage(AppContext::new(node, vault, rooms));
 
     let state = app.state::<state::AppContext>();
     state.drop_channel().await?; // Reset active channel on init.
+    if let Err(e) = state.apply_retention(&store).await {
+        tracing::warn!("Failed to apply retention policy: {e}");
+    }
 
     tracing::info!("Iroh node initialized.");
     Ok(())
@@ -53,7 +88,10 @@ pub fn run() {
             let handle = app.handle().clone();
 
             tauri::async_runtime::spawn(async move {
-                init_context(handle).await.unwrap();
+                // A corrupt identity key lands here, the frontend can still import a backup
+                if let Err(e) = init_context(handle).await {
+                    tracing::error!("Failed to initialize: {e}");
+                }
             });
             Ok(())
         })
@@ -61,14 +99,118 @@ pub fn run() {
             ipc::create_room,
             ipc::join_room,
             ipc::send_message,
+            ipc::attach_file,
+            ipc::fetch_attachment,
+            ipc::search_messages,
+            ipc::get_unread_counts,
+            ipc::mark_room_read,
             ipc::leave_room,
             ipc::get_latest_ticket,
             ipc::get_node_id,
+            ipc::get_identity_fingerprint,
+            ipc::export_identity,
+            ipc::import_identity,
+            ipc::rotate_identity,
+            ipc::restore_previous_identity,
+            ipc::is_locked,
+            ipc::unlock,
+            ipc::enable_encryption,
+            ipc::disable_encryption,
             ipc::set_nickname,
+            ipc::get_profile,
+            ipc::set_profile,
+            ipc::set_avatar,
+            ipc::get_avatar,
             ipc::get_nickname,
+            ipc::get_network_mode,
+            ipc::set_network_mode,
+            ipc::get_relay_settings,
+            ipc::set_relay_settings,
+            ipc::get_retention,
+            ipc::set_retention,
+            ipc::get_storage_usage,
+            ipc::clear_room,
             ipc::get_message_log,
             ipc::get_peers,
+            ipc::get_connections,
+            ipc::join_voice,
+            ipc::leave_voice,
+            ipc::get_voice,
+            ipc::set_voice_mode,
+            ipc::set_talking,
+            ipc::mute_voice_peer,
+            ipc::get_admission,
+            ipc::set_admission,
+            ipc::approve_peer,
+            ipc::reject_peer,
+            ipc::get_room_meta,
+            ipc::set_room_meta,
+            ipc::get_moderation,
+            ipc::set_moderator,
+            ipc::kick_peer,
+            ipc::ban_peer,
+            ipc::unban_peer,
+            ipc::mute_peer,
+            ipc::unmute_peer,
+            ipc::get_game,
+            ipc::get_room_snapshot,
+            ipc::take_seat,
+            ipc::start_game,
+            ipc::play_move,
+            ipc::request_takeback,
+            ipc::get_takeback_request,
+            ipc::respond_takeback,
+            ipc::resign,
+            ipc::abort_game,
+            ipc::offer_draw,
+            ipc::get_draw_offer,
+            ipc::respond_draw,
+            ipc::start_tournament,
+            ipc::get_tournament,
+            ipc::get_match_game,
+            ipc::play_match_move,
+            ipc::resign_match,
+            ipc::join_lobby,
+            ipc::leave_lobby,
+            ipc::get_lobby_rooms,
+            ipc::advertise_room,
+            ipc::stop_advertising,
         ])
-        .run(tauri::generate_context!()) // Run the Tauri application
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            // Closing the window or quitting from the OS leaves the room before exiting
+            tauri::RunEvent::WindowEvent {
+                event: tauri::WindowEvent::CloseRequested { api, .. },
+                ..
+            } => {
+                api.prevent_close();
+                request_shutdown(app);
+            }
+            tauri::RunEvent::ExitRequested {
+                code: None, api, ..
+            } => {
+                api.prevent_exit();
+                request_shutdown(app);
+            }
+            _ => {}
+        });
+}
+
+/// Shut the node down gracefully in the background, then exit the application.
+fn request_shutdown(app: &tauri::AppHandle) {
+    static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        // The context is missing if we are closed before the node finished starting
+        if let Some(state) = app.try_state::<AppContext>() {
+            if timeout(SHUTDOWN_TIMEOUT, state.shutdown()).await.is_err() {
+                tracing::warn!("Timed out shutting down the node");
+            }
+        }
+        app.exit(0);
+    });
 }
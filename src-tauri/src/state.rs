@@ -1,83 +1,881 @@
-use crate::gossip::{
-    doc::{
-        chat::ChatMessage,
-        peers::{PeerInfo, PeerStatus},
-        SharedActivity,
+use crate::{
+    error::app_error,
+    game::{
+        board::Piece,
+        tournament::{MatchId, TournamentFormat, TournamentView},
+        GameView, Move, PlayerType,
     },
-    spawn_event_listener, GossipNode,
+    gossip::{
+        diagnostics::{diagnose, spawn_diagnostics_reporter, PeerConnection},
+        doc::{
+            admission::{AdmissionSettings, AdmissionView},
+            attachment::Attachment,
+            chat::ChatMessage,
+            game::{DrawOffer, DrawResponse, TakebackRequest, TakebackResponse},
+            moderation::{ModerationView, Role, Sanction},
+            peers::{PeerInfo, PeerStatus},
+            profile::Profile,
+            room::{default_room_name, RoomMeta, DEFAULT_VARIANT},
+            ticket::{RoomCredentials, RoomTicket},
+            SharedActivity,
+        },
+        lobby::{Lobby, RoomAdvert, ADVERT_INTERVAL},
+        notify::{FrontendNotifier, NotificationHook},
+        search::{ChatIndex, SearchHit, SearchQuery},
+        spawn_event_listener,
+        storage::{RoomUsage, StorageReport},
+        voice::{
+            audio::{self, AudioBackend},
+            VoiceMode, VoiceSession, VoiceView,
+        },
+        DeltaCursor, GossipNode, NodeId, RelaySettings, RoomSnapshot,
+    },
+    utils::{get_timestamp, AppStore},
+    vault::Vault,
 };
 use anyhow::anyhow;
-use iroh_docs::DocTicket;
+use bytes::Bytes;
+use iroh_blobs::Hash;
+use iroh_docs::NamespaceId;
 use n0_future::task::AbortOnDropHandle;
-use std::sync::Arc;
+use std::{cmp::Reverse, collections::HashMap, sync::Arc, time::Duration};
 use tauri::AppHandle;
-use tokio::sync::Mutex as TokioMutex;
+use tokio::{sync::Mutex as TokioMutex, time::sleep};
+use tracing::{info, warn};
+
+/// How long leaving a room waits for our offline status to reach a peer.
+const LEAVE_SYNC_TIMEOUT: Duration = Duration::from_secs(3);
 
 /// Holds information about the currently active game.
 pub struct ActiveChannel {
     name: String,
     pub activity: SharedActivity,
+    /// Number of the last delta the event listener sent
+    cursor: DeltaCursor,
     receiver_handle: AbortOnDropHandle<()>,
+    _diagnostics_handle: AbortOnDropHandle<()>,
+    /// The room password, checked against joiners' proofs when we host the room and
+    /// proved to the host when we join it
+    pub room_password: Option<String>,
+    /// Our part in the room's voice chat, if we joined it
+    pub voice: Option<VoiceSession>,
 }
 
 impl ActiveChannel {
     pub fn new(
         activity: SharedActivity,
+        cursor: DeltaCursor,
         receiver_handle: AbortOnDropHandle<()>,
+        diagnostics_handle: AbortOnDropHandle<()>,
         name: &str,
     ) -> Self {
         Self {
             name: name.to_string(),
             activity,
+            cursor,
             receiver_handle,
+            _diagnostics_handle: diagnostics_handle,
+            room_password: None,
+            voice: None,
         }
     }
+    /// The nickname we joined under.
+    pub fn nickname(&self) -> &str {
+        &self.name
+    }
 }
 
 /// Holds the application's runtime context, including the iroh client,
 /// game document handle, current game state, and background task handles.
 pub struct AppContext {
-    // The iroh client instance used for all interactions.
-    pub node: GossipNode,
+    // The iroh client instance used for all interactions, replaced when the network settings change.
+    node: TokioMutex<GossipNode>,
+    // Stays the same when the node is rebuilt, as the secret key is kept.
+    pub node_id: NodeId,
     active_channel: Arc<TokioMutex<Option<ActiveChannel>>>,
     pub latest_ticket: TokioMutex<Option<String>>,
+    lobby: TokioMutex<Option<Lobby>>,
+    // Passphrase sealing the identity and room keys in the store, if encryption is enabled.
+    vault: TokioMutex<Option<Vault>>,
+    // What we keep about each stored room, by room ID, as kept in the store.
+    rooms: TokioMutex<HashMap<String, RoomCredentials>>,
+    // Chat history of the rooms searched so far, fed by the event listener.
+    chat_index: Arc<TokioMutex<ChatIndex>>,
+    // Where voice chat audio is captured and played.
+    audio: Arc<dyn AudioBackend>,
+    // Shows mentions and our turn coming up while the app is in the background.
+    notifier: Arc<dyn NotificationHook>,
 }
 
 impl AppContext {
     /// Creates a new, empty AppContext.
-    pub fn new(gossip_node: GossipNode) -> Self {
+    pub fn new(
+        gossip_node: GossipNode,
+        vault: Option<Vault>,
+        rooms: HashMap<String, RoomCredentials>,
+    ) -> Self {
         Self {
-            node: gossip_node,
+            node_id: gossip_node.node_id(),
+            node: TokioMutex::new(gossip_node),
             active_channel: Arc::new(TokioMutex::new(None)),
             latest_ticket: TokioMutex::new(None),
+            lobby: TokioMutex::new(None),
+            vault: TokioMutex::new(vault),
+            rooms: TokioMutex::new(rooms),
+            chat_index: Arc::new(TokioMutex::new(ChatIndex::default())),
+            audio: audio::default_backend(),
+            notifier: Arc::new(FrontendNotifier),
+        }
+    }
+    /// Return a handle to the running node.
+    pub async fn node(&self) -> GossipNode {
+        self.node.lock().await.clone()
+    }
+    /// Rebuild the node with new relay servers, leaving the active channel and the lobby.
+    pub async fn set_relay_settings(&self, relays: RelaySettings) -> anyhow::Result<()> {
+        self.leave_lobby().await;
+        self.drop_channel().await?;
+        let mut node = self.node.lock().await;
+        let mode = node.network_mode();
+        node.respawn(mode, relays).await?;
+        info!("Node rebuilt with {:?} relays", node.relay_settings());
+        Ok(())
+    }
+    /// Leave the room and the lobby, then stop the node so its stores are flushed.
+    pub async fn shutdown(&self) {
+        self.leave_lobby().await;
+        if let Err(e) = self.drop_channel().await {
+            warn!("Failed to leave room on shutdown: {e}");
+        }
+        self.node().await.shutdown().await;
+        info!("Node shut down");
+    }
+    /// Return the vault protecting this install, if encryption is enabled.
+    pub async fn vault(&self) -> Option<Vault> {
+        self.vault.lock().await.clone()
+    }
+    /// Turn encryption on or off, sealing the identity and room credentials with the new
+    /// vault.
+    pub async fn set_vault(&self, vault: Option<Vault>, store: &AppStore) -> anyhow::Result<()> {
+        // the stored keys, which may no longer be the one in use
+        store.reseal_secret_keys(self.vault().await.as_ref(), vault.as_ref())?;
+        store.set_room_credentials(&*self.rooms.lock().await, vault.as_ref())?;
+        *self.vault.lock().await = vault;
+        Ok(())
+    }
+    /// Update what we keep about a room, so its history can still be read once we leave it.
+    async fn remember_room(
+        &self,
+        id: NamespaceId,
+        update: impl FnOnce(&mut RoomCredentials),
+        store: &AppStore,
+    ) -> anyhow::Result<()> {
+        let vault = self.vault().await;
+        let mut rooms = self.rooms.lock().await;
+        let room = rooms.entry(id.to_string()).or_default();
+        let before = room.clone();
+        update(room);
+        if *room != before {
+            store.set_room_credentials(&rooms, vault.as_ref())?;
+        }
+        Ok(())
+    }
+    /// Drop what we kept about a deleted room.
+    async fn forget_room(&self, id: NamespaceId, store: &AppStore) -> anyhow::Result<()> {
+        let vault = self.vault().await;
+        let mut rooms = self.rooms.lock().await;
+        if rooms.remove(&id.to_string()).is_some() {
+            store.set_room_credentials(&rooms, vault.as_ref())?;
         }
+        Ok(())
+    }
+    /// Return how joiners are admitted to the active channel, as seen by this node.
+    pub async fn get_admission(&self) -> anyhow::Result<AdmissionView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel.activity.admission().await?.view_for(&self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get admission. No active channel."
+            )),
+        }
+    }
+    /// Change how joiners are admitted to the active channel, which we must host.
+    pub async fn set_admission(
+        &self,
+        approval_required: bool,
+        password: Option<String>,
+        store: &AppStore,
+    ) -> anyhow::Result<AdmissionSettings> {
+        match self.active_channel.lock().await.as_mut() {
+            Some(channel) => {
+                let settings = channel
+                    .activity
+                    .set_admission(approval_required, password.is_some())
+                    .await?;
+                // kept so joiners can still be checked after a restart
+                self.remember_room(
+                    channel.activity.id(),
+                    |room| room.password = password.clone(),
+                    store,
+                )
+                .await?;
+                channel.room_password = password;
+                if channel.room_password.is_some() {
+                    channel.activity.challenge_pending().await?;
+                }
+                Ok(settings)
+            }
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not set admission. No active channel."
+            )),
+        }
+    }
+    /// Approve or reject a node asking to join the active channel, which we must host.
+    pub async fn decide_admission(&self, node_id: NodeId, approved: bool) -> anyhow::Result<()> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.decide_admission(node_id, approved).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not decide admission. No active channel."
+            )),
+        }
+    }
+    /// Return the roles and sanctions in the active channel, as seen by this node.
+    pub async fn get_moderation(&self) -> anyhow::Result<ModerationView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel
+                .activity
+                .admission()
+                .await?
+                .moderation()
+                .view_for(&self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get moderation. No active channel."
+            )),
+        }
+    }
+    /// Appoint or dismiss a moderator of the active channel, which we must host.
+    pub async fn set_role(&self, node_id: NodeId, role: Role) -> anyhow::Result<()> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.set_role(node_id, role).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not set role. No active channel."
+            )),
+        }
+    }
+    /// Sanction a node in the active channel, as its host or a moderator.
+    pub async fn moderate(&self, node_id: NodeId, sanction: Sanction) -> anyhow::Result<()> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.moderate(node_id, sanction).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not moderate. No active channel."
+            )),
+        }
+    }
+    /// Report the connection to each peer of the active channel.
+    pub async fn get_connections(&self) -> anyhow::Result<Vec<PeerConnection>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => diagnose(channel, &self.node().await).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get connections. No active channel."
+            )),
+        }
+    }
+    /// Join the voice chat of the active channel, or switch mode if already in it.
+    pub async fn join_voice(
+        &self,
+        mode: VoiceMode,
+        app_handle: &AppHandle,
+    ) -> anyhow::Result<VoiceView> {
+        let node = self.node().await;
+        let mut channel = self.active_channel.lock().await;
+        let Some(channel) = channel.as_mut() else {
+            return Err(app_error!(
+                NoActiveChannel,
+                "Could not join voice chat. No active channel."
+            ));
+        };
+        if let Some(voice) = &channel.voice {
+            voice.set_mode(mode);
+            return Ok(voice.view());
+        }
+        if channel.activity.is_read_only() {
+            return Err(app_error!(
+                NotPermitted,
+                "Spectators can't join voice chat."
+            ));
+        }
+        let admission = channel.activity.admission().await?;
+        if admission.moderation().is_muted(&self.node_id) {
+            return Err(app_error!(NotPermitted, "You are muted in this room."));
+        }
+        let peers = channel
+            .activity
+            .get_all_peer_info()
+            .await?
+            .into_iter()
+            .filter(|peer| {
+                peer.id != self.node_id
+                    && peer.status == PeerStatus::Online
+                    && admission.honours_node(&peer.id)
+            })
+            .map(|peer| peer.id)
+            .collect();
+        let voice = VoiceSession::start(
+            node,
+            channel.activity.id(),
+            peers,
+            mode,
+            self.audio.as_ref(),
+            self.active_channel.clone(),
+            app_handle.clone(),
+        )?;
+        let view = voice.view();
+        channel.voice = Some(voice);
+        Ok(view)
+    }
+    /// Hang up every voice call of the active channel.
+    pub async fn leave_voice(&self) {
+        if let Some(channel) = self.active_channel.lock().await.as_mut() {
+            channel.voice.take();
+        }
+    }
+    /// Return the voice chat of the active channel, if we joined it.
+    pub async fn get_voice(&self) -> anyhow::Result<Option<VoiceView>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel.voice.as_ref().map(VoiceSession::view)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get voice chat. No active channel."
+            )),
+        }
+    }
+    /// Helper function to change a setting of the voice chat we are in.
+    async fn control_voice(
+        &self,
+        action: &str,
+        control: impl FnOnce(&VoiceSession),
+    ) -> anyhow::Result<VoiceView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => match &channel.voice {
+                Some(voice) => {
+                    control(voice);
+                    Ok(voice.view())
+                }
+                None => Err(app_error!(
+                    NotFound,
+                    "Could not {action}. Not in voice chat."
+                )),
+            },
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not {action}. No active channel."
+            )),
+        }
+    }
+    pub async fn set_voice_mode(&self, mode: VoiceMode) -> anyhow::Result<VoiceView> {
+        self.control_voice("change voice mode", |voice| voice.set_mode(mode))
+            .await
+    }
+    /// Hold or release the push-to-talk key.
+    pub async fn set_talking(&self, talking: bool) -> anyhow::Result<VoiceView> {
+        self.control_voice("talk", |voice| voice.set_talking(talking))
+            .await
+    }
+    /// Stop or resume hearing a peer in voice chat.
+    pub async fn mute_voice_peer(&self, node_id: NodeId, muted: bool) -> anyhow::Result<VoiceView> {
+        self.control_voice("mute peer", |voice| voice.set_muted(node_id, muted))
+            .await
     }
     /// Return a list of the known members of this Gossip Swarm.
     pub async fn get_peers(&self) -> anyhow::Result<Vec<PeerInfo>> {
         match self.active_channel.lock().await.as_ref() {
             Some(channel) => channel.activity.get_all_peer_info().await,
-            None => Err(anyhow!("Could not get peers. No active channel.")),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get peers. No active channel."
+            )),
         }
     }
     /// Send a message on the active channel.
-    pub async fn send_message(&self, message: &str) -> anyhow::Result<()> {
+    pub async fn send_message(
+        &self,
+        message: &str,
+        attachments: Vec<Attachment>,
+    ) -> anyhow::Result<()> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => {
+                channel
+                    .activity
+                    .send_message(&channel.name, message, attachments)
+                    .await
+            }
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not send message. No active channel."
+            )),
+        }
+    }
+    /// Store a file in the room, ready to be sent with a message.
+    pub async fn attach_file(
+        &self,
+        name: &str,
+        mime: &str,
+        data: Vec<u8>,
+    ) -> anyhow::Result<Attachment> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.attach(name, mime, data).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not attach file. No active channel."
+            )),
+        }
+    }
+    /// Return an attachment's content, downloading it if needed.
+    pub async fn fetch_attachment(&self, hash: Hash) -> anyhow::Result<Bytes> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.fetch_attachment(hash).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not fetch attachment. No active channel."
+            )),
+        }
+    }
+    /// Get a player's profile, our own when no node is given.
+    pub async fn get_profile(
+        &self,
+        node_id: Option<NodeId>,
+        store: &AppStore,
+    ) -> anyhow::Result<Option<Profile>> {
+        let node_id = match node_id {
+            Some(node_id) if node_id != self.node_id => node_id,
+            _ => return Ok(Some(store.get_profile())),
+        };
         match self.active_channel.lock().await.as_ref() {
-            Some(channel) => channel.activity.send_message(&channel.name, message).await,
-            None => Err(anyhow!("Could not send message. No active channel.")),
+            Some(channel) => channel.activity.get_profile(&node_id).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get profile. No active channel."
+            )),
+        }
+    }
+    /// Save our profile, keeping the avatar, and publish it in the current room.
+    pub async fn set_profile(
+        &self,
+        bio: &str,
+        favourite_side: Piece,
+        rating: Option<u32>,
+        store: &AppStore,
+    ) -> anyhow::Result<Profile> {
+        let profile = Profile {
+            bio: bio.trim().to_string(),
+            favourite_side,
+            rating,
+            ..store.get_profile()
+        };
+        self.publish_profile(&profile, store).await?;
+        Ok(profile)
+    }
+    /// Replace or remove our avatar image, and publish it in the current room.
+    pub async fn set_avatar(
+        &self,
+        image: Option<(Vec<u8>, String)>,
+        store: &AppStore,
+    ) -> anyhow::Result<Profile> {
+        let node = self.node().await;
+        let avatar = match image {
+            Some((data, mime)) => Some(node.store_avatar(data, &mime).await?),
+            None => {
+                node.remove_avatar().await?;
+                None
+            }
+        };
+        let profile = Profile {
+            avatar,
+            ..store.get_profile()
+        };
+        self.publish_profile(&profile, store).await?;
+        Ok(profile)
+    }
+    /// Store our profile and show it to the room we are in, if any.
+    async fn publish_profile(&self, profile: &Profile, store: &AppStore) -> anyhow::Result<()> {
+        profile.validate()?;
+        store.set_profile(profile)?;
+        if let Some(channel) = self.active_channel.lock().await.as_ref() {
+            if !channel.activity.is_read_only() {
+                channel.activity.set_profile(profile).await?;
+            }
+        }
+        Ok(())
+    }
+    /// Read a player's avatar image, our own when no node is given.
+    ///
+    /// Returns `None` when they have none or it hasn't been downloaded yet.
+    pub async fn get_avatar(
+        &self,
+        node_id: Option<NodeId>,
+        store: &AppStore,
+    ) -> anyhow::Result<Option<Bytes>> {
+        let avatar = self
+            .get_profile(node_id, store)
+            .await?
+            .and_then(|profile| profile.avatar);
+        match avatar {
+            Some(avatar) => self.node().await.read_avatar(&avatar).await,
+            None => Ok(None),
         }
     }
     /// Return the full message log so far for all connected participants.
     pub async fn get_message_log(&self) -> anyhow::Result<Vec<ChatMessage>> {
         match self.active_channel.lock().await.as_ref() {
             Some(channel) => Ok(channel.activity.get_messages().await?),
-            None => Err(anyhow!("Could not get message log. No active channel.")),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get message log. No active channel."
+            )),
+        }
+    }
+    /// Return everything shown of the active channel at once, along with the number of the
+    /// last delta it includes. Deltas after it carry only newer state.
+    pub async fn get_room_snapshot(&self) -> anyhow::Result<RoomSnapshot> {
+        // the listener takes this lock while holding the cursor, so let go of it first
+        let (activity, cursor) = match self.active_channel.lock().await.as_ref() {
+            Some(channel) => (channel.activity.clone(), channel.cursor.clone()),
+            None => {
+                return Err(app_error!(
+                    NoActiveChannel,
+                    "Could not get room snapshot. No active channel."
+                ))
+            }
+        };
+        cursor.snapshot(&activity).await
+    }
+    /// Return the current game state in the active channel, as seen by this node.
+    pub async fn get_game(&self) -> anyhow::Result<Option<GameView>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel
+                .activity
+                .get_game()
+                .await?
+                .map(|game| game.view_for(self.node_id))),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get game. No active channel."
+            )),
+        }
+    }
+    /// Take a seat in the active channel's game.
+    pub async fn take_seat(&self, role: PlayerType, piece: Piece) -> anyhow::Result<GameView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel
+                .activity
+                .take_seat(role, piece)
+                .await?
+                .view_for(self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not take seat. No active channel."
+            )),
+        }
+    }
+    /// Start a new game in the active channel.
+    pub async fn start_game(&self) -> anyhow::Result<GameView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel.activity.start_game().await?.view_for(self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not start game. No active channel."
+            )),
+        }
+    }
+    /// Play a move in the active channel's game.
+    pub async fn play_move(&self, x: usize, y: usize) -> anyhow::Result<Move> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.play_move(x, y).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not play move. No active channel."
+            )),
+        }
+    }
+    /// Ask the opponent to undo our last move.
+    pub async fn request_takeback(&self) -> anyhow::Result<TakebackRequest> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.request_takeback().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not request takeback. No active channel."
+            )),
+        }
+    }
+    /// Return the outstanding takeback request, if any.
+    pub async fn get_takeback_request(&self) -> anyhow::Result<Option<TakebackRequest>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.get_takeback_request().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get takeback request. No active channel."
+            )),
+        }
+    }
+    /// Accept or decline the opponent's takeback request.
+    pub async fn respond_takeback(&self, accept: bool) -> anyhow::Result<TakebackResponse> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.respond_takeback(accept).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not respond to takeback. No active channel."
+            )),
+        }
+    }
+    /// Concede the active channel's game.
+    pub async fn resign(&self) -> anyhow::Result<GameView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel.activity.resign().await?.view_for(self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not resign. No active channel."
+            )),
+        }
+    }
+    /// Call off the active channel's game before the first move.
+    pub async fn abort_game(&self) -> anyhow::Result<GameView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel.activity.abort_game().await?.view_for(self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not abort game. No active channel."
+            )),
+        }
+    }
+    /// Offer the opponent a draw.
+    pub async fn offer_draw(&self) -> anyhow::Result<DrawOffer> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.offer_draw().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not offer draw. No active channel."
+            )),
+        }
+    }
+    /// Return the outstanding draw offer, if any.
+    pub async fn get_draw_offer(&self) -> anyhow::Result<Option<DrawOffer>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.get_draw_offer().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get draw offer. No active channel."
+            )),
+        }
+    }
+    /// Accept or decline the opponent's draw offer.
+    pub async fn respond_draw(&self, accept: bool) -> anyhow::Result<DrawResponse> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.respond_draw(accept).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not respond to draw offer. No active channel."
+            )),
+        }
+    }
+    /// Start a tournament between everyone online in the active channel.
+    pub async fn start_tournament(
+        &self,
+        format: TournamentFormat,
+    ) -> anyhow::Result<TournamentView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.start_tournament(format).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not start tournament. No active channel."
+            )),
+        }
+    }
+    /// Return the active channel's tournament bracket and standings.
+    pub async fn get_tournament(&self) -> anyhow::Result<Option<TournamentView>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.get_tournament_view().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get tournament. No active channel."
+            )),
+        }
+    }
+    /// Return the game for one tournament match, as seen by this node.
+    pub async fn get_match_game(&self, id: MatchId) -> anyhow::Result<Option<GameView>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel
+                .activity
+                .get_match_game(id)
+                .await?
+                .map(|game| game.view_for(self.node_id))),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get match. No active channel."
+            )),
+        }
+    }
+    /// Play a move in one of our tournament matches.
+    pub async fn play_match_move(&self, id: MatchId, x: usize, y: usize) -> anyhow::Result<Move> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.play_match_move(id, x, y).await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not play match move. No active channel."
+            )),
+        }
+    }
+    /// Concede one of our tournament matches.
+    pub async fn resign_match(&self, id: MatchId) -> anyhow::Result<GameView> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => Ok(channel
+                .activity
+                .resign_match(id)
+                .await?
+                .view_for(self.node_id)),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not resign match. No active channel."
+            )),
+        }
+    }
+    /// Join the public lobby, bootstrapping from the given nodes and our room's peers.
+    pub async fn join_lobby(
+        &self,
+        app_handle: &AppHandle,
+        mut bootstrap: Vec<NodeId>,
+    ) -> anyhow::Result<()> {
+        let mut lobby = self.lobby.lock().await;
+        if lobby.is_some() {
+            return Ok(());
+        }
+        if let Ok(peers) = self.get_peers().await {
+            let node_id = self.node_id;
+            bootstrap.extend(peers.into_iter().map(|peer| peer.id));
+            bootstrap.retain(|id| id != &node_id);
+        }
+        *lobby = Some(Lobby::join(self.node().await, bootstrap, app_handle.clone()).await?);
+        Ok(())
+    }
+    /// Leave the public lobby, which also stops advertising our room.
+    pub async fn leave_lobby(&self) {
+        if self.lobby.lock().await.take().is_some() {
+            info!("Left lobby");
+        }
+    }
+    /// Return the name and rules of the active channel, if its host has set them.
+    pub async fn get_room_meta(&self) -> anyhow::Result<Option<RoomMeta>> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.room_meta().await,
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get room metadata. No active channel."
+            )),
+        }
+    }
+    /// Describe the active channel, which we must host.
+    pub async fn set_room_meta(
+        &self,
+        name: &str,
+        description: &str,
+        variant: &str,
+        rules: &str,
+    ) -> anyhow::Result<RoomMeta> {
+        match self.active_channel.lock().await.as_ref() {
+            Some(channel) => {
+                channel
+                    .activity
+                    .set_room_meta(name, description, variant, rules)
+                    .await
+            }
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not set room metadata. No active channel."
+            )),
+        }
+    }
+    /// Return the rooms currently advertised in the lobby.
+    pub async fn list_lobby_rooms(&self) -> anyhow::Result<Vec<RoomAdvert>> {
+        match self.lobby.lock().await.as_ref() {
+            Some(lobby) => Ok(lobby.rooms()),
+            None => Err(app_error!(
+                NotInLobby,
+                "Could not list rooms. Not in the lobby."
+            )),
+        }
+    }
+    /// Keep advertising the active channel in the lobby until it closes or we stop.
+    ///
+    /// The advert is named after the room's metadata, read afresh each time. Only the
+    /// room's host advertises it, as adverts are published under the host's node.
+    pub async fn advertise_room(&self) -> anyhow::Result<()> {
+        let activity = match self.active_channel.lock().await.as_ref() {
+            Some(channel) => channel.activity.clone(),
+            None => {
+                return Err(app_error!(
+                    NoActiveChannel,
+                    "Could not advertise room. No active channel."
+                ))
+            }
+        };
+        if activity.admission().await?.host() != Some(self.node_id) {
+            return Err(app_error!(
+                NotPermitted,
+                "Only the host can advertise the room."
+            ));
+        }
+        let mut lobby = self.lobby.lock().await;
+        let Some(lobby) = lobby.as_mut() else {
+            return Err(app_error!(
+                NotInLobby,
+                "Could not advertise room. Not in the lobby."
+            ));
+        };
+        let room_id = self.get_topic_id().await?;
+        let publisher = lobby.publisher();
+        let active_channel = self.active_channel.clone();
+        let advertiser = n0_future::task::spawn(async move {
+            loop {
+                let advert = match active_channel.lock().await.as_ref() {
+                    Some(channel) if channel.activity.id().to_string() == room_id => {
+                        build_advert(channel).await
+                    }
+                    _ => break, // the room we were advertising has closed
+                };
+                match advert {
+                    Ok(advert) => {
+                        if let Err(e) = publisher.publish(&advert).await {
+                            warn!("Failed to publish lobby advert: {e}");
+                        }
+                    }
+                    Err(e) => warn!("Failed to build lobby advert: {e}"),
+                }
+                sleep(ADVERT_INTERVAL).await;
+            }
+            info!("Stopped advertising room {}", room_id);
+        });
+        lobby.set_advertiser(Some(AbortOnDropHandle::new(advertiser)));
+        Ok(())
+    }
+    /// Stop advertising our room in the lobby, it drops out once its advert expires.
+    pub async fn stop_advertising(&self) {
+        if let Some(lobby) = self.lobby.lock().await.as_mut() {
+            lobby.set_advertiser(None);
         }
     }
     /// Return the active channel's id.
     pub async fn get_topic_id(&self) -> anyhow::Result<String> {
         match self.active_channel.lock().await.as_ref() {
             Some(channel) => Ok(channel.activity.id().to_string()),
-            None => Err(anyhow!("Could not get topic ID. No active channel.")),
+            None => Err(app_error!(
+                NoActiveChannel,
+                "Could not get topic ID. No active channel."
+            )),
         }
     }
     /// Generate a new ticket token string or use the existing one.
@@ -91,7 +889,10 @@ impl AppContext {
             }
             None => match latest_ticket.clone() {
                 Some(ticket) => Ok(ticket),
-                None => Err(anyhow!("Could not generate ticket. No active channel.")),
+                None => Err(app_error!(
+                    NoActiveChannel,
+                    "Could not generate ticket. No active channel."
+                )),
             },
         }
     }
@@ -100,42 +901,292 @@ impl AppContext {
         self.generate_ticket().await.ok(); // try to generate a new ticket
         match self.active_channel.lock().await.take() {
             Some(channel) => {
-                channel.activity.set_status(PeerStatus::Offline).await?;
+                // failing to announce we left must not keep the document open
+                if !channel.activity.is_read_only() {
+                    if let Err(e) = channel.activity.set_status(PeerStatus::Offline).await {
+                        warn!("Failed to set offline status: {e}");
+                    }
+                    // give a neighbour the chance to see us leave before the doc closes
+                    match channel
+                        .activity
+                        .sync_with_online_peers(LEAVE_SYNC_TIMEOUT)
+                        .await
+                    {
+                        Ok(true) => info!("Offline status synced"),
+                        Ok(false) => warn!("Offline status not synced to any peer"),
+                        Err(e) => warn!("Failed to sync offline status: {e}"),
+                    }
+                }
                 let id = channel.activity.id().to_string();
-                channel.activity.close().await?;
                 channel.receiver_handle.abort();
+                channel.activity.close().await?;
                 Ok(Some(id))
             }
             None => Ok(None),
         }
     }
+    /// Return the ID of the room currently joined.
+    async fn active_room(&self) -> Option<NamespaceId> {
+        self.active_channel
+            .lock()
+            .await
+            .as_ref()
+            .map(|channel| channel.activity.id())
+    }
+    /// Delete rooms not visited within the retention policy's age limit.
+    ///
+    /// Rooms stored before visits were recorded count as visited now, so they get a full
+    /// period before being deleted.
+    pub async fn apply_retention(&self, store: &AppStore) -> anyhow::Result<()> {
+        let visits = store.get_room_visits();
+        let max_age = store.get_retention().room_max_age();
+        let active = self.active_room().await;
+        let node = self.node().await;
+        let now = get_timestamp();
+        let mut kept = HashMap::new();
+        for id in node.rooms().await? {
+            let visited = visits.get(&id.to_string()).copied().unwrap_or(now);
+            let expired =
+                max_age.is_some_and(|age| now.saturating_sub(visited) > age.as_micros() as u64);
+            match expired && active != Some(id) {
+                true => {
+                    node.drop_room(id).await?;
+                    self.chat_index.lock().await.forget(&id);
+                    self.forget_room(id, store).await?;
+                    store.mark_read(&id.to_string())?;
+                }
+                false => {
+                    kept.insert(id.to_string(), visited);
+                }
+            }
+        }
+        store.set_room_visits(&kept)
+    }
+    /// Report the disk space taken by each stored room.
+    pub async fn storage_usage(&self, store: &AppStore) -> anyhow::Result<StorageReport> {
+        let visits = store.get_room_visits();
+        let active = self.active_room().await;
+        let node = self.node().await;
+        let mut rooms = Vec::new();
+        for id in node.rooms().await? {
+            let (entries, bytes) = node.room_usage(id).await?;
+            rooms.push(RoomUsage {
+                id: id.to_string(),
+                entries,
+                bytes,
+                last_visited: visits.get(&id.to_string()).copied(),
+                active: active == Some(id),
+            });
+        }
+        rooms.sort_by_key(|room| Reverse(room.last_visited));
+        Ok(StorageReport {
+            rooms,
+            total_bytes: node.disk_usage().await?,
+        })
+    }
+    /// Delete a stored room, which can't be the one currently joined.
+    pub async fn clear_room(&self, id: NamespaceId, store: &AppStore) -> anyhow::Result<()> {
+        if self.active_room().await == Some(id) {
+            return Err(app_error!(
+                NotPermitted,
+                "Leave the room before clearing it."
+            ));
+        }
+        self.node().await.drop_room(id).await?;
+        self.chat_index.lock().await.forget(&id);
+        self.forget_room(id, store).await?;
+        store.mark_read(&id.to_string())?;
+        let mut visits = store.get_room_visits();
+        visits.remove(&id.to_string());
+        store.set_room_visits(&visits)
+    }
+    /// Search the chat history of the current room, or of every stored room.
+    pub async fn search_messages(&self, query: SearchQuery) -> anyhow::Result<Vec<SearchHit>> {
+        let rooms = match (query.all_rooms, self.active_room().await) {
+            (true, _) => self.node().await.rooms().await?,
+            (false, Some(room)) => vec![room],
+            (false, None) => {
+                return Err(app_error!(
+                    NoActiveChannel,
+                    "Could not search messages. No active channel."
+                ))
+            }
+        };
+        for &room in &rooms {
+            if !self.chat_index.lock().await.is_loaded(&room) {
+                self.index_room(room).await?;
+            }
+        }
+        Ok(self.chat_index.lock().await.search(&rooms, &query))
+    }
+    /// Read a room's chat history into the search index.
+    ///
+    /// The room is tracked first so messages arriving meanwhile aren't missed.
+    async fn index_room(&self, room: NamespaceId) -> anyhow::Result<()> {
+        self.chat_index.lock().await.track(room);
+        match self.read_history(room).await {
+            Ok(history) => {
+                info!("Indexed {} messages of room {room}", history.len());
+                self.chat_index.lock().await.load(room, history);
+                Ok(())
+            }
+            Err(e) => {
+                self.chat_index.lock().await.forget(&room);
+                Err(e)
+            }
+        }
+    }
+    /// Read the chat history of a stored room, whether we are in it or not.
+    async fn read_history(&self, room: NamespaceId) -> anyhow::Result<Vec<ChatMessage>> {
+        let credentials = self.rooms.lock().await.get(&room.to_string()).cloned();
+        let credentials = credentials.unwrap_or_default();
+        let Some(activity) =
+            SharedActivity::open_stored(room, self.node().await, &credentials).await?
+        else {
+            return Ok(Vec::new());
+        };
+        let history = activity.get_messages().await;
+        activity.close().await?;
+        history
+    }
     /// Start the Docs channel.
     pub async fn start_channel(
         &self,
-        doc_ticket: Option<DocTicket>,
+        mut ticket: Option<RoomTicket>,
         app_handle: &AppHandle,
         nickname: &str,
+        password: Option<&str>,
     ) -> anyhow::Result<String> {
-        let activity = SharedActivity::new(doc_ticket, self.node.clone()).await?;
+        let node = self.node().await;
+        let store = AppStore::acquire(app_handle)?;
+        let creating = ticket.is_none();
+        let mut stored = RoomCredentials::default();
+        if let Some(ticket) = ticket.as_mut() {
+            let id = ticket.doc.capability.id().to_string();
+            stored = self
+                .rooms
+                .lock()
+                .await
+                .get(&id)
+                .cloned()
+                .unwrap_or_default();
+            // the key and host we first joined with win, and let older tickets rejoin
+            ticket.key = stored.key.clone().or(ticket.key.take());
+            ticket.host = stored.host.or(ticket.host);
+        }
+        let mut activity = SharedActivity::new(ticket, node.clone()).await?;
+        // rooms created before keys and hosts existed are taken over by their creator
+        let creating = creating || activity.adopt_legacy_room().await?;
+        if stored.key.is_none() {
+            if let Err(e) = activity.seal_legacy_messages().await {
+                warn!("Failed to seal messages written before the room had a key: {e}");
+            }
+        }
+        let credentials = activity.credentials();
+        self.remember_room(
+            activity.id(),
+            |room| {
+                room.key = credentials.key;
+                room.host = credentials.host;
+            },
+            &store,
+        )
+        .await?;
+        let hosting = credentials.host == Some(node.node_id());
 
-        // Spawn the event listener task
+        // Spawn the event listener and connection diagnostics tasks
+        let cursor = DeltaCursor::default();
         let receiver_handle = spawn_event_listener(
             app_handle.clone(),
+            activity.clone(),
+            cursor.clone(),
             Box::pin(activity.activity_subscribe().await?),
             self.active_channel.clone(),
+            self.chat_index.clone(),
+            self.notifier.clone(),
+        );
+        let diagnostics_handle =
+            spawn_diagnostics_reporter(app_handle.clone(), node, self.active_channel.clone());
+        let mut active_channel = ActiveChannel::new(
+            activity,
+            cursor,
+            receiver_handle,
+            diagnostics_handle,
+            nickname,
         );
-        let active_channel = ActiveChannel::new(activity, receiver_handle, nickname);
+        active_channel.room_password = match hosting {
+            true => stored.password,
+            false => password.map(str::to_string),
+        };
 
-        active_channel.activity.set_nickname(nickname).await?;
-        active_channel
-            .activity
-            .set_status(PeerStatus::Online)
-            .await?;
+        // Spectators joining with a read only ticket can't announce themselves
+        if !active_channel.activity.is_read_only() {
+            let activity = &active_channel.activity;
+            activity.bind_author().await?;
+            match creating {
+                true => {
+                    activity.claim_host().await?;
+                    activity
+                        .set_room_meta(&default_room_name(nickname), "", DEFAULT_VARIANT, "")
+                        .await?;
+                }
+                false => activity.request_admission(nickname).await?,
+            }
+            active_channel.activity.set_nickname(nickname).await?;
+            active_channel
+                .activity
+                .set_profile(&store.get_profile())
+                .await?;
+            active_channel
+                .activity
+                .set_status(PeerStatus::Online)
+                .await?;
+            if let Some(cap) = store.get_retention().chat_history_cap {
+                if let Err(e) = active_channel.activity.prune_messages(cap).await {
+                    warn!("Failed to prune chat history: {e}");
+                }
+            }
+        }
 
         let topic_id = active_channel.activity.id().to_string();
+        store.record_room_visit(&topic_id)?;
+        // Catch up on challenges once the listener can see the password
+        let activity = active_channel.activity.clone();
+        let password = active_channel.room_password.clone();
         // Store the active channel info
         *self.active_channel.lock().await = Some(active_channel);
+        if let (Some(password), false) = (password, activity.is_read_only()) {
+            let caught_up = match hosting {
+                true => activity.challenge_pending().await,
+                false => activity.answer_challenge(&password).await,
+            };
+            if let Err(e) = caught_up {
+                warn!("Failed to catch up on admission challenges: {e}");
+            }
+        }
 
         Ok(topic_id)
     }
 }
+
+/// Describe the active channel for the lobby.
+async fn build_advert(channel: &ActiveChannel) -> anyhow::Result<RoomAdvert> {
+    let meta = channel
+        .activity
+        .room_meta()
+        .await?
+        .ok_or_else(|| anyhow!("room has no name yet"))?;
+    let seats_free = match channel.activity.get_game().await? {
+        Some(game) => game.seats_free(),
+        None => 2,
+    };
+    Ok(RoomAdvert {
+        host: channel.activity.node_id(),
+        name: meta.name,
+        rules: meta.rules,
+        seats_free: seats_free as u8,
+        ticket: channel.activity.ticket().await?,
+        spectator_ticket: channel.activity.spectator_ticket().await?,
+        timestamp: get_timestamp(),
+    })
+}
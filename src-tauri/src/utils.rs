@@ -1,11 +1,40 @@
 use anyhow::Context as _;
 use iroh::SecretKey;
-use std::{sync::Arc, time::SystemTime};
+
+use crate::{
+    error::app_error,
+    gossip::{
+        doc::{profile::Profile, ticket::RoomCredentials},
+        notify::UnreadCount,
+        storage::RetentionPolicy,
+        NetworkMode, RelaySettings,
+    },
+    identity,
+    vault::Vault,
+};
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
 use tauri::Wry;
 use tauri_plugin_store::{Store, StoreExt as _};
 
 pub struct AppStore(Arc<Store<Wry>>);
 
+/// Store entries a secret key is kept under, in plaintext or sealed with the vault.
+struct KeySlot {
+    plain: &'static str,
+    sealed: &'static str,
+}
+
+/// The identity the node starts with.
+const IDENTITY: KeySlot = KeySlot {
+    plain: "key",
+    sealed: "sealed_key",
+};
+/// The identity last replaced by a rotation or import, kept so it can be restored.
+const PREVIOUS_IDENTITY: KeySlot = KeySlot {
+    plain: "previous_key",
+    sealed: "sealed_previous_key",
+};
+
 impl AppStore {
     /// Get a handle for the persistent background store of this application
     pub fn acquire(app: &tauri::AppHandle) -> anyhow::Result<Self> {
@@ -24,6 +53,17 @@ impl AppStore {
         self.0.set("nickname", serde_json::to_value(nickname)?);
         Ok(())
     }
+    /// Return the profile published in the rooms we join
+    pub fn get_profile(&self) -> Profile {
+        self.0
+            .get("profile")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_profile(&self, profile: &Profile) -> anyhow::Result<()> {
+        self.0.set("profile", serde_json::to_value(profile)?);
+        Ok(())
+    }
     /// Return the list of recently visited rooms
     #[allow(unused)]
     pub fn get_recent_rooms(&self) -> Vec<String> {
@@ -32,23 +72,261 @@ impl AppStore {
             .map(|val| serde_json::from_value(val).unwrap_or_default())
             .unwrap_or_default()
     }
+    /// Return the networking mode to start the node in
+    pub fn get_network_mode(&self) -> NetworkMode {
+        self.0
+            .get("network_mode")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_network_mode(&self, mode: NetworkMode) -> anyhow::Result<()> {
+        self.0.set("network_mode", serde_json::to_value(mode)?);
+        Ok(())
+    }
+    /// Return the relay servers to start the node with
+    pub fn get_relay_settings(&self) -> RelaySettings {
+        self.0
+            .get("relays")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_relay_settings(&self, relays: &RelaySettings) -> anyhow::Result<()> {
+        self.0.set("relays", serde_json::to_value(relays)?);
+        Ok(())
+    }
+    /// Return what to keep on disk
+    pub fn get_retention(&self) -> RetentionPolicy {
+        self.0
+            .get("retention")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_retention(&self, policy: &RetentionPolicy) -> anyhow::Result<()> {
+        self.0.set("retention", serde_json::to_value(policy)?);
+        Ok(())
+    }
+    /// Return when each stored room was last joined, in micros by room ID
+    pub fn get_room_visits(&self) -> HashMap<String, u64> {
+        self.0
+            .get("room_visits")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_room_visits(&self, visits: &HashMap<String, u64>) -> anyhow::Result<()> {
+        self.0.set("room_visits", serde_json::to_value(visits)?);
+        Ok(())
+    }
+    /// Record joining a room now
+    pub fn record_room_visit(&self, id: &str) -> anyhow::Result<()> {
+        let mut visits = self.get_room_visits();
+        visits.insert(id.to_string(), get_timestamp());
+        self.set_room_visits(&visits)
+    }
+    /// Return the unread counts of every room with messages we haven't read
+    pub fn get_unread(&self) -> HashMap<String, UnreadCount> {
+        self.0
+            .get("unread")
+            .and_then(|val| serde_json::from_value(val).ok())
+            .unwrap_or_default()
+    }
+    pub fn set_unread(&self, unread: &HashMap<String, UnreadCount>) -> anyhow::Result<()> {
+        self.0.set("unread", serde_json::to_value(unread)?);
+        Ok(())
+    }
+    /// Count new messages in a room as unread, returning the room's counts
+    pub fn record_unread(&self, id: &str, added: UnreadCount) -> anyhow::Result<UnreadCount> {
+        let mut unread = self.get_unread();
+        let count = unread.entry(id.to_string()).or_default();
+        count.messages += added.messages;
+        count.mentions += added.mentions;
+        let count = *count;
+        self.set_unread(&unread)?;
+        Ok(count)
+    }
+    /// Forget a room's unread messages
+    pub fn mark_read(&self, id: &str) -> anyhow::Result<()> {
+        let mut unread = self.get_unread();
+        if unread.remove(id).is_some() {
+            self.set_unread(&unread)?;
+        }
+        Ok(())
+    }
+    /// Whether the identity key is sealed with a passphrase.
+    pub fn is_encrypted(&self) -> bool {
+        self.0.has(IDENTITY.sealed)
+    }
+    /// Return the stored identity key, generating one on first run.
+    ///
+    /// A key which fails to load is an error rather than replaced, as replacing it
+    /// would lose the player's identity.
     pub fn get_secret_key(&self) -> anyhow::Result<SecretKey> {
-        match self.0.get("key") {
-            Some(val) => match serde_json::from_value::<SecretKey>(val) {
-                Ok(key) => Ok(key),
-                Err(_) => {
-                    let key = SecretKey::generate(rand::rngs::OsRng);
-                    self.0.set("key", serde_json::to_value(&key)?);
-                    Ok(key)
-                }
-            },
+        if self.is_encrypted() {
+            return Err(app_error!(
+                Locked,
+                "Identity key is encrypted, unlock it first."
+            ));
+        }
+        match self.0.get(IDENTITY.plain) {
+            Some(val) => serde_json::from_value::<SecretKey>(val).map_err(|_| {
+                app_error!(
+                    Corrupt,
+                    "Stored identity key is corrupt, import a backup to restore it."
+                )
+            }),
             None => {
                 let key = SecretKey::generate(rand::rngs::OsRng);
-                self.0.set("key", serde_json::to_value(&key)?);
+                self.set_secret_key(&key, None)?;
                 Ok(key)
             }
         }
     }
+    /// Return the identity key sealed with the vault's passphrase.
+    pub fn unlock_secret_key(&self, vault: &Vault) -> anyhow::Result<SecretKey> {
+        let sealed: String = self
+            .0
+            .get(IDENTITY.sealed)
+            .and_then(|val| serde_json::from_value(val).ok())
+            .ok_or_else(|| app_error!(NotFound, "Identity key is not encrypted."))?;
+        identity::open_backup(&sealed, vault)
+    }
+    /// Return the identity key the next startup uses, which differs from the one in use
+    /// after a rotation or import.
+    pub fn stored_secret_key(&self, vault: Option<&Vault>) -> anyhow::Result<SecretKey> {
+        self.read_key(&IDENTITY, vault)?
+            .ok_or_else(|| app_error!(NotFound, "No identity key is stored."))
+    }
+    /// Store the identity key, used from the next startup.
+    ///
+    /// With a vault, the key is sealed with its passphrase and any plaintext copy removed.
+    pub fn set_secret_key(&self, key: &SecretKey, vault: Option<&Vault>) -> anyhow::Result<()> {
+        self.write_key(&IDENTITY, key, vault)
+    }
+    /// Replace the identity key, keeping the one it replaces so it can be restored.
+    ///
+    /// A stored key which can't be read, the reason to import a backup, isn't kept.
+    pub fn replace_secret_key(&self, key: &SecretKey, vault: Option<&Vault>) -> anyhow::Result<()> {
+        match self.read_key(&IDENTITY, vault) {
+            Ok(Some(previous)) if previous.public() != key.public() => {
+                self.write_key(&PREVIOUS_IDENTITY, &previous, vault)?
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Not keeping the unreadable identity key: {e}"),
+        }
+        self.set_secret_key(key, vault)
+    }
+    /// Swap the identity key with the one it last replaced, returning the restored key.
+    pub fn restore_previous_secret_key(&self, vault: Option<&Vault>) -> anyhow::Result<SecretKey> {
+        let previous = self
+            .read_key(&PREVIOUS_IDENTITY, vault)?
+            .ok_or_else(|| app_error!(NotFound, "There is no previous identity to restore."))?;
+        let current = self.stored_secret_key(vault)?;
+        self.write_key(&PREVIOUS_IDENTITY, &current, vault)?;
+        self.set_secret_key(&previous, vault)?;
+        Ok(previous)
+    }
+    /// Seal the stored identity keys with another vault, or store them in plaintext.
+    pub fn reseal_secret_keys(
+        &self,
+        from: Option<&Vault>,
+        to: Option<&Vault>,
+    ) -> anyhow::Result<()> {
+        for slot in [&IDENTITY, &PREVIOUS_IDENTITY] {
+            if let Some(key) = self.read_key(slot, from)? {
+                self.write_key(slot, &key, to)?;
+            }
+        }
+        Ok(())
+    }
+    fn read_key(&self, slot: &KeySlot, vault: Option<&Vault>) -> anyhow::Result<Option<SecretKey>> {
+        let corrupt = || app_error!(Corrupt, "Stored identity key is corrupt.");
+        match (self.0.get(slot.sealed), vault) {
+            (Some(val), Some(vault)) => {
+                let sealed = serde_json::from_value::<String>(val).map_err(|_| corrupt())?;
+                identity::open_backup(&sealed, vault).map(Some)
+            }
+            (Some(_), None) => Err(app_error!(
+                Locked,
+                "Identity key is encrypted, unlock it first."
+            )),
+            (None, _) => self
+                .0
+                .get(slot.plain)
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|_| corrupt()),
+        }
+    }
+    fn write_key(
+        &self,
+        slot: &KeySlot,
+        key: &SecretKey,
+        vault: Option<&Vault>,
+    ) -> anyhow::Result<()> {
+        match vault {
+            Some(vault) => {
+                let sealed = identity::seal_backup(key, vault)?;
+                self.0.set(slot.sealed, serde_json::to_value(sealed)?);
+                self.0.delete(slot.plain);
+            }
+            None => {
+                self.0.set(slot.plain, serde_json::to_value(key)?);
+                self.0.delete(slot.sealed);
+            }
+        }
+        // don't leave a plaintext key on disk waiting for the autosave
+        self.0.save()?;
+        Ok(())
+    }
+    /// Return what we keep about each stored room, by room ID.
+    ///
+    /// Sealed credentials need the vault they were sealed with.
+    pub fn get_room_credentials(
+        &self,
+        vault: Option<&Vault>,
+    ) -> anyhow::Result<HashMap<String, RoomCredentials>> {
+        let corrupt = || app_error!(Corrupt, "Stored room credentials are corrupt.");
+        match (self.0.get("sealed_room_credentials"), vault) {
+            (Some(val), Some(vault)) => {
+                let sealed = serde_json::from_value::<String>(val)
+                    .ok()
+                    .and_then(|sealed| hex::decode(sealed).ok())
+                    .ok_or_else(corrupt)?;
+                serde_json::from_slice(&vault.open(sealed)?).map_err(|_| corrupt())
+            }
+            (Some(_), None) => Err(app_error!(
+                Locked,
+                "Room credentials are encrypted, unlock them first."
+            )),
+            (None, _) => Ok(self
+                .0
+                .get("room_credentials")
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(|_| corrupt())?
+                .unwrap_or_default()),
+        }
+    }
+    /// Replace what we keep about stored rooms, sealing it with the vault if given.
+    pub fn set_room_credentials(
+        &self,
+        rooms: &HashMap<String, RoomCredentials>,
+        vault: Option<&Vault>,
+    ) -> anyhow::Result<()> {
+        match vault {
+            Some(vault) => {
+                let sealed = hex::encode(vault.seal(serde_json::to_vec(rooms)?)?);
+                self.0
+                    .set("sealed_room_credentials", serde_json::to_value(sealed)?);
+                self.0.delete("room_credentials");
+            }
+            None => {
+                self.0.set("room_credentials", serde_json::to_value(rooms)?);
+                self.0.delete("sealed_room_credentials");
+            }
+        }
+        self.0.save()?;
+        Ok(())
+    }
 }
 
 /// Generate a Unix timestamp in Micros.
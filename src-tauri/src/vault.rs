@@ -0,0 +1,175 @@
+//! Optional at-rest encryption of the identity key and the keys of stored rooms.
+//!
+//! Everything is sealed with ChaCha20-Poly1305 under a key derived from the player's
+//! passphrase with PBKDF2, so no OS keyring is needed. Chat content is sealed as it is
+//! written with its room's key, see [`crate::gossip::doc::sealing`], so the iroh data
+//! directory itself is never sealed.
+//!
+//! Deriving a key is deliberately slow, so a [`Vault`] seals everything under one salt
+//! for the session and keeps the keys it derived, opening data sealed in earlier
+//! sessions with one derivation per salt.
+
+use crate::error::app_error;
+use anyhow::anyhow;
+use rand::RngCore as _;
+use ring::{aead, pbkdf2};
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{Arc, Mutex},
+};
+
+const PBKDF2_ROUNDS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+pub const SALT_LEN: usize = 16;
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Derive a sealing key from the passphrase.
+fn derive_key(passphrase: &str, salt: &[u8]) -> anyhow::Result<aead::LessSafeKey> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ROUNDS,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow!("failed to create sealing key"))?;
+    Ok(aead::LessSafeKey::new(key))
+}
+
+/// Check the passphrase is long enough to protect anything.
+pub fn check_passphrase(passphrase: &str) -> anyhow::Result<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(app_error!(
+            InvalidInput,
+            "Passphrase must be at least {MIN_PASSPHRASE_LEN} characters."
+        ));
+    }
+    Ok(())
+}
+
+/// Seal data under a fresh nonce, which is prepended to the result.
+pub(crate) fn seal(key: &aead::LessSafeKey, mut data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce),
+        aead::Aad::empty(),
+        &mut data,
+    )
+    .map_err(|_| anyhow!("failed to encrypt data"))?;
+    Ok([nonce.as_slice(), &data].concat())
+}
+
+/// Open data produced by [`seal`].
+pub(crate) fn open(key: &aead::LessSafeKey, mut data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    if data.len() < aead::NONCE_LEN {
+        return Err(app_error!(Corrupt, "Sealed data is truncated."));
+    }
+    let mut sealed = data.split_off(aead::NONCE_LEN);
+    let nonce = aead::Nonce::try_assume_unique_for_key(&data)
+        .map_err(|_| app_error!(Corrupt, "Sealed data is truncated."))?;
+    let len = key
+        .open_in_place(nonce, aead::Aad::empty(), &mut sealed)
+        .map_err(|_| app_error!(Corrupt, "Sealed data is corrupt."))?
+        .len();
+    sealed.truncate(len);
+    Ok(sealed)
+}
+
+/// The passphrase protecting this install's identity and data.
+#[derive(Clone)]
+pub struct Vault {
+    passphrase: String,
+    /// Salt of the key everything is sealed with this session
+    salt: [u8; SALT_LEN],
+    /// Keys derived from the passphrase so far, by salt
+    keys: Arc<Mutex<HashMap<[u8; SALT_LEN], Arc<aead::LessSafeKey>>>>,
+}
+
+impl Vault {
+    pub fn new(passphrase: &str) -> anyhow::Result<Self> {
+        check_passphrase(passphrase)?;
+        let mut salt = [0u8; SALT_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Ok(Self {
+            passphrase: passphrase.to_string(),
+            salt,
+            keys: Arc::default(),
+        })
+    }
+
+    /// Return the salt this session seals with.
+    pub fn salt(&self) -> [u8; SALT_LEN] {
+        self.salt
+    }
+
+    /// Return the key derived from the passphrase and a salt, deriving it only the first
+    /// time the salt is seen.
+    pub fn key(&self, salt: &[u8]) -> anyhow::Result<Arc<aead::LessSafeKey>> {
+        let salt: [u8; SALT_LEN] = salt
+            .try_into()
+            .map_err(|_| app_error!(Corrupt, "Sealed data has a malformed salt."))?;
+        let mut keys = self.keys.lock().expect("vault keys poisoned");
+        if let Some(key) = keys.get(&salt) {
+            return Ok(key.clone());
+        }
+        let key = Arc::new(derive_key(&self.passphrase, &salt)?);
+        keys.insert(salt, key.clone());
+        Ok(key)
+    }
+
+    /// Seal data under the session's key, prepending its salt to the result.
+    pub fn seal(&self, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let sealed = seal(&*self.key(&self.salt)?, data)?;
+        Ok([self.salt.as_slice(), &sealed].concat())
+    }
+
+    /// Open data produced by [`Vault::seal`], failing if it was sealed with another passphrase.
+    pub fn open(&self, mut data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        if data.len() < SALT_LEN {
+            return Err(app_error!(Corrupt, "Sealed data is truncated."));
+        }
+        let sealed = data.split_off(SALT_LEN);
+        open(&*self.key(&data)?, sealed)
+            .map_err(|_| app_error!(WrongPassphrase, "Wrong passphrase."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{AppError, ErrorCode};
+
+    #[test]
+    fn sealed_data_needs_the_same_passphrase() {
+        let vault = Vault::new("correct horse").unwrap();
+        let sealed = vault.seal(b"room keys".to_vec()).unwrap();
+        assert_eq!(vault.open(sealed.clone()).unwrap(), b"room keys");
+
+        let error = Vault::new("battery staple")
+            .unwrap()
+            .open(sealed)
+            .unwrap_err();
+        let error = error.downcast_ref::<AppError>().unwrap();
+        assert_eq!(error.code, ErrorCode::WrongPassphrase);
+    }
+
+    #[test]
+    fn keys_are_derived_once_per_salt() {
+        let vault = Vault::new("correct horse").unwrap();
+        let first = vault.seal(b"identity".to_vec()).unwrap();
+        let second = vault.seal(b"room keys".to_vec()).unwrap();
+        assert_eq!(first[..SALT_LEN], second[..SALT_LEN]);
+        assert_eq!(vault.keys.lock().unwrap().len(), 1);
+
+        // a later session opens it with one more derivation, and seals with its own salt
+        let later = Vault::new("correct horse").unwrap();
+        assert_eq!(later.open(first).unwrap(), b"identity");
+        assert_eq!(later.open(second).unwrap(), b"room keys");
+        assert_eq!(later.keys.lock().unwrap().len(), 1);
+        later.seal(Vec::new()).unwrap();
+        assert_eq!(later.keys.lock().unwrap().len(), 2);
+    }
+}
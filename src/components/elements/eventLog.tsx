@@ -104,46 +104,63 @@ const RenderEvent: React.FC<{ event: ChatEvent }> = ({ event }) => {
   );
 
   switch (event.type) {
-    case "syncFinished":
-      return <Card title="Sync Finished"></Card>;
-    case "contentReady":
-      return <Card title="Content Ready"></Card>;
-    case "pendingContentReady":
-      return <Card title="Pending Content Ready"></Card>;
     case "disconnected":
       return <Card title="Disconnected"></Card>;
-    case "peerUpdate":
+    case "delta":
       return (
-        <Card title="Peer Info Updated">
-          <Property label="Node ID">{event.info.id}</Property>
-          <Property label="Nickname">{event.info.nickname}</Property>
-          <Property label="Status">{event.info.status}</Property>
-          <Property label="Ready">{event.info.ready}</Property>
+        <Card title="Room Changed">
+          {event.delta.messages.map((message) => (
+            <Property
+              key={`${message.sender}-${message.timestamp}`}
+              label={`Message from ${message.nickname}`}
+            >
+              {message.content}
+            </Property>
+          ))}
+          {event.delta.peers.map((peer) => (
+            <Property key={peer.id} label={`Peer ${peer.nickname}`}>
+              {peer.status}
+              {peer.ready ? ", ready" : ""}
+            </Property>
+          ))}
+          {event.delta.neighborsUp.map((nodeId) => (
+            <Property key={nodeId} label="Neighbor Connected">
+              {nodeId}
+            </Property>
+          ))}
+          {event.delta.neighborsDown.map((nodeId) => (
+            <Property key={nodeId} label="Neighbor Disconnected">
+              {nodeId}
+            </Property>
+          ))}
+          {event.delta.game && (
+            <Property label="Game">Version {event.delta.game.version}</Property>
+          )}
+          {event.delta.syncFinished && (
+            <Property label="Sync">Finished</Property>
+          )}
         </Card>
       );
-    case "newMessage":
+    case "syncProgress":
+    case "syncSummary": {
+      const progress =
+        event.type === "syncProgress" ? event.progress : event.summary;
       return (
-        <Card title="Message Received">
-          <Property label="From">{event.message.sender}</Property>
-          <Property label="Nickname">{event.message.nickname}</Property>
-          <Property label="Message">{event.message.content}</Property>
-          <Property label="Timestamp">
-            {new Date(event.message.timestamp / 1000).toLocaleString()}
+        <Card
+          title={event.type === "syncProgress" ? "Sync Progress" : "Sync Complete"}
+        >
+          {progress.peers.map((peer) => (
+            <Property key={peer.nodeId} label={peer.nodeId}>
+              {peer.contentFetched}/{peer.entriesReceived} entries,{" "}
+              {peer.bytesFetched} bytes fetched
+            </Property>
+          ))}
+          <Property label="Outstanding">
+            {progress.blobsOutstanding} blobs, {progress.bytesOutstanding} bytes
           </Property>
         </Card>
       );
-    case "neighborUp":
-      return (
-        <Card title="Neighbor Connected">
-          <Property label="Node ID">{event.nodeId}</Property>
-        </Card>
-      );
-    case "neighborDown":
-      return (
-        <Card title="Neighbor Disconnected">
-          <Property label="Node ID">{event.nodeId}</Property>
-        </Card>
-      );
+    }
     case "errored":
       return (
         <Card title="Error Occurred">
@@ -1,11 +1,11 @@
-import { useEffect, useState } from "react";
-import { ChatEvent } from "types/events";
+import { useEffect, useRef, useState } from "react";
+import { ChatEvent, Delta } from "types/events";
 import { listen } from "@tauri-apps/api/event";
 import TopBar from "components/elements/topbar";
 import EventLogModal from "components/elements/eventLog";
 import Messages from "components/elements/messages";
 import { ChatMessage, PeerInfo } from "types";
-import { getMessageLog, getNodeId, getPeers } from "services/ipc";
+import { getNodeId, getRoomSnapshot } from "services/ipc";
 
 export function ChatPage() {
   const [messages, setMessages] = useState<ChatMessage[]>([]);
@@ -14,70 +14,71 @@ export function ChatPage() {
     new Map()
   );
   const [openLog, setOpenLog] = useState<boolean>(false);
-  const [myNodeId, setMyNodeId] = useState<string | undefined>();
+  const myNodeId = useRef<string | undefined>();
+
   useEffect(() => {
-    getNodeId().then((id) => {
-      setMyNodeId(id);
-      getMessageLog().then((messages) => {
-        setMessages(messages);
-      });
-      getPeers().then((peers) => {
-        const newNeighbours = new Map();
-        peers.forEach((peer) => {
-          if (peer.id !== id) {
-            newNeighbours.set(peer.id, peer);
-          }
+    // deltas are held back until the snapshot says which ones it already includes
+    let cursor: number | undefined;
+    let heldBack: Delta[] = [];
+
+    const applyDelta = (delta: Delta) => {
+      if (cursor === undefined) {
+        heldBack.push(delta);
+        return;
+      }
+      if (delta.seq <= cursor) return;
+      if (delta.messages.length > 0) {
+        setMessages((messages) => {
+          // edited messages replace the ones they were sent as
+          const sameMessage = (a: ChatMessage, b: ChatMessage) =>
+            a.sender === b.sender && a.timestamp === b.timestamp;
+          const kept = messages.filter(
+            (message) => !delta.messages.some((m) => sameMessage(m, message))
+          );
+          return [...kept, ...delta.messages].sort(
+            (a, b) => a.timestamp - b.timestamp
+          );
         });
-        setNeighbours(newNeighbours);
-      });
-    });
-  }, []);
+      }
+      if (delta.peers.length > 0) {
+        setNeighbours((neighbours) => {
+          const newNeighbours = new Map(neighbours); // react needs us to make a new object here
+          delta.peers.forEach((peer) => {
+            if (peer.id !== myNodeId.current) {
+              newNeighbours.set(peer.id, peer);
+            }
+          });
+          return newNeighbours;
+        });
+      }
+    };
 
-  useEffect(() => {
-    const eventsRef = listen<ChatEvent>("chat-event", async (event) => {
+    const eventsRef = listen<ChatEvent>("chat-event", (event) => {
       setEventLog((eventLog) => [...eventLog, event.payload]);
       console.log(event);
-      // any time anything changes, update all data.
-      getMessageLog().then((messages) => {
-        setMessages(messages);
-      });
-      getPeers().then((peers) => {
-        const newNeighbours = new Map();
-        peers.forEach((peer) => {
-          if (peer.id !== myNodeId) {
-            newNeighbours.set(peer.id, peer);
-          }
-        });
-        setNeighbours(newNeighbours);
-      });
-      // if (event.payload.type === "newMessage") {
-      //   const message = event.payload.message;
-      //   setMessages((messages) => [...messages, message]);
-      // } else if (event.payload.type === "peerUpdate") {
-      //   const peer = event.payload.info;
-      //   setNeighbours((neighbours) => {
-      //     if (peer.id === myNodeId) return neighbours;
-      //     if (!neighbours.has(peer.id)) {
-      //       notify(`New peer joined ${peer.nickname}`);
-      //     }
-      //     const newNeighbours = new Map(neighbours); // react needs us to make a new object here
-      //     newNeighbours.set(peer.id, peer);
-      //     return newNeighbours;
-      //   });
-      // } else if (event.payload.type === "contentReady") {
-      //   getMessageLog().then((messages) => {
-      //     setMessages(messages);
-      //   });
-      //   getPeers().then((peers) => {
-      //     const newNeighbours = new Map();
-      //     peers.forEach((peer) => {
-      //       if (peer.id === myNodeId) return;
-      //       newNeighbours.set(peer.id, peer);
-      //     });
-      //     setNeighbours(newNeighbours);
-      //   });
-      // }
+      if (event.payload.type === "delta") {
+        applyDelta(event.payload.delta);
+      }
     });
+    // only take the snapshot once listening, so no delta falls in between
+    eventsRef
+      .then(() => getNodeId())
+      .then(async (id) => {
+        myNodeId.current = id;
+        const snapshot = await getRoomSnapshot();
+        if (!snapshot) return;
+        setMessages(snapshot.messages);
+        setNeighbours(
+          new Map(
+            snapshot.peers
+              .filter((peer) => peer.id !== id)
+              .map((peer): [string, PeerInfo] => [peer.id, peer])
+          )
+        );
+        cursor = snapshot.cursor;
+        heldBack.forEach(applyDelta);
+        heldBack = [];
+      });
     return () => {
       eventsRef.then((drop) => drop());
     };
@@ -0,0 +1,22 @@
+import { AppError, ErrorCode } from "types";
+
+/** Whether a rejected IPC call carries a coded backend error. */
+export function isAppError(e: unknown): e is AppError {
+  return (
+    typeof e === "object" &&
+    e !== null &&
+    "code" in e &&
+    "message" in e &&
+    typeof (e as AppError).message === "string"
+  );
+}
+
+/** The code of a rejected IPC call, `Internal` when it has none. */
+export function errorCode(e: unknown): ErrorCode {
+  return isAppError(e) ? e.code : "Internal";
+}
+
+/** The human readable message of a rejected IPC call. */
+export function errorMessage(e: unknown): string {
+  return isAppError(e) ? e.message : `${e}`;
+}
@@ -1,6 +1,39 @@
 import { invoke } from "@tauri-apps/api/core";
+import { errorMessage } from "./errors";
 import { notifyError } from "./notifications";
-import { ChatMessage, PeerInfo } from "types";
+import {
+  AdmissionSettings,
+  AdmissionView,
+  Attachment,
+  ChatMessage,
+  DrawOffer,
+  DrawResponse,
+  GameView,
+  MatchId,
+  ModerationView,
+  Move,
+  NetworkMode,
+  PeerConnection,
+  PeerInfo,
+  Piece,
+  PlayerType,
+  Profile,
+  RelaySettings,
+  RetentionPolicy,
+  RoomAdvert,
+  RoomMeta,
+  RoomSnapshot,
+  SearchHit,
+  SearchQuery,
+  StorageReport,
+  TakebackRequest,
+  TakebackResponse,
+  TournamentFormat,
+  TournamentView,
+  UnreadCount,
+  VoiceMode,
+  VoiceView,
+} from "types";
 
 /** Create a new room and return the information required to send
  an out-of-band Join Code to others to connect. */
@@ -9,7 +42,7 @@ export async function createRoom(nickname: string): Promise<string> {
     let ticket = await invoke<string>("create_room", { nickname });
     return ticket;
   } catch (e) {
-    notifyError(`Failed to create room: ${e}`, "RoomCreateError");
+    notifyError(`Failed to create room: ${errorMessage(e)}`, "RoomCreateError");
     return "";
   }
 }
@@ -17,13 +50,14 @@ export async function createRoom(nickname: string): Promise<string> {
 /** Join an existing room. */
 export async function joinRoom(
   ticket: string,
-  nickname: string
+  nickname: string,
+  password?: string
 ): Promise<boolean> {
   try {
-    await invoke("join_room", { ticket, nickname });
+    await invoke("join_room", { ticket, nickname, password: password ?? null });
     return true;
   } catch (e) {
-    notifyError(`Failed to join room: ${e}`, "RoomJoinError");
+    notifyError(`Failed to join room: ${errorMessage(e)}`, "RoomJoinError");
     return false;
   }
 }
@@ -33,17 +67,67 @@ export async function getLatestTicket(): Promise<string | null> {
   try {
     return await invoke<string | null>("get_latest_ticket");
   } catch (e) {
-    notifyError(`Failed to get latest ticket: ${e}`, "TicketGetError");
+    notifyError(`Failed to get latest ticket: ${errorMessage(e)}`, "TicketGetError");
     return null;
   }
 }
 
 /** Send a message to a room. */
-export async function sendMessage(message: string): Promise<void> {
+export async function sendMessage(message: string, attachments?: Attachment[]): Promise<void> {
   try {
-    await invoke("send_message", { message });
+    await invoke("send_message", { message, attachments });
   } catch (e) {
-    notifyError(`Failed to send message: ${e}`, "MessageSendError");
+    notifyError(`Failed to send message: ${errorMessage(e)}`, "MessageSendError");
+  }
+}
+
+/** Store a file in the room, returning the attachment to send with a message. */
+export async function attachFile(file: File): Promise<Attachment | null> {
+  try {
+    const data = Array.from(new Uint8Array(await file.arrayBuffer()));
+    return await invoke<Attachment>("attach_file", { name: file.name, mime: file.type, data });
+  } catch (e) {
+    notifyError(`Failed to attach file: ${errorMessage(e)}`, "AttachFileError");
+    return null;
+  }
+}
+
+/** Get an attachment's content, downloading it from peers if needed. */
+export async function fetchAttachment(hash: string): Promise<ArrayBuffer | null> {
+  try {
+    return await invoke<ArrayBuffer>("fetch_attachment", { hash });
+  } catch (e) {
+    notifyError(`Failed to fetch attachment: ${errorMessage(e)}`, "AttachmentFetchError");
+    return null;
+  }
+}
+
+/** Search the chat history of the current room, or of every stored room. */
+export async function searchMessages(query: SearchQuery): Promise<SearchHit[]> {
+  try {
+    return await invoke<SearchHit[]>("search_messages", { query });
+  } catch (e) {
+    notifyError(`Failed to search messages: ${errorMessage(e)}`, "MessageSearchError");
+    return [];
+  }
+}
+
+/** Get the unread messages and mentions of every room, by room ID. */
+export async function getUnreadCounts(): Promise<Record<string, UnreadCount>> {
+  try {
+    return await invoke<Record<string, UnreadCount>>("get_unread_counts");
+  } catch (e) {
+    notifyError(`Failed to get unread counts: ${errorMessage(e)}`, "UnreadCountsGetError");
+    return {};
+  }
+}
+
+/** Mark every message in a room as read. */
+export async function markRoomRead(id: string): Promise<void> {
+  try {
+    await invoke("mark_room_read", { id });
+  } catch (e) {
+    notifyError(`Failed to mark room read: ${errorMessage(e)}`, "RoomMarkReadError");
   }
 }
 
@@ -52,7 +136,54 @@ export async function setNickname(nickname: string): Promise<void> {
   try {
     await invoke("set_nickname", { nickname });
   } catch (e) {
-    notifyError(`Failed to set nickname: ${e}`, "NicknameSetError");
+    notifyError(`Failed to set nickname: ${errorMessage(e)}`, "NicknameSetError");
+  }
+}
+
+/** Get a player's profile in the current room, or our own when no node is given. */
+export async function getProfile(nodeId?: string): Promise<Profile | null> {
+  try {
+    return await invoke<Profile | null>("get_profile", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to get profile: ${errorMessage(e)}`, "ProfileGetError");
+    return null;
+  }
+}
+
+/** Set our bio, favourite side and rating, shown in every room we join. */
+export async function setProfile(
+  bio: string,
+  favouriteSide: Piece,
+  rating: number | null,
+): Promise<Profile | null> {
+  try {
+    return await invoke<Profile>("set_profile", { bio, favouriteSide, rating });
+  } catch (e) {
+    notifyError(`Failed to set profile: ${errorMessage(e)}`, "ProfileSetError");
+    return null;
+  }
+}
+
+/** Replace our avatar with an image file, or remove it when none is given. */
+export async function setAvatar(image: File | null): Promise<Profile | null> {
+  try {
+    const data = image ? Array.from(new Uint8Array(await image.arrayBuffer())) : null;
+    return await invoke<Profile>("set_avatar", { data, mime: image?.type ?? null });
+  } catch (e) {
+    notifyError(`Failed to set avatar: ${errorMessage(e)}`, "AvatarSetError");
+    return null;
+  }
+}
+
+/** Get a player's avatar image, our own when no node is given.
+ Null when they have none or it hasn't been downloaded yet. */
+export async function getAvatar(nodeId?: string): Promise<ArrayBuffer | null> {
+  try {
+    const image = await invoke<ArrayBuffer>("get_avatar", { nodeId });
+    return image.byteLength > 0 ? image : null;
+  } catch (e) {
+    notifyError(`Failed to get avatar: ${errorMessage(e)}`, "AvatarGetError");
+    return null;
   }
 }
 
@@ -61,17 +192,93 @@ export async function getNickname(): Promise<string | null> {
   try {
     return await invoke<string | null>("get_nickname");
   } catch (e) {
-    notifyError(`Failed to get nickname: ${e}`, "NicknameGetError");
+    notifyError(`Failed to get nickname: ${errorMessage(e)}`, "NicknameGetError");
     return null;
   }
 }
 
+/** Get the networking mode the node is currently running in. */
+export async function getNetworkMode(): Promise<NetworkMode | null> {
+  try {
+    return await invoke<NetworkMode>("get_network_mode");
+  } catch (e) {
+    notifyError(`Failed to get network mode: ${errorMessage(e)}`, "NetworkModeGetError");
+    return null;
+  }
+}
+
+/** Choose the networking mode used from the next startup. */
+export async function setNetworkMode(mode: NetworkMode): Promise<void> {
+  try {
+    await invoke("set_network_mode", { mode });
+  } catch (e) {
+    notifyError(`Failed to set network mode: ${errorMessage(e)}`, "NetworkModeSetError");
+  }
+}
+
+/** Get the relay servers the node is currently using. */
+export async function getRelaySettings(): Promise<RelaySettings | null> {
+  try {
+    return await invoke<RelaySettings>("get_relay_settings");
+  } catch (e) {
+    notifyError(`Failed to get relay settings: ${errorMessage(e)}`, "RelaySettingsGetError");
+    return null;
+  }
+}
+
+/** Change the relay servers. This restarts the node and leaves the current room. */
+export async function setRelaySettings(relays: RelaySettings): Promise<void> {
+  try {
+    await invoke("set_relay_settings", { relays });
+  } catch (e) {
+    notifyError(`Failed to set relay settings: ${errorMessage(e)}`, "RelaySettingsSetError");
+  }
+}
+
+/** Get what is kept on disk. */
+export async function getRetention(): Promise<RetentionPolicy | null> {
+  try {
+    return await invoke<RetentionPolicy>("get_retention");
+  } catch (e) {
+    notifyError(`Failed to get retention policy: ${errorMessage(e)}`, "RetentionGetError");
+    return null;
+  }
+}
+
+/** Change what is kept on disk, deleting rooms which fall outside the new policy. */
+export async function setRetention(policy: RetentionPolicy): Promise<void> {
+  try {
+    await invoke("set_retention", { policy });
+  } catch (e) {
+    notifyError(`Failed to set retention policy: ${errorMessage(e)}`, "RetentionSetError");
+  }
+}
+
+/** Report the disk space taken by each stored room. */
+export async function getStorageUsage(): Promise<StorageReport | null> {
+  try {
+    return await invoke<StorageReport>("get_storage_usage");
+  } catch (e) {
+    notifyError(`Failed to get storage usage: ${errorMessage(e)}`, "StorageUsageError");
+    return null;
+  }
+}
+
+/** Delete a stored room other than the one currently joined. */
+export async function clearRoom(id: string): Promise<void> {
+  try {
+    await invoke("clear_room", { id });
+  } catch (e) {
+    notifyError(`Failed to clear room: ${errorMessage(e)}`, "RoomClearError");
+  }
+}
+
 /** Leave the currently joined room. */
 export async function leaveRoom(): Promise<void> {
   try {
     await invoke("leave_room");
   } catch (e) {
-    notifyError(`Failed to leave room: ${e}`, "RoomLeaveError");
+    notifyError(`Failed to leave room: ${errorMessage(e)}`, "RoomLeaveError");
   }
 }
 
@@ -80,17 +287,116 @@ export async function getNodeId(): Promise<string> {
   try {
     return await invoke<string>("get_node_id");
   } catch (e) {
-    notifyError(`Failed to get node id: ${e}`, "NodeIdGetError");
+    notifyError(`Failed to get node id: ${errorMessage(e)}`, "NodeIdGetError");
+    return "";
+  }
+}
+
+/** Return a short fingerprint of this node's identity, for players to compare. */
+export async function getIdentityFingerprint(): Promise<string> {
+  try {
+    return await invoke<string>("get_identity_fingerprint");
+  } catch (e) {
+    notifyError(`Failed to get identity: ${errorMessage(e)}`, "IdentityGetError");
     return "";
   }
 }
 
+/** Save the identity to a backup file at `path`, encrypted with the passphrase. */
+export async function exportIdentity(
+  path: string,
+  passphrase: string
+): Promise<void> {
+  try {
+    await invoke("export_identity", { path, passphrase });
+  } catch (e) {
+    notifyError(`Failed to export identity: ${errorMessage(e)}`, "IdentityExportError");
+  }
+}
+
+/** Restore the identity from a backup file, used once the app restarts.
+ The replaced identity is kept, see `restorePreviousIdentity`.
+ Returns the fingerprint of the restored identity. */
+export async function importIdentity(
+  path: string,
+  passphrase: string
+): Promise<string | null> {
+  try {
+    return await invoke<string>("import_identity", { path, passphrase });
+  } catch (e) {
+    notifyError(`Failed to import identity: ${errorMessage(e)}`, "IdentityImportError");
+    return null;
+  }
+}
+
+/** Replace the identity with a new one, used once the app restarts.
+ The replaced identity is kept, see `restorePreviousIdentity`.
+ Returns the fingerprint of the new identity. */
+export async function rotateIdentity(): Promise<string | null> {
+  try {
+    return await invoke<string>("rotate_identity");
+  } catch (e) {
+    notifyError(`Failed to rotate identity: ${errorMessage(e)}`, "IdentityRotateError");
+    return null;
+  }
+}
+
+/** Undo the last rotation or import, used once the app restarts.
+ Returns the fingerprint of the restored identity. */
+export async function restorePreviousIdentity(): Promise<string | null> {
+  try {
+    return await invoke<string>("restore_previous_identity");
+  } catch (e) {
+    notifyError(`Failed to restore identity: ${errorMessage(e)}`, "IdentityRestoreError");
+    return null;
+  }
+}
+
+/** Whether the app is waiting for the passphrase before it can start. */
+export async function isLocked(): Promise<boolean> {
+  try {
+    return await invoke<boolean>("is_locked");
+  } catch (e) {
+    notifyError(`Failed to check lock: ${errorMessage(e)}`, "LockGetError");
+    return false;
+  }
+}
+
+/** Decrypt the identity and stored data with the passphrase, then start the node. */
+export async function unlock(passphrase: string): Promise<boolean> {
+  try {
+    await invoke("unlock", { passphrase });
+    return true;
+  } catch (e) {
+    notifyError(`Failed to unlock: ${errorMessage(e)}`, "UnlockError");
+    return false;
+  }
+}
+
+/** Encrypt the identity and stored data with a passphrase. */
+export async function enableEncryption(passphrase: string): Promise<void> {
+  try {
+    await invoke("enable_encryption", { passphrase });
+  } catch (e) {
+    notifyError(`Failed to enable encryption: ${errorMessage(e)}`, "EncryptionEnableError");
+  }
+}
+
+/** Store the identity and data in plaintext again. */
+export async function disableEncryption(passphrase: string): Promise<void> {
+  try {
+    await invoke("disable_encryption", { passphrase });
+  } catch (e) {
+    notifyError(`Failed to disable encryption: ${errorMessage(e)}`, "EncryptionDisableError");
+  }
+}
+
 /** Return the list of messages sent be activity participants. */
 export async function getMessageLog(): Promise<ChatMessage[]> {
   try {
     return await invoke<ChatMessage[]>("get_message_log");
   } catch (e) {
-    notifyError(`Failed to get message log: ${e}`, "MessageLogGetError");
+    notifyError(`Failed to get message log: ${errorMessage(e)}`, "MessageLogGetError");
     return [];
   }
 }
@@ -100,7 +406,459 @@ export async function getPeers(): Promise<PeerInfo[]> {
   try {
     return await invoke<PeerInfo[]>("get_peers");
   } catch (e) {
-    notifyError(`Failed to get peers: ${e}`, "PeersGetError");
+    notifyError(`Failed to get peers: ${errorMessage(e)}`, "PeersGetError");
     return [];
   }
 }
+
+/** Return how joiners are admitted to the room, and who is waiting. */
+export async function getAdmission(): Promise<AdmissionView | null> {
+  try {
+    return await invoke<AdmissionView>("get_admission");
+  } catch (e) {
+    notifyError(`Failed to get admission: ${errorMessage(e)}`, "AdmissionGetError");
+    return null;
+  }
+}
+
+/** Change how joiners are admitted to the room we host. Joiners proving
+ the password are let in without waiting for approval. */
+export async function setAdmission(
+  approvalRequired: boolean,
+  password?: string
+): Promise<AdmissionSettings | null> {
+  try {
+    return await invoke<AdmissionSettings>("set_admission", {
+      approvalRequired,
+      password: password ?? null,
+    });
+  } catch (e) {
+    notifyError(`Failed to set admission: ${errorMessage(e)}`, "AdmissionSetError");
+    return null;
+  }
+}
+
+/** Let a node waiting to join into the room we host. */
+export async function approvePeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("approve_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to approve peer: ${errorMessage(e)}`, "PeerApproveError");
+  }
+}
+
+/** Turn away a node waiting to join the room we host. */
+export async function rejectPeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("reject_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to reject peer: ${errorMessage(e)}`, "PeerRejectError");
+  }
+}
+
+/** Return the name, description and rules of the room, if the host has set them. */
+export async function getRoomMeta(): Promise<RoomMeta | null> {
+  try {
+    return await invoke<RoomMeta | null>("get_room_meta");
+  } catch (e) {
+    notifyError(`Failed to get room details: ${errorMessage(e)}`, "RoomMetaGetError");
+    return null;
+  }
+}
+
+/** Describe the room we host. */
+export async function setRoomMeta(
+  name: string,
+  description: string,
+  variant: string,
+  rules: string,
+): Promise<RoomMeta | null> {
+  try {
+    return await invoke<RoomMeta>("set_room_meta", {
+      name,
+      description,
+      variant,
+      rules,
+    });
+  } catch (e) {
+    notifyError(`Failed to set room details: ${errorMessage(e)}`, "RoomMetaSetError");
+    return null;
+  }
+}
+
+/** Return the moderators and sanctioned nodes of the room, and our own role. */
+export async function getModeration(): Promise<ModerationView | null> {
+  try {
+    return await invoke<ModerationView>("get_moderation");
+  } catch (e) {
+    notifyError(`Failed to get moderation: ${errorMessage(e)}`, "ModerationGetError");
+    return null;
+  }
+}
+
+/** Appoint or dismiss a moderator of the room we host. */
+export async function setModerator(
+  nodeId: string,
+  moderator: boolean,
+): Promise<void> {
+  try {
+    await invoke("set_moderator", { nodeId, moderator });
+  } catch (e) {
+    notifyError(`Failed to set moderator: ${errorMessage(e)}`, "ModeratorSetError");
+  }
+}
+
+/** End a node's session in the room. */
+export async function kickPeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("kick_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to kick peer: ${errorMessage(e)}`, "PeerKickError");
+  }
+}
+
+/** Ignore everything a node writes to the room until it is unbanned. */
+export async function banPeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("ban_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to ban peer: ${errorMessage(e)}`, "PeerBanError");
+  }
+}
+
+/** Lift a ban. */
+export async function unbanPeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("unban_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to unban peer: ${errorMessage(e)}`, "PeerUnbanError");
+  }
+}
+
+/** Hide a node's chat messages until it is unmuted. */
+export async function mutePeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("mute_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to mute peer: ${errorMessage(e)}`, "PeerMuteError");
+  }
+}
+
+/** Lift a mute. */
+export async function unmutePeer(nodeId: string): Promise<void> {
+  try {
+    await invoke("unmute_peer", { nodeId });
+  } catch (e) {
+    notifyError(`Failed to unmute peer: ${errorMessage(e)}`, "PeerUnmuteError");
+  }
+}
+
+/** Return the connection quality to each peer in the room. */
+export async function getConnections(): Promise<PeerConnection[]> {
+  try {
+    return await invoke<PeerConnection[]>("get_connections");
+  } catch (e) {
+    notifyError(`Failed to get connections: ${errorMessage(e)}`, "ConnectionsGetError");
+    return [];
+  }
+}
+
+/** Join the room's voice chat, or switch mode if already in it. */
+export async function joinVoice(mode: VoiceMode): Promise<VoiceView | null> {
+  try {
+    return await invoke<VoiceView>("join_voice", { mode });
+  } catch (e) {
+    notifyError(`Failed to join voice chat: ${errorMessage(e)}`, "VoiceJoinError");
+    return null;
+  }
+}
+
+/** Hang up every voice call in the room. */
+export async function leaveVoice(): Promise<void> {
+  try {
+    await invoke("leave_voice");
+  } catch (e) {
+    notifyError(`Failed to leave voice chat: ${errorMessage(e)}`, "VoiceLeaveError");
+  }
+}
+
+/** Return the room's voice chat, or null if we haven't joined it. */
+export async function getVoice(): Promise<VoiceView | null> {
+  try {
+    return await invoke<VoiceView | null>("get_voice");
+  } catch (e) {
+    notifyError(`Failed to get voice chat: ${errorMessage(e)}`, "VoiceGetError");
+    return null;
+  }
+}
+
+/** Choose between push-to-talk and an open mic. */
+export async function setVoiceMode(mode: VoiceMode): Promise<VoiceView | null> {
+  try {
+    return await invoke<VoiceView>("set_voice_mode", { mode });
+  } catch (e) {
+    notifyError(`Failed to set voice mode: ${errorMessage(e)}`, "VoiceModeSetError");
+    return null;
+  }
+}
+
+/** Press or release the push-to-talk key. */
+export async function setTalking(talking: boolean): Promise<VoiceView | null> {
+  try {
+    return await invoke<VoiceView>("set_talking", { talking });
+  } catch (e) {
+    notifyError(`Failed to talk: ${errorMessage(e)}`, "VoiceTalkError");
+    return null;
+  }
+}
+
+/** Stop or resume hearing a peer in voice chat, which only affects what we play. */
+export async function muteVoicePeer(nodeId: string, muted: boolean): Promise<VoiceView | null> {
+  try {
+    return await invoke<VoiceView>("mute_voice_peer", { nodeId, muted });
+  } catch (e) {
+    notifyError(`Failed to mute voice peer: ${errorMessage(e)}`, "VoicePeerMuteError");
+    return null;
+  }
+}
+
+/** Return the current game state, if a game has been created. */
+export async function getGame(): Promise<GameView | null> {
+  try {
+    return await invoke<GameView | null>("get_game");
+  } catch (e) {
+    notifyError(`Failed to get game: ${errorMessage(e)}`, "GameGetError");
+    return null;
+  }
+}
+
+/** Get the peers, messages, game and metadata of the current room at once. */
+export async function getRoomSnapshot(): Promise<RoomSnapshot | null> {
+  try {
+    return await invoke<RoomSnapshot>("get_room_snapshot");
+  } catch (e) {
+    notifyError(`Failed to get room snapshot: ${errorMessage(e)}`, "RoomSnapshotGetError");
+    return null;
+  }
+}
+
+/** Take a seat in the current room's game, as a player or spectator. */
+export async function takeSeat(
+  role: PlayerType,
+  piece: Piece
+): Promise<GameView | null> {
+  try {
+    return await invoke<GameView>("take_seat", { role, piece });
+  } catch (e) {
+    notifyError(`Failed to take seat: ${errorMessage(e)}`, "SeatTakeError");
+    return null;
+  }
+}
+
+/** Start a new game with the seated players. */
+export async function startGame(): Promise<GameView | null> {
+  try {
+    return await invoke<GameView>("start_game");
+  } catch (e) {
+    notifyError(`Failed to start game: ${errorMessage(e)}`, "GameStartError");
+    return null;
+  }
+}
+
+/** Place our next piece on the board. */
+export async function playMove(x: number, y: number): Promise<Move | null> {
+  try {
+    return await invoke<Move>("play_move", { x, y });
+  } catch (e) {
+    notifyError(`Failed to play move: ${errorMessage(e)}`, "MovePlayError");
+    return null;
+  }
+}
+
+/** Ask the opponent to undo our last move. */
+export async function requestTakeback(): Promise<TakebackRequest | null> {
+  try {
+    return await invoke<TakebackRequest>("request_takeback");
+  } catch (e) {
+    notifyError(`Failed to request takeback: ${errorMessage(e)}`, "TakebackRequestError");
+    return null;
+  }
+}
+
+/** Return the outstanding takeback request, if any. */
+export async function getTakebackRequest(): Promise<TakebackRequest | null> {
+  try {
+    return await invoke<TakebackRequest | null>("get_takeback_request");
+  } catch (e) {
+    notifyError(`Failed to get takeback request: ${errorMessage(e)}`, "TakebackGetError");
+    return null;
+  }
+}
+
+/** Accept or decline the opponent's takeback request. */
+export async function respondTakeback(
+  accept: boolean
+): Promise<TakebackResponse | null> {
+  try {
+    return await invoke<TakebackResponse>("respond_takeback", { accept });
+  } catch (e) {
+    notifyError(`Failed to respond to takeback: ${errorMessage(e)}`, "TakebackRespondError");
+    return null;
+  }
+}
+
+/** Concede the current game. */
+export async function resign(): Promise<GameView | null> {
+  try {
+    return await invoke<GameView>("resign");
+  } catch (e) {
+    notifyError(`Failed to resign: ${errorMessage(e)}`, "ResignError");
+    return null;
+  }
+}
+
+/** Call off the current game before the first move. */
+export async function abortGame(): Promise<GameView | null> {
+  try {
+    return await invoke<GameView>("abort_game");
+  } catch (e) {
+    notifyError(`Failed to abort game: ${errorMessage(e)}`, "GameAbortError");
+    return null;
+  }
+}
+
+/** Offer the opponent a draw. */
+export async function offerDraw(): Promise<DrawOffer | null> {
+  try {
+    return await invoke<DrawOffer>("offer_draw");
+  } catch (e) {
+    notifyError(`Failed to offer draw: ${errorMessage(e)}`, "DrawOfferError");
+    return null;
+  }
+}
+
+/** Return the outstanding draw offer, if any. */
+export async function getDrawOffer(): Promise<DrawOffer | null> {
+  try {
+    return await invoke<DrawOffer | null>("get_draw_offer");
+  } catch (e) {
+    notifyError(`Failed to get draw offer: ${errorMessage(e)}`, "DrawOfferGetError");
+    return null;
+  }
+}
+
+/** Accept or decline the opponent's draw offer. */
+export async function respondDraw(
+  accept: boolean
+): Promise<DrawResponse | null> {
+  try {
+    return await invoke<DrawResponse>("respond_draw", { accept });
+  } catch (e) {
+    notifyError(`Failed to respond to draw offer: ${errorMessage(e)}`, "DrawRespondError");
+    return null;
+  }
+}
+
+/** Start a tournament between everyone online in the current room. */
+export async function startTournament(
+  format: TournamentFormat
+): Promise<TournamentView | null> {
+  try {
+    return await invoke<TournamentView>("start_tournament", { format });
+  } catch (e) {
+    notifyError(`Failed to start tournament: ${errorMessage(e)}`, "TournamentStartError");
+    return null;
+  }
+}
+
+/** Return the current room's tournament bracket and standings. */
+export async function getTournament(): Promise<TournamentView | null> {
+  try {
+    return await invoke<TournamentView | null>("get_tournament");
+  } catch (e) {
+    notifyError(`Failed to get tournament: ${errorMessage(e)}`, "TournamentGetError");
+    return null;
+  }
+}
+
+/** Return the game for one tournament match. */
+export async function getMatchGame(matchId: MatchId): Promise<GameView | null> {
+  try {
+    return await invoke<GameView | null>("get_match_game", { matchId });
+  } catch (e) {
+    notifyError(`Failed to get match: ${errorMessage(e)}`, "MatchGetError");
+    return null;
+  }
+}
+
+/** Place our next piece in one of our tournament matches. */
+export async function playMatchMove(
+  matchId: MatchId,
+  x: number,
+  y: number
+): Promise<Move | null> {
+  try {
+    return await invoke<Move>("play_match_move", { matchId, x, y });
+  } catch (e) {
+    notifyError(`Failed to play match move: ${errorMessage(e)}`, "MatchMoveError");
+    return null;
+  }
+}
+
+/** Concede one of our tournament matches. */
+export async function resignMatch(matchId: MatchId): Promise<GameView | null> {
+  try {
+    return await invoke<GameView>("resign_match", { matchId });
+  } catch (e) {
+    notifyError(`Failed to resign match: ${errorMessage(e)}`, "MatchResignError");
+    return null;
+  }
+}
+
+/** Join the public room lobby, optionally bootstrapping from known node ids. */
+export async function joinLobby(bootstrap: string[] = []): Promise<boolean> {
+  try {
+    await invoke("join_lobby", { bootstrap });
+    return true;
+  } catch (e) {
+    notifyError(`Failed to join lobby: ${errorMessage(e)}`, "LobbyJoinError");
+    return false;
+  }
+}
+
+/** Leave the public room lobby. */
+export async function leaveLobby(): Promise<void> {
+  try {
+    await invoke("leave_lobby");
+  } catch (e) {
+    notifyError(`Failed to leave lobby: ${errorMessage(e)}`, "LobbyLeaveError");
+  }
+}
+
+/** Return the rooms currently advertised in the lobby. */
+export async function getLobbyRooms(): Promise<RoomAdvert[]> {
+  try {
+    return await invoke<RoomAdvert[]>("get_lobby_rooms");
+  } catch (e) {
+    notifyError(`Failed to get lobby rooms: ${errorMessage(e)}`, "LobbyRoomsGetError");
+    return [];
+  }
+}
+
+/** Advertise the current room in the lobby under its name and rules. */
+export async function advertiseRoom(): Promise<void> {
+  try {
+    await invoke("advertise_room");
+  } catch (e) {
+    notifyError(`Failed to advertise room: ${errorMessage(e)}`, "RoomAdvertiseError");
+  }
+}
+
+/** Stop advertising the current room in the lobby. */
+export async function stopAdvertising(): Promise<void> {
+  try {
+    await invoke("stop_advertising");
+  } catch (e) {
+    notifyError(`Failed to stop advertising: ${errorMessage(e)}`, "RoomAdvertiseStopError");
+  }
+}
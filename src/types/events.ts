@@ -1,28 +1,51 @@
-import { ChatMessage, PeerInfo } from "types";
+import {
+  AdmissionView,
+  ChatMessage,
+  DrawOffer,
+  DrawResponse,
+  GameResult,
+  GameView,
+  JoinRequest,
+  MatchId,
+  ModerationView,
+  Notification,
+  PeerConnection,
+  PeerInfo,
+  RoomAdvert,
+  RoomMeta,
+  SyncProgress,
+  TakebackRequest,
+  TakebackResponse,
+  TournamentView,
+  UnreadCount,
+  VoiceView,
+} from "types";
 
 interface BaseEvent {
   type:
-    | "neighborUp"
-    | "neighborDown"
     | "errored"
     | "disconnected"
-    | "newMessage"
-    | "syncFinished"
-    | "peerUpdate"
-    | "contentReady"
-    | "pendingContentReady";
-}
-
-/** We have a new, direct neighbor in the swarm membership layer for this topic. */
-export interface NeighborUpEvent extends BaseEvent {
-  type: "neighborUp";
-  nodeId: string;
-}
-
-/** We dropped direct neighbor in the swarm membership layer for this topic. */
-export interface NeighborDownEvent extends BaseEvent {
-  type: "neighborDown";
-  nodeId: string;
+    | "delta"
+    | "takebackRequested"
+    | "takebackAnswered"
+    | "drawOffered"
+    | "drawAnswered"
+    | "gameFinished"
+    | "tournamentUpdate"
+    | "matchUpdate"
+    | "lobbyUpdate"
+    | "joinRequested"
+    | "admissionUpdate"
+    | "moderationUpdate"
+    | "roomUpdated"
+    | "attachmentReady"
+    | "avatarReady"
+    | "connectionUpdate"
+    | "voiceUpdate"
+    | "unreadUpdate"
+    | "notification"
+    | "syncProgress"
+    | "syncSummary";
 }
 
 /** Backend reporting an end of stream event.  Not part of the Gossip Events protocol. */
@@ -36,50 +59,175 @@ export interface ErrorEvent extends BaseEvent {
   message: string;
 }
 
-/** Backend reporting a new message. */
-export interface MessageEvent extends BaseEvent {
-  type: "newMessage";
-  message: ChatMessage;
+/** Changes to the room merged from a burst of backend events. */
+export interface Delta {
+  /** Number of this delta, counting from one in each room */
+  seq: number;
+  /** New or edited messages, in the order they arrived */
+  messages: ChatMessage[];
+  /** Latest info of every peer which changed */
+  peers: PeerInfo[];
+  /** Direct neighbors in the swarm which connected and are still connected */
+  neighborsUp: string[];
+  /** Direct neighbors in the swarm which dropped and are still gone */
+  neighborsDown: string[];
+  /** The room's game as seen by this node, if it changed */
+  game: GameView | null;
+  /** Whether a set-reconciliation sync with a peer finished */
+  syncFinished: boolean;
+}
+
+/** Backend reporting what changed in the room since its last delta. */
+export interface DeltaEvent extends BaseEvent {
+  type: "delta";
+  delta: Delta;
+}
+
+/** Backend reporting a player asked to undo their last move. */
+export interface TakebackRequestedEvent extends BaseEvent {
+  type: "takebackRequested";
+  request: TakebackRequest;
+}
+
+/** Backend reporting the opponent accepted or declined a takeback. */
+export interface TakebackAnsweredEvent extends BaseEvent {
+  type: "takebackAnswered";
+  response: TakebackResponse;
+}
+
+/** Backend reporting a player offered a draw. */
+export interface DrawOfferedEvent extends BaseEvent {
+  type: "drawOffered";
+  offer: DrawOffer;
+}
+
+/** Backend reporting the opponent accepted or declined a draw offer. */
+export interface DrawAnsweredEvent extends BaseEvent {
+  type: "drawAnswered";
+  response: DrawResponse;
+}
+
+/** Backend reporting the game has ended, and how. */
+export interface GameFinishedEvent extends BaseEvent {
+  type: "gameFinished";
+  result: GameResult;
+}
+
+/** Backend reporting the tournament bracket or standings have changed. */
+export interface TournamentUpdateEvent extends BaseEvent {
+  type: "tournamentUpdate";
+  tournament: TournamentView;
+}
+
+/** Backend reporting the game for a tournament match has changed. */
+export interface MatchUpdateEvent extends BaseEvent {
+  type: "matchUpdate";
+  matchId: MatchId;
+  game: GameView;
+}
+
+/** Backend reporting the rooms listed in the lobby have changed. */
+export interface LobbyUpdateEvent extends BaseEvent {
+  type: "lobbyUpdate";
+  rooms: RoomAdvert[];
+}
+
+/** Backend reporting a node has asked to join the room. */
+export interface JoinRequestedEvent extends BaseEvent {
+  type: "joinRequested";
+  request: JoinRequest;
+}
+
+/** Backend reporting the room's admission settings or decisions have changed. */
+export interface AdmissionUpdateEvent extends BaseEvent {
+  type: "admissionUpdate";
+  admission: AdmissionView;
+}
+
+/** Backend reporting a moderator was appointed or a node sanctioned in the room. */
+export interface ModerationUpdateEvent extends BaseEvent {
+  type: "moderationUpdate";
+  moderation: ModerationView;
+}
+
+/** Backend reporting the host has renamed or described the room. */
+export interface RoomUpdatedEvent extends BaseEvent {
+  type: "roomUpdated";
+  room: RoomMeta;
+}
+
+/** Backend reporting an attachment's content is stored locally and can be fetched. */
+export interface AttachmentReadyEvent extends BaseEvent {
+  type: "attachmentReady";
+  hash: string;
+}
+
+/** Backend reporting a peer's avatar image has been downloaded. */
+export interface AvatarReadyEvent extends BaseEvent {
+  type: "avatarReady";
+  nodeId: string;
+  hash: string;
+}
+
+/** Backend reporting the periodic connection quality to each peer in the room. */
+export interface ConnectionUpdateEvent extends BaseEvent {
+  type: "connectionUpdate";
+  peers: PeerConnection[];
+}
+
+/** Backend reporting a change to our voice chat: a call started or ended, or a setting changed. */
+export interface VoiceUpdateEvent extends BaseEvent {
+  type: "voiceUpdate";
+  voice: VoiceView;
 }
 
-/** Backend reporting it has finished synching the shared activity document.
- *
- * "A set-reconciliation sync finished."
- */
-export interface SyncFinishedEvent extends BaseEvent {
-  type: "syncFinished";
+/** Backend reporting a new unread count for a room, after messages from others arrived in it. */
+export interface UnreadUpdateEvent extends BaseEvent {
+  type: "unreadUpdate";
+  room: string;
+  unread: UnreadCount;
 }
 
-/** Backend reporting a peer has changed. */
-export interface PeerUpdateEvent extends BaseEvent {
-  type: "peerUpdate";
-  info: PeerInfo;
+/** Backend reporting something to alert the player about: a mention or their turn. */
+export interface NotificationEvent extends BaseEvent {
+  type: "notification";
+  notification: Notification;
 }
 
-/** Backend reporting the content of an entry was downloaded and is now available at the local node */
-export interface ContentReadyEvent extends BaseEvent {
-  type: "contentReady";
+/** Backend reporting how much of the room's content has been fetched from each peer. */
+export interface SyncProgressEvent extends BaseEvent {
+  type: "syncProgress";
+  progress: SyncProgress;
 }
 
-/** Backend reporting All pending content is now ready.
- *
- * This event signals that all queued content downloads from the last sync run have either completed or failed.
- * It will only be emitted after a `Self::SyncFinished` event, never before.
- * Receiving this event does not guarantee that all content in the document is available.
- * If blobs failed to download, this event will still be emitted after all operations completed.
- */
-export interface PendingContentReadyEvent extends BaseEvent {
-  type: "pendingContentReady";
+/** Backend reporting every blob missing from the room was fetched, and what it took. */
+export interface SyncSummaryEvent extends BaseEvent {
+  type: "syncSummary";
+  summary: SyncProgress;
 }
 
 /** Gossip Events */
 export type ChatEvent =
-  | NeighborUpEvent
-  | NeighborDownEvent
   | DisconnectedEvent
   | ErrorEvent
-  | MessageEvent
-  | SyncFinishedEvent
-  | PeerUpdateEvent
-  | ContentReadyEvent
-  | PendingContentReadyEvent;
+  | DeltaEvent
+  | TakebackRequestedEvent
+  | TakebackAnsweredEvent
+  | DrawOfferedEvent
+  | DrawAnsweredEvent
+  | GameFinishedEvent
+  | TournamentUpdateEvent
+  | MatchUpdateEvent
+  | LobbyUpdateEvent
+  | JoinRequestedEvent
+  | AdmissionUpdateEvent
+  | ModerationUpdateEvent
+  | RoomUpdatedEvent
+  | AttachmentReadyEvent
+  | AvatarReadyEvent
+  | ConnectionUpdateEvent
+  | VoiceUpdateEvent
+  | UnreadUpdateEvent
+  | NotificationEvent
+  | SyncProgressEvent
+  | SyncSummaryEvent;
@@ -3,6 +3,61 @@ export interface ChatMessage {
   content: string;
   nickname: string;
   timestamp: number;
+  attachments: Attachment[];
+}
+
+/** A file attached to a chat message, downloaded when first fetched. */
+export interface Attachment {
+  hash: string;
+  name: string;
+  /** MIME type given by the sender, such as `image/png` */
+  mime: string;
+  /** bytes */
+  size: number;
+}
+
+/** What to look for in the chat history, every field optional. */
+export interface SearchQuery {
+  /** Words which must all appear in a message, each matching the start of a word */
+  text?: string;
+  sender?: string;
+  /** Part of the nickname the message was sent under, ignoring case */
+  nickname?: string;
+  /** Earliest message timestamp, inclusive */
+  since?: number;
+  /** Latest message timestamp, inclusive */
+  until?: number;
+  /** Search every stored room rather than only the current one */
+  allRooms?: boolean;
+  /** Messages to return either side of each match */
+  context?: number;
+  limit?: number;
+}
+
+/** A message matching a search, with the messages around it. */
+export interface SearchHit {
+  room: string;
+  message: ChatMessage;
+  before: ChatMessage[];
+  after: ChatMessage[];
+}
+
+/** Messages in a room which arrived while the app was in the background. */
+export interface UnreadCount {
+  messages: number;
+  /** Of those messages, how many mention us */
+  mentions: number;
+}
+
+export type NotificationKind = "Mention" | "YourTurn";
+
+/** Something worth interrupting the player for. */
+export interface Notification {
+  kind: NotificationKind;
+  /** ID of the room it happened in */
+  room: string;
+  title: string;
+  body: string;
 }
 
 export interface PeerInfo {
@@ -10,6 +65,333 @@ export interface PeerInfo {
   nickname: string;
   status: PeerStatus;
   ready: boolean;
+  profile: Profile;
+}
+
+/** An avatar image, fetched with `getAvatar`. */
+export interface Avatar {
+  hash: string;
+  /** MIME type of the image, such as `image/png` */
+  mime: string;
+  /** bytes */
+  size: number;
+}
+
+/** What other players see about someone besides their nickname. */
+export interface Profile {
+  bio: string;
+  /** The side the player prefers to play, "Empty" for no preference */
+  favouriteSide: Piece;
+  /** Self-reported rating */
+  rating: number | null;
+  avatar: Avatar | null;
+}
+
+export type PeerStatus = "Online" | "Offline" | "Unknown" | "Pending";
+
+/** How packets currently reach a peer. */
+export type ConnectionKind = "Direct" | "Relay" | "Mixed" | "None";
+
+/** A room peer along with the state of our connection to it. */
+export interface PeerConnection extends PeerInfo {
+  connection: ConnectionKind;
+  /** Round trip time of the current path in milliseconds */
+  latencyMs: number | null;
+  /** Milliseconds since anything was last sent to or received from the peer */
+  lastSeenMs: number | null;
+  relayUrl: string | null;
+  /** Whether the connection looks too unreliable for a timed game */
+  poor: boolean;
+}
+
+export type Piece = "Empty" | "Naught" | "Cross";
+
+/** When our microphone is sent to the room, only while the talk key is held or all the time. */
+export type VoiceMode = "PushToTalk" | "OpenMic";
+
+/** A peer we are in a voice call with. */
+export interface VoicePeer {
+  nodeId: string;
+  /** Whether we muted them, which they can't tell */
+  muted: boolean;
 }
 
-export type PeerStatus = "Online" | "Offline" | "Unknown";
+/** Voice chat as seen by this node. */
+export interface VoiceView {
+  mode: VoiceMode;
+  /** Whether our microphone is currently sent */
+  transmitting: boolean;
+  peers: VoicePeer[];
+}
+
+export type PlayerType = "Spectator" | "Human" | "Ai";
+
+/** A participant's place in the game, the same for every peer. */
+export interface Seat {
+  nodeId: string;
+  role: PlayerType;
+  /** Piece played from this seat, "Empty" for spectators */
+  piece: Piece;
+}
+
+/** A seat as seen by this node. */
+export interface SeatView extends Seat {
+  isMe: boolean;
+}
+
+export interface Move {
+  x: number;
+  y: number;
+  piece: Piece;
+  /** Node which made this move */
+  by: string;
+}
+
+export type GamePhase =
+  | "New"
+  | { InProgress: { turn: number; board: Piece[] } }
+  | { Finished: { board: Piece[]; result: GameResult } };
+
+/** How a finished game ended. */
+export type GameResult =
+  | { Line: { winner: string; piece: Piece } }
+  | { Resignation: { resigned: string } }
+  | "DrawAgreement"
+  | "BoardFull"
+  | "Aborted"
+  | { Legacy: { winner: string | null } };
+
+/** The game as seen by this node. */
+export interface GameView {
+  state: GamePhase;
+  seats: SeatView[];
+  history: Move[];
+  version: number;
+  mySeat: Seat | null;
+  myTurn: boolean;
+}
+
+export interface TakebackRequest {
+  requester: string;
+  version: number;
+  timestamp: number;
+}
+
+export interface TakebackResponse {
+  responder: string;
+  version: number;
+  accepted: boolean;
+  timestamp: number;
+}
+
+export interface DrawOffer {
+  from: string;
+  version: number;
+  timestamp: number;
+}
+
+export interface DrawResponse {
+  responder: string;
+  version: number;
+  accepted: boolean;
+  timestamp: number;
+}
+
+export type TournamentFormat = "RoundRobin" | "Knockout";
+
+export interface MatchId {
+  round: number;
+  index: number;
+}
+
+export interface Pairing {
+  id: MatchId;
+  /** Plays crosses, and moves first */
+  cross: string;
+  /** Plays naughts, null if cross has a bye this round */
+  naught: string | null;
+}
+
+export interface MatchResult {
+  id: MatchId;
+  /** null for a drawn match */
+  winner: string | null;
+  result: GameResult;
+}
+
+export interface Standing {
+  nodeId: string;
+  played: number;
+  wins: number;
+  draws: number;
+  losses: number;
+  points: number;
+}
+
+export interface TournamentView {
+  format: TournamentFormat;
+  roster: string[];
+  rounds: Pairing[][];
+  results: MatchResult[];
+  standings: Standing[];
+  champion: string | null;
+  complete: boolean;
+}
+
+/** A room offered to the public through the lobby. */
+export interface RoomAdvert {
+  /** Node hosting the room, which signed this advert */
+  host: string;
+  name: string;
+  rules: string;
+  seatsFree: number;
+  /** Ticket for joining the room as a participant */
+  ticket: string;
+  /** Read only ticket for watching the room */
+  spectatorTicket: string;
+  timestamp: number;
+}
+
+/**
+ * How the node finds its peers: through n0's DNS and relay servers, or by
+ * local network discovery alone. Changes apply on the next startup.
+ */
+export type NetworkMode = "Internet" | "LocalNetwork";
+
+/**
+ * Relay servers used when peers can't reach each other directly: n0's public
+ * relays, none at all, or a custom list of relay URLs.
+ */
+export type RelaySettings = "Default" | "Disabled" | { Custom: string[] };
+
+/** What to keep on disk, applied on startup and when joining a room. */
+export interface RetentionPolicy {
+  /** Rooms not visited for this many days are deleted */
+  roomMaxAgeDays: number | null;
+  /** Most chat messages of our own to keep per room */
+  chatHistoryCap: number | null;
+}
+
+/** Space taken by one stored room. */
+export interface RoomUsage {
+  id: string;
+  entries: number;
+  bytes: number;
+  /** microseconds since EPOCH */
+  lastVisited: number | null;
+  /** The room currently joined, which can't be cleared */
+  active: boolean;
+}
+
+/** Space taken by the node's data. */
+export interface StorageReport {
+  rooms: RoomUsage[];
+  totalBytes: number;
+}
+
+/** How the host admits new nodes into the room. */
+export interface AdmissionSettings {
+  host: string;
+  /** Whether joiners wait for the host's approval before their entries count */
+  approvalRequired: boolean;
+  /** Whether joiners proving the room password are approved automatically */
+  hasPassword: boolean;
+}
+
+/** A node asking the host to be let into the room. */
+export interface JoinRequest {
+  nodeId: string;
+  nickname: string;
+  timestamp: number;
+}
+
+export type AdmissionStatus =
+  | "Open"
+  | "Host"
+  | "Approved"
+  | "Pending"
+  | "Rejected"
+  | "Banned";
+
+/** Admission as seen by this node. */
+export interface AdmissionView {
+  settings: AdmissionSettings | null;
+  myStatus: AdmissionStatus;
+  /** Requests from nodes the host has not decided on yet */
+  pending: JoinRequest[];
+}
+
+/** What a room is called and how it plays, set by its host. */
+export interface RoomMeta {
+  name: string;
+  description: string;
+  /** microseconds since EPOCH */
+  createdAt: number;
+  host: string;
+  variant: string;
+  /** House rules, shown to joiners and in the lobby */
+  rules: string;
+}
+
+/** Entries a peer sent us and how much of their content we have. */
+export interface PeerSyncProgress {
+  nodeId: string;
+  entriesReceived: number;
+  /** Entries whose content we have, whether it came with them or later */
+  contentFetched: number;
+  bytesFetched: number;
+  /** Blobs we are still waiting for */
+  blobsOutstanding: number;
+  bytesOutstanding: number;
+}
+
+/** Progress of fetching the room's content from every peer which sent us entries. */
+export interface SyncProgress {
+  peers: PeerSyncProgress[];
+  blobsOutstanding: number;
+  bytesOutstanding: number;
+}
+
+/** Everything shown of a room, to start from before applying delta events. */
+export interface RoomSnapshot {
+  /** Number of the last delta included, deltas up to it can be dropped */
+  cursor: number;
+  peers: PeerInfo[];
+  messages: ChatMessage[];
+  game: GameView | null;
+  room: RoomMeta | null;
+}
+
+/** What a node may do in the room. */
+export type Role = "Host" | "Moderator" | "Member";
+
+/** Roles and sanctions in the room as seen by this node. */
+export interface ModerationView {
+  myRole: Role;
+  moderators: string[];
+  banned: string[];
+  muted: string[];
+}
+
+/** Stable code of an error reported by the backend. */
+export type ErrorCode =
+  | "NoActiveChannel"
+  | "NotInLobby"
+  | "InvalidTicket"
+  | "IllegalMove"
+  | "NotYourTurn"
+  | "NotPermitted"
+  | "NotFound"
+  | "InvalidInput"
+  | "WrongPassphrase"
+  | "Locked"
+  | "NetworkUnavailable"
+  | "UpdateRequired"
+  | "Corrupt"
+  | "Internal";
+
+/** An error as rejected by an IPC command. */
+export interface AppError {
+  code: ErrorCode;
+  message: string;
+}